@@ -0,0 +1,1085 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::db::{load_price_history, HistoryEntry};
+use crate::expr::{self, Expr};
+use crate::notify;
+use crate::timestamp;
+
+/// アラートルールの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    /// 価格が threshold 円を下回った
+    BelowPrice,
+    /// 前回のチェックから threshold % 以上値下がりした
+    DropFromLast,
+    /// 30日間の平均価格より threshold % 以上安い
+    DropFromAvg30d,
+    /// 過去最安値を更新した
+    AllTimeLow,
+    /// 在庫切れから在庫ありに戻った（threshold を指定すると threshold 円未満のときだけ）
+    BackInStock,
+    /// 条件式を満たした（例: `price < 2500 and price < min_30d * 0.95`）
+    Expression,
+}
+
+impl RuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleKind::BelowPrice => "below_price",
+            RuleKind::DropFromLast => "drop_from_last",
+            RuleKind::DropFromAvg30d => "drop_from_avg30d",
+            RuleKind::AllTimeLow => "all_time_low",
+            RuleKind::BackInStock => "back_in_stock",
+            RuleKind::Expression => "expression",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "below_price" => RuleKind::BelowPrice,
+            "drop_from_last" => RuleKind::DropFromLast,
+            "drop_from_avg30d" => RuleKind::DropFromAvg30d,
+            "all_time_low" => RuleKind::AllTimeLow,
+            "back_in_stock" => RuleKind::BackInStock,
+            "expression" => RuleKind::Expression,
+            _ => bail!("Unknown alert rule kind: {}", s),
+        })
+    }
+
+    /// ルールの条件を読みやすく表示
+    pub fn describe(&self, threshold: Option<f64>, expression: Option<&str>) -> String {
+        let t = threshold.unwrap_or_default();
+        match self {
+            RuleKind::BelowPrice => format!("¥{} を下回ったら", t),
+            RuleKind::DropFromLast => format!("前回から {}% 以上値下がりしたら", t),
+            RuleKind::DropFromAvg30d => format!("30日平均より {}% 以上安くなったら", t),
+            RuleKind::AllTimeLow => "過去最安値を更新したら".to_string(),
+            RuleKind::BackInStock => match threshold {
+                Some(t) => format!("在庫が戻って ¥{} 未満なら", t),
+                None => "在庫が戻ったら".to_string(),
+            },
+            RuleKind::Expression => format!("{} を満たしたら", expression.unwrap_or_default()),
+        }
+    }
+}
+
+impl rusqlite::types::FromSql for RuleKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        RuleKind::parse(value.as_str()?).map_err(|e| rusqlite::types::FromSqlError::Other(e.into()))
+    }
+}
+
+/// 条件式の長さの上限（文字数）
+pub const MAX_EXPRESSION_LEN: usize = 1000;
+
+/// ルールの種類としきい値・条件式の組み合わせを検証
+pub fn validate(kind: RuleKind, threshold: Option<f64>, expression: Option<&str>) -> Result<()> {
+    match (kind, expression) {
+        (RuleKind::Expression, None) => bail!("expression requires an expression"),
+        (RuleKind::Expression, Some(src)) => {
+            if threshold.is_some() {
+                bail!("expression does not take a threshold");
+            }
+            if src.chars().count() > MAX_EXPRESSION_LEN {
+                bail!("expression is too long (max {} characters)", MAX_EXPRESSION_LEN);
+            }
+            Expr::parse(src)?;
+            return Ok(());
+        }
+        (_, Some(_)) => bail!("{} does not take an expression", kind.as_str()),
+        (_, None) => {}
+    }
+
+    match (kind, threshold) {
+        (RuleKind::AllTimeLow, None) => Ok(()),
+        (RuleKind::AllTimeLow, Some(_)) => bail!("all_time_low does not take a threshold"),
+        (RuleKind::BackInStock, None) => Ok(()),
+        (_, None) => bail!("{} requires a threshold", kind.as_str()),
+        (RuleKind::BelowPrice | RuleKind::BackInStock, Some(t)) if t <= 0.0 => {
+            bail!("Price threshold must be positive")
+        }
+        (RuleKind::DropFromLast | RuleKind::DropFromAvg30d, Some(t)) if !(t > 0.0 && t < 100.0) => {
+            bail!("Percentage threshold must be between 0 and 100")
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Serialize)]
+pub struct AlertRule {
+    pub id: i64,
+    pub product_id: i64,
+    pub product_name: String,
+    pub kind: RuleKind,
+    pub threshold: Option<f64>,
+    /// 条件式（kind が expression のとき）
+    pub expression: Option<String>,
+    pub enabled: bool,
+    /// 同じルールが再び発火するまでの最短間隔（分）
+    pub cooldown_minutes: Option<i64>,
+    /// 発火したら、条件を満たさなくなるまで再び発火しない
+    pub rearm: bool,
+    /// 次に条件を満たしたとき発火できる状態か
+    pub armed: bool,
+    /// 通知先ID（空なら全ての通知先に送る）
+    pub channels: Vec<i64>,
+    pub created_at: String,
+}
+
+/// ルールの発火に関する設定と状態
+#[derive(Debug, Clone, Copy)]
+pub struct RuleState {
+    pub cooldown: Option<Duration>,
+    pub rearm: bool,
+    pub armed: bool,
+    pub last_triggered_at: Option<DateTime<Utc>>,
+}
+
+/// 条件を評価した結果、発火するかどうかと次の状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub fire: bool,
+    pub armed: bool,
+}
+
+impl RuleState {
+    /// 条件の評価結果から、発火するかどうかを決める
+    ///
+    /// - 条件を満たさなくなったら再アームする（例: 価格がしきい値より上に戻った）
+    /// - 再アームが有効なら、発火した後は再アームされるまで発火しない
+    /// - クールダウン中は、条件を満たしていても発火しない（アームの状態はそのまま）
+    pub fn next(&self, condition_met: bool, now: DateTime<Utc>) -> Transition {
+        if !condition_met {
+            return Transition { fire: false, armed: true };
+        }
+
+        if self.rearm && !self.armed {
+            return Transition { fire: false, armed: false };
+        }
+
+        let cooling_down = match (self.cooldown, self.last_triggered_at) {
+            (Some(cooldown), Some(last)) => now < last + cooldown,
+            _ => false,
+        };
+        if cooling_down {
+            return Transition { fire: false, armed: self.armed };
+        }
+
+        Transition { fire: true, armed: !self.rearm }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AlertEvent {
+    pub id: i64,
+    pub rule_id: i64,
+    pub product_id: i64,
+    pub product_name: String,
+    pub old_price: Option<i32>,
+    pub new_price: i32,
+    pub message: String,
+    pub triggered_at: String,
+}
+
+/// 今回のチェックで発火したアラート
+pub struct TriggeredAlert {
+    pub event_id: i64,
+    pub rule_id: i64,
+    pub kind: RuleKind,
+    pub threshold: Option<f64>,
+    pub expression: Option<String>,
+    pub product_id: i64,
+    pub product_name: String,
+    pub url: String,
+    pub image_url: Option<String>,
+    pub old_price: Option<i32>,
+    pub new_price: i32,
+    pub message: String,
+    pub triggered_at: String,
+    /// 送り先の通知先ID（空なら全ての通知先）
+    pub channel_ids: Vec<i64>,
+    /// 静かな時間帯に発火したので、通知は後で送る
+    pub queued: bool,
+}
+
+/// アラート評価に使う、今回の価格を保存する前の履歴の統計
+pub struct HistoryStats {
+    /// 前回の価格
+    pub last_price: Option<i32>,
+    /// 過去最安値（日次に集約済みのデータも含む）
+    pub all_time_low: Option<i32>,
+    /// 30日間の平均価格（価格が続いた時間で重み付け）
+    pub avg_30d: Option<f64>,
+    /// 30日間の最安値
+    pub min_30d: Option<i32>,
+    /// 30日間の最高値
+    pub max_30d: Option<i32>,
+    /// 前回のチェックで在庫があったか
+    pub was_in_stock: bool,
+}
+
+impl HistoryStats {
+    /// 価格を保存する前に呼ぶこと（今回の価格を含めないため）
+    pub fn load(conn: &Connection, product_id: i64) -> Result<Self> {
+        let history = load_price_history(conn, product_id, None, None)?;
+
+        let was_in_stock = conn.query_row(
+            "SELECT in_stock FROM products WHERE id = ?1",
+            [product_id],
+            |row| row.get(0),
+        )?;
+
+        Self::from_history(&history, was_in_stock, Utc::now())
+    }
+
+    /// `now` より前の価格履歴（時系列順）から統計を計算
+    fn from_history(history: &[HistoryEntry], was_in_stock: bool, now: DateTime<Utc>) -> Result<Self> {
+        let start = now - Duration::days(30);
+        let since = timestamp::to_db(start);
+        // 30日以内に確認された記録（期間の始まりより前から続いていた価格も含む）
+        let recent: Vec<&HistoryEntry> = history.iter().filter(|h| h.last_seen_at >= since).collect();
+
+        Ok(Self {
+            last_price: history.last().map(|h| h.price),
+            all_time_low: history.iter().map(|h| h.min_price.unwrap_or(h.price)).min(),
+            avg_30d: weighted_average(&recent, start, now)?,
+            min_30d: recent.iter().map(|h| h.min_price.unwrap_or(h.price)).min(),
+            max_30d: recent.iter().map(|h| h.max_price.unwrap_or(h.price)).max(),
+            was_in_stock,
+        })
+    }
+}
+
+/// `start` から `now` までの平均価格（各価格が続いた時間で重み付け）
+fn weighted_average(history: &[&HistoryEntry], start: DateTime<Utc>, now: DateTime<Utc>) -> Result<Option<f64>> {
+    if history.is_empty() {
+        return Ok(None);
+    }
+
+    let mut weighted = 0.0;
+    let mut total = 0.0;
+    for (i, h) in history.iter().enumerate() {
+        let from = timestamp::parse(&h.checked_at)?.max(start);
+        let to = match history.get(i + 1) {
+            Some(next) => timestamp::parse(&next.checked_at)?,
+            None => now,
+        };
+        let secs = (to - from).num_seconds().max(0) as f64;
+        weighted += h.price as f64 * secs;
+        total += secs;
+    }
+
+    if total == 0.0 {
+        let sum: f64 = history.iter().map(|h| h.price as f64).sum();
+        return Ok(Some(sum / history.len() as f64));
+    }
+
+    Ok(Some(weighted / total))
+}
+
+/// 今回のチェックで取得した商品の状態
+pub struct Observation<'a> {
+    pub price: i32,
+    pub name: &'a str,
+    pub seller: Option<&'a str>,
+    /// 商品に設定した目標価格
+    pub target: Option<i32>,
+}
+
+/// ルールの条件を満たしていればメッセージを返す
+fn check_rule(
+    kind: RuleKind,
+    threshold: Option<f64>,
+    expression: Option<&str>,
+    stats: &HistoryStats,
+    observation: &Observation,
+) -> Result<Option<String>> {
+    let t = threshold.unwrap_or_default();
+    let price = observation.price;
+    let p = price as f64;
+
+    Ok(match kind {
+        RuleKind::BelowPrice => (p < t).then(|| format!("¥{} が目標価格 ¥{} を下回りました", price, t)),
+        RuleKind::DropFromLast => stats.last_price.and_then(|last| {
+            let last = last as f64;
+            let drop = (last - p) / last * 100.0;
+            (drop >= t).then(|| format!("前回の ¥{} から {:.1}% 値下がりしました（¥{}）", last, drop, price))
+        }),
+        RuleKind::DropFromAvg30d => stats.avg_30d.and_then(|avg| {
+            let drop = (avg - p) / avg * 100.0;
+            (drop >= t).then(|| format!("30日平均 ¥{:.0} より {:.1}% 安くなりました（¥{}）", avg, drop, price))
+        }),
+        RuleKind::AllTimeLow => stats.all_time_low.and_then(|low| {
+            (price < low).then(|| format!("過去最安値を更新しました: ¥{}（これまでの最安値 ¥{}）", price, low))
+        }),
+        RuleKind::BackInStock => {
+            let under_threshold = threshold.is_none_or(|t| p < t);
+            (!stats.was_in_stock && under_threshold).then(|| format!("在庫が復活しました（¥{}）", price))
+        }
+        RuleKind::Expression => {
+            let src = expression.unwrap_or_default();
+            let context = expr::Context {
+                price,
+                last_price: stats.last_price,
+                avg_30d: stats.avg_30d,
+                min_30d: stats.min_30d,
+                max_30d: stats.max_30d,
+                all_time_low: stats.all_time_low,
+                was_in_stock: stats.was_in_stock,
+                seller: observation.seller,
+                name: observation.name,
+                target: observation.target,
+            };
+            Expr::parse(src)?
+                .matches(&context)
+                .then(|| format!("条件 {} を満たしました（¥{}）", src, price))
+        }
+    })
+}
+
+/// 評価する有効なルール
+struct ActiveRule {
+    id: i64,
+    kind: RuleKind,
+    threshold: Option<f64>,
+    expression: Option<String>,
+    state: RuleState,
+}
+
+/// 保存した価格に対して商品のアラートルールを評価し、発火したものを履歴に記録
+///
+/// 在庫があるときだけ呼ぶこと（在庫切れの間は買えないので、価格のルールも通知しない）。
+pub fn evaluate(
+    conn: &Connection,
+    product_id: i64,
+    stats: &HistoryStats,
+    price: i32,
+) -> Result<Vec<TriggeredAlert>> {
+    let (product_name, url, image_url, seller, target): (String, String, Option<String>, Option<String>, Option<i32>) =
+        conn.query_row(
+            "SELECT COALESCE(display_name, name), url, image_url, seller, target_price FROM products WHERE id = ?1",
+            [product_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        )?;
+    let observation = Observation {
+        price,
+        name: &product_name,
+        seller: seller.as_deref(),
+        target,
+    };
+
+    let rules: Vec<ActiveRule> = {
+        let mut stmt = conn.prepare(
+            "SELECT r.id, r.kind, r.threshold, r.expression, r.cooldown_minutes, r.rearm, r.armed,
+                    (SELECT MAX(e.triggered_at) FROM alert_events e WHERE e.rule_id = r.id)
+             FROM alert_rules r
+             WHERE r.product_id = ?1 AND r.enabled = 1
+             ORDER BY r.id",
+        )?;
+        stmt.query_map([product_id], |row| {
+            let last_triggered_at: Option<String> = row.get(7)?;
+            Ok(ActiveRule {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                threshold: row.get(2)?,
+                expression: row.get(3)?,
+                state: RuleState {
+                    cooldown: row.get::<_, Option<i64>>(4)?.map(Duration::minutes),
+                    rearm: row.get(5)?,
+                    armed: row.get(6)?,
+                    last_triggered_at: last_triggered_at.and_then(|s| timestamp::parse(&s).ok()),
+                },
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?
+    };
+
+    let now = Utc::now();
+    // 静かな時間帯は記録だけして、通知は後で送る
+    let queued = notify::is_quiet_at(now);
+    let mut triggered = Vec::new();
+
+    for ActiveRule { id: rule_id, kind, threshold, expression, state } in rules {
+        // 壊れたルールが1件あっても、同じ商品のほかのルールは評価する
+        let message = match check_rule(kind, threshold, expression.as_deref(), stats, &observation) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("⚠️  アラートルール {} を評価できないためスキップします: {}", rule_id, e);
+                continue;
+            }
+        };
+        let transition = state.next(message.is_some(), now);
+
+        if transition.armed != state.armed {
+            conn.execute(
+                "UPDATE alert_rules SET armed = ?1 WHERE id = ?2",
+                (transition.armed, rule_id),
+            )?;
+        }
+
+        let Some(message) = message.filter(|_| transition.fire) else {
+            continue;
+        };
+
+        let triggered_at = timestamp::to_db(now);
+        conn.execute(
+            "INSERT INTO alert_events
+                 (rule_id, product_id, old_price, new_price, message, triggered_at, queued)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![rule_id, product_id, stats.last_price, price, &message, &triggered_at, queued],
+        )?;
+
+        triggered.push(TriggeredAlert {
+            event_id: conn.last_insert_rowid(),
+            rule_id,
+            kind,
+            threshold,
+            expression,
+            product_id,
+            product_name: product_name.clone(),
+            url: url.clone(),
+            image_url: image_url.clone(),
+            old_price: stats.last_price,
+            new_price: price,
+            message,
+            triggered_at,
+            channel_ids: rule_channels(conn, rule_id)?,
+            queued,
+        });
+    }
+
+    Ok(triggered)
+}
+
+/// 静かな時間帯に溜まったアラート
+///
+/// 静かな時間帯の間は何も返さない。ここではまだキューから外さず、送った後に `clear_queued` で外す。
+pub fn queued_alerts(conn: &Connection) -> Result<Vec<TriggeredAlert>> {
+    if notify::is_quiet_at(Utc::now()) {
+        return Ok(Vec::new());
+    }
+
+    let mut alerts: Vec<TriggeredAlert> = {
+        let mut stmt = conn.prepare(
+            "SELECT e.id, e.rule_id, r.kind, r.threshold, r.expression, e.product_id,
+                    COALESCE(p.display_name, p.name), p.url, p.image_url, e.old_price, e.new_price, e.message, e.triggered_at
+             FROM alert_events e
+             JOIN alert_rules r ON r.id = e.rule_id
+             JOIN products p ON p.id = e.product_id
+             WHERE e.queued = 1
+             ORDER BY e.triggered_at, e.id",
+        )?;
+        stmt.query_map([], |row| {
+            Ok(TriggeredAlert {
+                event_id: row.get(0)?,
+                rule_id: row.get(1)?,
+                kind: row.get(2)?,
+                threshold: row.get(3)?,
+                expression: row.get(4)?,
+                product_id: row.get(5)?,
+                product_name: row.get(6)?,
+                url: row.get(7)?,
+                image_url: row.get(8)?,
+                old_price: row.get(9)?,
+                new_price: row.get(10)?,
+                message: row.get(11)?,
+                triggered_at: row.get(12)?,
+                channel_ids: Vec::new(),
+                queued: false,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?
+    };
+
+    for alert in &mut alerts {
+        alert.channel_ids = rule_channels(conn, alert.rule_id)?;
+    }
+
+    Ok(alerts)
+}
+
+/// 送ったアラートをキューから外す
+///
+/// どの通知先にも届かなかったアラートは、次のチェックで送り直すためにキューに残す。
+/// 送る先の通知先がなかったアラートは、残しても送れないので外す。戻り値はキューに残した件数。
+pub fn clear_queued(conn: &Connection, alerts: &[TriggeredAlert], deliveries: &[notify::Delivery]) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let mut kept = 0;
+    for alert in alerts {
+        let mut attempts = deliveries.iter().filter(|d| d.alert_event_id == alert.event_id).peekable();
+        if attempts.peek().is_some() && !attempts.any(|d| d.delivered()) {
+            kept += 1;
+            continue;
+        }
+        tx.execute("UPDATE alert_events SET queued = 0 WHERE id = ?1", [alert.event_id])?;
+    }
+    tx.commit()?;
+
+    Ok(kept)
+}
+
+/// ルールの発火に関するオプション
+#[derive(Debug, Clone, Copy)]
+pub struct RuleOptions {
+    pub cooldown_minutes: Option<i64>,
+    pub rearm: bool,
+}
+
+impl Default for RuleOptions {
+    fn default() -> Self {
+        Self {
+            cooldown_minutes: None,
+            rearm: true,
+        }
+    }
+}
+
+pub fn add_rule(
+    conn: &Connection,
+    product_id: i64,
+    kind: RuleKind,
+    threshold: Option<f64>,
+    expression: Option<&str>,
+    options: RuleOptions,
+    channels: &[i64],
+) -> Result<i64> {
+    validate(kind, threshold, expression)?;
+    if options.cooldown_minutes.is_some_and(|m| m <= 0) {
+        bail!("Cooldown must be a positive number of minutes");
+    }
+    ensure_channels_exist(conn, channels)?;
+
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM products WHERE id = ?1)",
+        [product_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        bail!("Product not found: {}", product_id);
+    }
+
+    conn.execute(
+        "INSERT INTO alert_rules (product_id, kind, threshold, expression, cooldown_minutes, rearm, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        (
+            product_id,
+            kind.as_str(),
+            threshold,
+            expression.map(str::trim),
+            options.cooldown_minutes,
+            options.rearm,
+            timestamp::now(),
+        ),
+    )?;
+    let rule_id = conn.last_insert_rowid();
+
+    if !channels.is_empty() {
+        set_rule_channels(conn, rule_id, channels)?;
+    }
+
+    Ok(rule_id)
+}
+
+/// ルールの通知先を設定（空にすると全ての通知先に戻る）
+///
+/// 対象のルールが存在しない場合は `false` を返す。
+pub fn set_rule_channels(conn: &Connection, rule_id: i64, channels: &[i64]) -> Result<bool> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM alert_rules WHERE id = ?1)",
+        [rule_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(false);
+    }
+    ensure_channels_exist(conn, channels)?;
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM alert_rule_channels WHERE rule_id = ?1", [rule_id])?;
+    for &channel_id in channels {
+        tx.execute(
+            "INSERT OR IGNORE INTO alert_rule_channels (rule_id, channel_id) VALUES (?1, ?2)",
+            (rule_id, channel_id),
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(true)
+}
+
+/// 通知先がすべて存在するか確かめる（存在しない通知先があればエラー）
+pub fn ensure_channels_exist(conn: &Connection, channels: &[i64]) -> Result<()> {
+    for &channel_id in channels {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM notify_channels WHERE id = ?1)",
+            [channel_id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            bail!("Channel not found: {}", channel_id);
+        }
+    }
+    Ok(())
+}
+
+/// ルールの通知先ID（空なら全ての通知先）
+fn rule_channels(conn: &Connection, rule_id: i64) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT channel_id FROM alert_rule_channels WHERE rule_id = ?1 ORDER BY channel_id",
+    )?;
+    let channels = stmt
+        .query_map([rule_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(channels)
+}
+
+pub fn remove_rule(conn: &Connection, rule_id: i64) -> Result<bool> {
+    let deleted = conn.execute("DELETE FROM alert_rules WHERE id = ?1", [rule_id])?;
+    Ok(deleted > 0)
+}
+
+pub fn list_rules(conn: &Connection, product_id: Option<i64>) -> Result<Vec<AlertRule>> {
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.product_id, COALESCE(p.display_name, p.name), r.kind, r.threshold, r.expression,
+                r.enabled, r.cooldown_minutes, r.rearm, r.armed, r.created_at
+         FROM alert_rules r
+         JOIN products p ON p.id = r.product_id
+         WHERE ?1 IS NULL OR r.product_id = ?1
+         ORDER BY r.product_id, r.id",
+    )?;
+
+    let mut rules = stmt
+        .query_map([product_id], |row| {
+            Ok(AlertRule {
+                id: row.get(0)?,
+                product_id: row.get(1)?,
+                product_name: row.get(2)?,
+                kind: row.get(3)?,
+                threshold: row.get(4)?,
+                expression: row.get(5)?,
+                enabled: row.get(6)?,
+                cooldown_minutes: row.get(7)?,
+                rearm: row.get(8)?,
+                armed: row.get(9)?,
+                channels: Vec::new(),
+                created_at: row.get(10)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for rule in &mut rules {
+        rule.channels = rule_channels(conn, rule.id)?;
+    }
+
+    Ok(rules)
+}
+
+/// 発火したアラートの履歴（新しい順）
+pub fn list_events(conn: &Connection, product_id: Option<i64>, limit: usize) -> Result<Vec<AlertEvent>> {
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.rule_id, e.product_id, COALESCE(p.display_name, p.name), e.old_price, e.new_price,
+                e.message, e.triggered_at
+         FROM alert_events e
+         JOIN products p ON p.id = e.product_id
+         WHERE ?1 IS NULL OR e.product_id = ?1
+         ORDER BY e.triggered_at DESC, e.id DESC
+         LIMIT ?2",
+    )?;
+
+    let events = stmt
+        .query_map((product_id, limit), |row| {
+            Ok(AlertEvent {
+                id: row.get(0)?,
+                rule_id: row.get(1)?,
+                product_id: row.get(2)?,
+                product_name: row.get(3)?,
+                old_price: row.get(4)?,
+                new_price: row.get(5)?,
+                message: row.get(6)?,
+                triggered_at: row.get(7)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(events)
+}
+
+/// 保存済みの価格履歴にルールを当てはめた結果の1件
+pub struct ReplayPoint {
+    pub checked_at: String,
+    pub price: i32,
+    /// 条件を満たしたときのメッセージ
+    pub message: Option<String>,
+    /// 実際に発火したか（再アーム・クールダウンで抑えられたものは false）
+    pub fired: bool,
+}
+
+/// 保存済みの価格履歴に対してルールを評価する（`rules test` 用）
+///
+/// 各時点の価格を、それより前の履歴の統計と比べる。在庫と出品者は履歴に残っていないため、
+/// 在庫は常にあったものとし、出品者は現在の値を使う。
+pub fn replay(
+    conn: &Connection,
+    product_id: i64,
+    kind: RuleKind,
+    threshold: Option<f64>,
+    expression: Option<&str>,
+    options: RuleOptions,
+) -> Result<Vec<ReplayPoint>> {
+    validate(kind, threshold, expression)?;
+
+    let (name, seller, target): (String, Option<String>, Option<i32>) = conn
+        .query_row(
+            "SELECT COALESCE(display_name, name), seller, target_price FROM products WHERE id = ?1",
+            [product_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| anyhow::anyhow!("Product not found: {}", product_id))?;
+
+    let history = load_price_history(conn, product_id, None, None)?;
+    let mut state = RuleState {
+        cooldown: options.cooldown_minutes.map(Duration::minutes),
+        rearm: options.rearm,
+        armed: true,
+        last_triggered_at: None,
+    };
+
+    let mut points = Vec::with_capacity(history.len());
+    for (i, entry) in history.iter().enumerate() {
+        let now = timestamp::parse(&entry.checked_at)?;
+        let stats = HistoryStats::from_history(&history[..i], true, now)?;
+        let observation = Observation {
+            price: entry.price,
+            name: &name,
+            seller: seller.as_deref(),
+            target,
+        };
+
+        let message = check_rule(kind, threshold, expression, &stats, &observation)?;
+        let transition = state.next(message.is_some(), now);
+        state.armed = transition.armed;
+        if transition.fire {
+            state.last_triggered_at = Some(now);
+        }
+
+        points.push(ReplayPoint {
+            checked_at: entry.checked_at.clone(),
+            price: entry.price,
+            message,
+            fired: transition.fire,
+        });
+    }
+
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::scraper::Product;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        timestamp::parse(s).unwrap()
+    }
+
+    fn state(rearm: bool, armed: bool, cooldown_minutes: Option<i64>, last: Option<&str>) -> RuleState {
+        RuleState {
+            cooldown: cooldown_minutes.map(Duration::minutes),
+            rearm,
+            armed,
+            last_triggered_at: last.map(at),
+        }
+    }
+
+    #[test]
+    fn armed_rule_fires_and_disarms() {
+        let t = state(true, true, None, None).next(true, at("2026-10-19T00:00:00Z"));
+        assert_eq!(t, Transition { fire: true, armed: false });
+    }
+
+    #[test]
+    fn disarmed_rule_stays_silent_while_condition_holds() {
+        let t = state(true, false, None, Some("2026-10-19T00:00:00Z")).next(true, at("2026-10-19T01:00:00Z"));
+        assert_eq!(t, Transition { fire: false, armed: false });
+    }
+
+    #[test]
+    fn rule_rearms_when_condition_clears() {
+        let now = at("2026-10-19T02:00:00Z");
+        let t = state(true, false, None, Some("2026-10-19T00:00:00Z")).next(false, now);
+        assert_eq!(t, Transition { fire: false, armed: true });
+
+        // 再アームされたら、次に条件を満たしたとき発火する
+        let t = state(true, t.armed, None, Some("2026-10-19T00:00:00Z")).next(true, now);
+        assert_eq!(t, Transition { fire: true, armed: false });
+    }
+
+    #[test]
+    fn oscillating_price_fires_once_per_crossing() {
+        // 価格がしきい値の上下を行き来する: 下回る, 下回る, 上回る, 下回る
+        let mut rule = state(true, true, None, None);
+        let mut fired = Vec::new();
+        for (hour, below) in [true, true, false, true].into_iter().enumerate() {
+            let now = at("2026-10-19T00:00:00Z") + Duration::hours(hour as i64);
+            let t = rule.next(below, now);
+            rule.armed = t.armed;
+            if t.fire {
+                rule.last_triggered_at = Some(now);
+            }
+            fired.push(t.fire);
+        }
+        assert_eq!(fired, [true, false, false, true]);
+    }
+
+    #[test]
+    fn cooldown_suppresses_until_window_passes() {
+        let rule = state(false, true, Some(60), Some("2026-10-19T00:00:00Z"));
+
+        let t = rule.next(true, at("2026-10-19T00:59:59Z"));
+        assert_eq!(t, Transition { fire: false, armed: true });
+
+        let t = rule.next(true, at("2026-10-19T01:00:00Z"));
+        assert_eq!(t, Transition { fire: true, armed: true });
+    }
+
+    #[test]
+    fn cooldown_applies_after_rearm() {
+        // 条件を外れて再アームされても、クールダウン中は発火しない
+        let rule = state(true, true, Some(60), Some("2026-10-19T00:00:00Z"));
+        let t = rule.next(true, at("2026-10-19T00:30:00Z"));
+        assert_eq!(t, Transition { fire: false, armed: true });
+    }
+
+    #[test]
+    fn without_rearm_fires_every_time() {
+        let rule = state(false, true, None, Some("2026-10-19T00:00:00Z"));
+        let t = rule.next(true, at("2026-10-19T00:00:01Z"));
+        assert_eq!(t, Transition { fire: true, armed: true });
+    }
+
+    #[test]
+    fn rejects_overlong_expression() {
+        let src = format!("price < 1{}", " or price < 1".repeat(MAX_EXPRESSION_LEN / 10));
+        let error = validate(RuleKind::Expression, None, Some(&src)).unwrap_err();
+        assert!(error.to_string().contains("too long"));
+        assert!(validate(RuleKind::Expression, None, Some("price < 1 or price < 2")).is_ok());
+    }
+
+    #[test]
+    fn broken_stored_rule_does_not_block_other_rules() {
+        let conn = db::open_in_memory().unwrap();
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at)
+             VALUES ('https://example.com/item', 'テスト商品', 2480, '2026-10-19T00:00:00Z')",
+            [],
+        )
+        .unwrap();
+        let product_id = conn.last_insert_rowid();
+        // 検証を通らない条件式が保存されていても（古い版で作ったルールなど）
+        conn.execute(
+            "INSERT INTO alert_rules (product_id, kind, expression, created_at)
+             VALUES (?1, 'expression', 'prise < 2500', '2026-10-19T00:00:00Z')",
+            [product_id],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO alert_rules (product_id, kind, threshold, created_at)
+             VALUES (?1, 'below_price', 2500, '2026-10-19T00:00:00Z')",
+            [product_id],
+        )
+        .unwrap();
+
+        let stats = HistoryStats::load(&conn, product_id).unwrap();
+        let triggered = evaluate(&conn, product_id, &stats, 2480).unwrap();
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].kind, RuleKind::BelowPrice);
+    }
+
+    fn entry(price: i32, checked_at: &str, last_seen_at: &str) -> HistoryEntry {
+        HistoryEntry {
+            price,
+            checked_at: checked_at.to_string(),
+            last_seen_at: last_seen_at.to_string(),
+            min_price: None,
+            max_price: None,
+        }
+    }
+
+    /// 統計に対して、今回の価格でルールの条件を満たすか
+    fn fires(kind: RuleKind, threshold: Option<f64>, stats: &HistoryStats, price: i32) -> bool {
+        let observation = Observation {
+            price,
+            name: "テスト商品",
+            seller: None,
+            target: None,
+        };
+        check_rule(kind, threshold, None, stats, &observation).unwrap().is_some()
+    }
+
+    #[test]
+    fn drop_from_last_fires_at_threshold() {
+        let history = [entry(1000, "2026-10-18T00:00:00Z", "2026-10-19T00:00:00Z")];
+        let stats = HistoryStats::from_history(&history, true, at("2026-10-19T00:00:00Z")).unwrap();
+
+        assert!(fires(RuleKind::DropFromLast, Some(10.0), &stats, 900));
+        assert!(!fires(RuleKind::DropFromLast, Some(10.0), &stats, 901));
+
+        // 前回の価格がなければ発火しない
+        let first = HistoryStats::from_history(&[], true, at("2026-10-19T00:00:00Z")).unwrap();
+        assert!(!fires(RuleKind::DropFromLast, Some(10.0), &first, 1));
+    }
+
+    #[test]
+    fn average_is_weighted_by_how_long_each_price_lasted() {
+        let now = at("2026-10-31T00:00:00Z");
+        let history = [
+            // 30日より前に終わった価格は含めない
+            entry(5000, "2026-09-01T00:00:00Z", "2026-09-10T00:00:00Z"),
+            // 30日の始まり（10/1）より前から続いていた価格は、始まりからの12日分
+            entry(1000, "2026-09-20T00:00:00Z", "2026-10-13T00:00:00Z"),
+            // 残りの18日
+            entry(2000, "2026-10-13T00:00:00Z", "2026-10-30T00:00:00Z"),
+        ];
+        let stats = HistoryStats::from_history(&history, true, now).unwrap();
+
+        assert_eq!(stats.avg_30d, Some(1600.0));
+        assert_eq!((stats.min_30d, stats.max_30d), (Some(1000), Some(2000)));
+        // 30日平均 ¥1600 より 25% 安い = ¥1200
+        assert!(fires(RuleKind::DropFromAvg30d, Some(25.0), &stats, 1200));
+        assert!(!fires(RuleKind::DropFromAvg30d, Some(25.0), &stats, 1201));
+    }
+
+    #[test]
+    fn all_time_low_includes_downsampled_days() {
+        let conn = db::open_in_memory().unwrap();
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at)
+             VALUES ('https://example.com/item', 'テスト商品', 1000, '2026-01-01T00:00:00Z')",
+            [],
+        )
+        .unwrap();
+        let product_id = conn.last_insert_rowid();
+        // 日次に集約した古い日の最安値（終値より安い）
+        conn.execute(
+            "INSERT INTO price_history_daily (product_id, day, min_price, max_price, close_price, closed_at)
+             VALUES (?1, '2026-01-01', 800, 1200, 900, '2026-01-01T14:59:59Z')",
+            [product_id],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO price_history (product_id, price, checked_at, last_seen_at)
+             VALUES (?1, 1000, '2026-10-18T00:00:00Z', '2026-10-19T00:00:00Z')",
+            [product_id],
+        )
+        .unwrap();
+
+        let stats = HistoryStats::load(&conn, product_id).unwrap();
+        assert_eq!(stats.all_time_low, Some(800));
+        assert!(fires(RuleKind::AllTimeLow, None, &stats, 799));
+        assert!(!fires(RuleKind::AllTimeLow, None, &stats, 800));
+
+        // 履歴がなければ（初回のチェック）発火しない
+        let first = HistoryStats::from_history(&[], true, at("2026-10-19T00:00:00Z")).unwrap();
+        assert!(!fires(RuleKind::AllTimeLow, None, &first, 1));
+    }
+
+    #[test]
+    fn back_in_stock_fires_once_when_stock_returns() {
+        let conn = db::open_in_memory().unwrap();
+        let mut product = Product {
+            name: "テスト商品".to_string(),
+            price: Some(2480),
+            in_stock: true,
+            url: "https://example.com/item".to_string(),
+            image_url: None,
+            seller: None,
+        };
+        let product_id = db::save_product(&conn, &product).unwrap();
+        add_rule(&conn, product_id, RuleKind::BackInStock, None, None, RuleOptions::default(), &[]).unwrap();
+
+        // 価格チェックと同じ順序: 保存前の統計を取り、保存し、在庫があれば評価する
+        let mut fired = Vec::new();
+        for in_stock in [true, false, false, true, true, true] {
+            product.in_stock = in_stock;
+            product.price = in_stock.then_some(2480);
+            let stats = HistoryStats::load(&conn, product_id).unwrap();
+            db::save_product(&conn, &product).unwrap();
+            let triggered = match product.price {
+                Some(price) if in_stock => evaluate(&conn, product_id, &stats, price).unwrap(),
+                _ => Vec::new(),
+            };
+            fired.push(triggered.iter().any(|a| a.kind == RuleKind::BackInStock));
+        }
+        assert_eq!(fired, [false, false, false, true, false, false]);
+    }
+
+    /// 静かな時間帯に発火して溜まっているアラートを1件作る
+    fn queue_alert(conn: &Connection) -> TriggeredAlert {
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at)
+             VALUES ('https://example.com/item', 'テスト商品', 2480, '2026-10-19T00:00:00Z')",
+            [],
+        )
+        .unwrap();
+        let product_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO alert_rules (product_id, kind, threshold, created_at)
+             VALUES (?1, 'below_price', 2500, '2026-10-19T00:00:00Z')",
+            [product_id],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO alert_events (rule_id, product_id, old_price, new_price, message, triggered_at, queued)
+             VALUES (?1, ?2, 2980, 2480, 'テスト', '2026-10-19T00:00:00Z', 1)",
+            (conn.last_insert_rowid(), product_id),
+        )
+        .unwrap();
+
+        let mut queued = queued_alerts(conn).unwrap();
+        assert_eq!(queued.len(), 1);
+        queued.remove(0)
+    }
+
+    fn delivery(alert: &TriggeredAlert, error: Option<&str>) -> notify::Delivery {
+        notify::Delivery {
+            alert_event_id: alert.event_id,
+            channel_id: 1,
+            attempt: 1,
+            http_status: None,
+            error: error.map(str::to_string),
+            attempted_at: timestamp::now(),
+        }
+    }
+
+    #[test]
+    fn undelivered_queued_alert_stays_queued() {
+        let conn = db::open_in_memory().unwrap();
+        let alert = queue_alert(&conn);
+
+        // どの通知先にも届かなければ残す
+        let failed = [delivery(&alert, Some("HTTP 503"))];
+        assert_eq!(clear_queued(&conn, std::slice::from_ref(&alert), &failed).unwrap(), 1);
+        assert_eq!(queued_alerts(&conn).unwrap().len(), 1);
+
+        // 1つでも届けば外す
+        let delivered = [delivery(&alert, Some("HTTP 503")), delivery(&alert, None)];
+        assert_eq!(clear_queued(&conn, std::slice::from_ref(&alert), &delivered).unwrap(), 0);
+        assert!(queued_alerts(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn queued_alert_without_channels_is_cleared() {
+        let conn = db::open_in_memory().unwrap();
+        let alert = queue_alert(&conn);
+
+        assert_eq!(clear_queued(&conn, &[alert], &[]).unwrap(), 0);
+        assert!(queued_alerts(&conn).unwrap().is_empty());
+    }
+}
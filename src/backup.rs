@@ -0,0 +1,194 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use std::path::Path;
+
+use crate::db::{self, SCHEMA_VERSION};
+use crate::timestamp;
+
+/// SQLite のオンラインバックアップAPIでDBをファイルにコピー
+///
+/// ページ単位でコピーするので、サーバーが書き込み中でも整合性のあるバックアップになる。
+pub fn backup_to(conn: &Connection, dest: &Path) -> Result<()> {
+    if dest.exists() {
+        bail!("Backup file already exists: {}", dest.display());
+    }
+
+    conn.backup(DatabaseName::Main, dest, None)
+        .with_context(|| format!("Failed to back up to {}", dest.display()))?;
+
+    Ok(())
+}
+
+/// バックアップを検証する
+///
+/// 戻り値はバックアップのスキーマバージョン。
+pub fn verify_backup(src: &Path) -> Result<usize> {
+    if !src.exists() {
+        bail!("Backup file not found: {}", src.display());
+    }
+
+    let conn = Connection::open_with_flags(src, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {}", src.display()))?;
+
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .with_context(|| format!("{} is not a valid SQLite database", src.display()))?;
+    if integrity != "ok" {
+        bail!("Backup failed integrity check: {}", integrity);
+    }
+
+    let has_products: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'products')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_products {
+        bail!("{} is not a price monitor database", src.display());
+    }
+
+    let version = db::schema_version(&conn)?;
+    if version > SCHEMA_VERSION {
+        bail!(
+            "Backup schema version {} is newer than supported version {} (please update the app)",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+
+    Ok(version)
+}
+
+/// バックアップからDBを復元する
+///
+/// 復元前に現在のDBを `<DBのパス>.<日時>.bak` に退避し、復元後は最新のスキーマまで
+/// マイグレーションする。戻り値は (復元したバックアップのスキーマバージョン, 退避先)。
+pub fn restore_from(src: &Path) -> Result<(usize, Option<String>)> {
+    let version = verify_backup(src)?;
+
+    let mut conn = Connection::open(db::db_path())?;
+
+    // 現在のDBに商品があれば退避しておく
+    let has_data: bool = conn
+        .query_row("SELECT EXISTS (SELECT 1 FROM products)", [], |row| row.get(0))
+        .unwrap_or(false);
+    let saved = if has_data {
+        let name = format!("{}.{}.bak", db::db_path().display(), timestamp::now().replace(':', ""));
+        backup_to(&conn, Path::new(&name))?;
+        Some(name)
+    } else {
+        None
+    };
+
+    conn.restore(DatabaseName::Main, src, None::<fn(rusqlite::backup::Progress)>)
+        .with_context(|| format!("Failed to restore from {}", src.display()))?;
+    drop(conn);
+
+    // 古いバックアップなら最新のスキーマまでマイグレーション
+    db::init_db()?;
+
+    Ok((version, saved))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// テストごとの作業ディレクトリ（前回の実行の残りは消す）
+    fn work_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("price-monitor-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn product_count(path: &Path) -> i64 {
+        Connection::open(path)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM products", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn backup_round_trip_and_version_checks() {
+        let dir = work_dir("backup");
+        let conn = db::open_in_memory().unwrap();
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at)
+             VALUES ('https://example.com/item', 'テスト商品', 2480, '2026-10-19T00:00:00Z')",
+            [],
+        )
+        .unwrap();
+
+        let backup = dir.join("backup.db");
+        backup_to(&conn, &backup).unwrap();
+        assert_eq!(verify_backup(&backup).unwrap(), SCHEMA_VERSION);
+        assert_eq!(product_count(&backup), 1);
+        // 既存のファイルは上書きしない
+        assert!(backup_to(&conn, &backup).is_err());
+
+        // 新しいバージョンのアプリで作られたバックアップは拒否する
+        let newer = dir.join("newer.db");
+        backup_to(&conn, &newer).unwrap();
+        Connection::open(&newer)
+            .unwrap()
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        let err = restore_from(&newer).unwrap_err();
+        assert!(err.to_string().contains("newer than supported"), "{}", err);
+
+        // v1 の古いバックアップは、復元後に最新のスキーマへ更新する
+        let older = dir.join("older.db");
+        let old = Connection::open(&older).unwrap();
+        old.execute_batch(
+            "CREATE TABLE products (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL UNIQUE,
+                name TEXT NOT NULL,
+                current_price INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            );
+            CREATE TABLE price_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                product_id INTEGER NOT NULL,
+                price INTEGER NOT NULL,
+                checked_at TEXT NOT NULL,
+                FOREIGN KEY (product_id) REFERENCES products(id)
+            );
+            INSERT INTO products VALUES (1, 'https://example.com/old', '古い商品', 980, '2025-01-01 00:00:00');
+            INSERT INTO price_history VALUES (1, 1, 980, '2025-01-01 00:00:00');
+            PRAGMA user_version = 1;",
+        )
+        .unwrap();
+        drop(old);
+
+        // 復元先のDBはこのテストでだけ設定する
+        db::set_db_path(dir.join("products.db"));
+        assert_eq!(restore_from(&older).unwrap(), (1, None));
+        let restored = db::init_db().unwrap();
+        assert_eq!(db::schema_version(&restored).unwrap(), SCHEMA_VERSION);
+        let (name, checked_at): (String, String) = restored
+            .query_row(
+                "SELECT p.name, h.checked_at FROM products p JOIN price_history h ON h.product_id = p.id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((name.as_str(), checked_at.as_str()), ("古い商品", "2025-01-01T00:00:00Z"));
+        drop(restored);
+
+        // 商品のあるDBに復元するときは、今のDBを退避してから置き換える
+        let (version, saved) = restore_from(&backup).unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        let saved = PathBuf::from(saved.unwrap());
+        assert_eq!(product_count(&saved), 1);
+        assert_eq!(product_count(db::db_path()), 1);
+        let name: String = Connection::open(db::db_path())
+            .unwrap()
+            .query_row("SELECT name FROM products", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(name, "テスト商品");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use chrono::Utc;
+use chrono::{SecondsFormat, Utc};
 use rusqlite::Connection;
 
 fn main() -> Result<()> {
@@ -60,7 +60,8 @@ fn main() -> Result<()> {
         ),
     ];
 
-    let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    // 日時はUTCのRFC3339で保存（本体のDB形式に合わせる）
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
 
     for (url, name, price) in sample_products {
         // 商品を挿入（既存の場合はスキップ）
@@ -89,8 +90,7 @@ fn main() -> Result<()> {
 
             for days_ago in (0..7).rev() {
                 let checked_at = (Utc::now() - Duration::days(days_ago))
-                    .format("%Y-%m-%d %H:%M:%S")
-                    .to_string();
+                    .to_rfc3339_opts(SecondsFormat::Secs, true);
 
                 // 価格を±10%でランダムに変動
                 let variation = rng.gen_range(-10..=10);
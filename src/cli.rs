@@ -1,11 +1,56 @@
 use clap::{Parser, Subcommand};
 
+use crate::alerts::RuleKind;
+use crate::notify::ChannelKind;
+
 #[derive(Parser)]
 #[command(name = "rust-price-monitor")]
 #[command(about = "Amazon商品の価格追跡ツール", long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+
+    /// 設定ファイル（省略時は ./config.toml、ユーザーの設定ディレクトリ、/etc の順に探す）
+    #[arg(long, global = true, env = "PRICE_MONITOR_CONFIG")]
+    pub config: Option<std::path::PathBuf>,
+
+    // 以下は設定ファイル・環境変数より優先する（値は config で検証する）
+
+    /// 日時の表示に使うタイムゾーン（例: Asia/Tokyo, UTC）
+    #[arg(long, global = true)]
+    pub tz: Option<String>,
+
+    /// 通知を送らない時間帯（例: 22:00-07:00）。この間のアラートは後でまとめて送る
+    #[arg(long, global = true)]
+    pub quiet_hours: Option<String>,
+
+    /// 定期価格チェックのスケジュール（Cron形式: "秒 分 時 日 月 曜日"）
+    #[arg(long, global = true)]
+    pub check_schedule: Option<String>,
+
+    /// 価格の変化の頻度から決めるチェック間隔の下限（よく変わる商品）
+    #[arg(long, global = true)]
+    pub min_check_interval: Option<String>,
+
+    /// 価格の変化の頻度から決めるチェック間隔の上限（変わらない商品）
+    #[arg(long, global = true)]
+    pub max_check_interval: Option<String>,
+
+    /// Web UI が待ち受けるアドレス（例: 0.0.0.0 で外部からも接続できる）
+    #[arg(long, global = true)]
+    pub host: Option<String>,
+
+    /// Web UI が待ち受けるポート
+    #[arg(long, global = true)]
+    pub port: Option<String>,
+
+    /// リバースプロキシの配下で動かすときの URL の接頭辞（例: /prices）
+    #[arg(long, global = true)]
+    pub base_path: Option<String>,
+
+    /// 画面のファイルを読み込むディレクトリ（省略時はバイナリに埋め込んだものを使う）
+    #[arg(long, global = true)]
+    pub static_dir: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -16,13 +61,324 @@ pub enum Commands {
         url: String,
     },
     /// すべての商品の価格をチェック
-    Check,
+    Check {
+        /// このタグのついた商品だけチェック
+        #[arg(long)]
+        tag: Option<String>,
+    },
     /// 登録済み商品の一覧を表示
-    List,
+    List {
+        /// アーカイブ済みの商品を表示
+        #[arg(long)]
+        archived: bool,
+        /// このタグのついた商品だけ表示
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// 商品名で検索
+    Search {
+        /// 検索する語（空白で区切るとすべてを含む商品）
+        query: String,
+        /// アーカイブ済みの商品も含める
+        #[arg(long)]
+        archived: bool,
+        /// 表示する件数
+        #[arg(long, default_value_t = crate::search::DEFAULT_LIMIT)]
+        limit: usize,
+    },
+    /// 商品の価格履歴を表示
+    History {
+        /// 商品ID
+        id: i64,
+        /// この日時以降（RFC3339 または YYYY-MM-DD）
+        #[arg(long)]
+        from: Option<String>,
+        /// この日時より前（RFC3339 または YYYY-MM-DD）
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// 価格チェックの実行記録を表示
+    Runs {
+        /// 指定した実行の商品ごとの結果を表示
+        #[arg(long, conflicts_with = "product")]
+        id: Option<i64>,
+        /// 指定した商品の試行履歴を表示
+        #[arg(long)]
+        product: Option<i64>,
+        /// 表示件数
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// 商品のタグの管理
+    Tag {
+        #[command(subcommand)]
+        action: TagCommands,
+    },
+    /// アラートルールの管理
+    Rules {
+        #[command(subcommand)]
+        action: RulesCommands,
+    },
+    /// アラートの通知先の管理
+    Notify {
+        #[command(subcommand)]
+        action: NotifyCommands,
+    },
+    /// 発火したアラートの履歴を表示
+    Alerts {
+        /// 商品IDで絞り込む
+        #[arg(long)]
+        product: Option<i64>,
+        /// 表示件数
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// 定期実行スケジューラーの状態確認・一時停止・再開・手動実行
+    Scheduler {
+        #[command(subcommand)]
+        action: SchedulerCommands,
+    },
+    /// 商品をアーカイブ（価格履歴は残す）
+    Archive {
+        /// 商品ID
+        id: i64,
+    },
+    /// アーカイブした商品を元に戻す
+    Unarchive {
+        /// 商品ID
+        id: i64,
+    },
+    /// 商品と価格履歴を完全に削除
+    Delete {
+        /// 商品ID
+        id: i64,
+    },
     /// 商品一覧をCSVにエクスポート
     Export {
         /// 出力ファイル名
         filename: String,
+        /// このタグのついた商品だけエクスポート
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// データベースをバックアップ（サーバー起動中でも安全）
+    Backup {
+        /// バックアップ先のファイル
+        file: String,
+    },
+    /// バックアップからデータベースを復元
+    Restore {
+        /// バックアップファイル
+        file: String,
+    },
+    /// データベースの整合性をチェック
+    Doctor {
+        /// 見つかった問題を修復する
+        #[arg(long)]
+        repair: bool,
+    },
+    /// 商品ごとのチェック間隔を設定（省略すると自動調整に戻す）
+    Interval {
+        /// 商品ID
+        id: i64,
+        /// チェック間隔（例: 15m, 2h, 1d）
+        interval: Option<crate::scheduler::CheckInterval>,
+    },
+    /// 商品の表示名・URL・目標価格・タグなどをまとめて変更（指定しなかった項目は変えない）
+    Edit {
+        /// 商品ID
+        id: i64,
+        /// 表示名（空文字で商品ページの名前に戻す）
+        #[arg(long)]
+        name: Option<String>,
+        /// 商品ページのURL（新しいURLで取得し直して確かめる）
+        #[arg(long)]
+        url: Option<String>,
+        /// 目標価格（円）
+        #[arg(long, conflicts_with = "no_target")]
+        target: Option<i32>,
+        /// 目標価格を解除
+        #[arg(long)]
+        no_target: bool,
+        /// タグ（カンマ区切り。今のタグを置き換える。空文字で全部外す）
+        #[arg(long)]
+        tags: Option<String>,
+        /// チェック間隔（例: 15m, 2h, 1d）
+        #[arg(long, conflicts_with = "auto_interval")]
+        interval: Option<crate::scheduler::CheckInterval>,
+        /// チェック間隔を自動調整に戻す
+        #[arg(long)]
+        auto_interval: bool,
+        /// 価格チェックを再開
+        #[arg(long, conflicts_with = "disable")]
+        enable: bool,
+        /// 価格チェックを止める（商品と価格履歴は残す）
+        #[arg(long)]
+        disable: bool,
+        /// カテゴリー（空文字で解除）
+        #[arg(long)]
+        category: Option<String>,
+        /// メモ（空文字で解除）
+        #[arg(long)]
+        note: Option<String>,
+    },
+    /// 価格履歴を圧縮し、古いデータを日次に集約
+    Prune {
+        /// 生データを保持する日数（設定の retention.days より優先）
+        #[arg(long)]
+        days: Option<String>,
+    },
+    /// Web UI を起動（サブコマンドなしと同じ）
+    Serve {
+        /// 定期価格チェックを動かさない（daemon を別プロセスで動かすとき）
+        #[arg(long)]
+        no_scheduler: bool,
+    },
+    /// Web UI なしで定期価格チェック（スケジューラー）だけを動かす
+    Daemon {
+        /// メトリクス（GET /metrics）を提供するアドレス（例: 127.0.0.1:9100）
+        #[arg(long)]
+        metrics: Option<String>,
+    },
+    /// 設定
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommands,
+    },
+    // サブコマンドなし => Web UIを起動（スケジューラー付き）
+}
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// 有効な設定（デフォルト・設定ファイル・環境変数・引数を合わせたもの）と、それぞれの値の出どころを表示
+    Show,
+}
+#[derive(Subcommand)]
+pub enum TagCommands {
+    /// 商品にタグをつける
+    Add {
+        /// 商品ID
+        id: i64,
+        /// タグ（複数指定可）
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// 商品からタグを外す
+    Remove {
+        /// 商品ID
+        id: i64,
+        /// タグ（複数指定可）
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// 使われているタグの一覧
+    List,
+}
+#[derive(Subcommand)]
+pub enum RulesCommands {
+    /// アラートルールを追加
+    Add {
+        /// 商品ID
+        product: i64,
+        /// ルールの種類
+        kind: RuleKind,
+        /// しきい値（below-price は円、drop-from-last / drop-from-avg30d は%）
+        threshold: Option<f64>,
+        /// 条件式（expression のとき。例: "price < 2500 and price < min_30d * 0.95"）
+        #[arg(long = "expr")]
+        expression: Option<String>,
+        /// 送り先の通知先ID（複数指定可。省略すると全ての通知先）
+        #[arg(long = "channel")]
+        channels: Vec<i64>,
+        /// 再び発火するまでの最短間隔（分）
+        #[arg(long)]
+        cooldown: Option<i64>,
+        /// 条件を満たしている間は毎回発火する（デフォルトは条件を外れるまで1回だけ）
+        #[arg(long)]
+        no_rearm: bool,
+    },
+    /// アラートルールの一覧
+    List {
+        /// 商品IDで絞り込む
+        #[arg(long)]
+        product: Option<i64>,
+    },
+    /// アラートルールの通知先を設定
+    Channels {
+        /// ルールID
+        id: i64,
+        /// 通知先ID（省略すると全ての通知先に戻す）
+        channels: Vec<i64>,
+    },
+    /// アラートルールを削除
+    Remove {
+        /// ルールID
+        id: i64,
+    },
+    /// 保存済みの価格履歴でアラートルールを試す（通知は送らない）
+    Test {
+        /// 商品ID
+        product: i64,
+        /// 試す条件式（省略すると商品に登録済みのルールを試す）
+        #[arg(long = "expr")]
+        expression: Option<String>,
+        /// 試す登録済みのルールID
+        #[arg(long, conflicts_with = "expression")]
+        rule: Option<i64>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SchedulerCommands {
+    /// 状態を表示（次の実行日時・実行中の価格チェック・一時停止中か）
+    Status,
+    /// 定期価格チェックを一時停止
+    Pause,
+    /// 定期価格チェックを再開
+    Resume,
+    /// 全商品の価格チェックをリクエスト（起動中のサーバーか daemon が1分以内に実行）
+    Run,
+}
+
+#[derive(Subcommand)]
+pub enum NotifyCommands {
+    /// 通知先を追加
+    Add {
+        /// 通知先の種類
+        kind: ChannelKind,
+        /// 送信先URL（line はアクセストークン、email は宛先アドレス）
+        target: String,
+        /// 本文の HMAC-SHA256 署名に使う秘密鍵（webhook のみ）
+        #[arg(long)]
+        secret: Option<String>,
+        /// 毎朝の日次レポートも送る（email のみ）
+        #[arg(long)]
+        digest: bool,
+    },
+    /// 通知先の一覧
+    List,
+    /// 通知先を削除
+    Remove {
+        /// 通知先ID
+        id: i64,
+    },
+    /// テスト通知を送信
+    Test {
+        /// 通知先ID
+        id: i64,
+    },
+    /// 日次レポートを表示（--send でメールの通知先に送信）
+    Digest {
+        /// 対象期間（時間）
+        #[arg(long, default_value_t = crate::digest::DEFAULT_DIGEST_HOURS)]
+        hours: i64,
+        /// レポートを受け取る通知先に送信する
+        #[arg(long)]
+        send: bool,
+    },
+    /// 配信ログを表示
+    Log {
+        /// 表示件数
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
     },
-    // サブコマンドなし => Web UIを起動
 }
\ No newline at end of file
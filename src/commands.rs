@@ -1,56 +1,228 @@
 use anyhow::Result;
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
+use std::path::Path;
 
-use crate::db::{save_price_history, save_product};
-use crate::scraper::fetch_amazon_price;
+use crate::db::{
+    archive_product, delete_product, load_price_history, restore_product, save_price_history,
+    save_product, set_check_interval, SCHEMA_VERSION,
+};
+use crate::alerts::{self, HistoryStats, RuleKind};
+use crate::backup;
+use crate::doctor;
+use crate::digest;
+use crate::edit::{self, EditErrorKind, ProductEdit};
+use crate::notify::{self, ChannelKind};
+use crate::retention;
+use crate::runs::{self, Trigger};
+use crate::scheduler::{self, CheckInterval};
+use crate::search;
+use crate::tags;
+use crate::timestamp;
+use crate::scraper::{self, fetch_amazon_price};
 
 // 🔧 async fnに変更
 pub async fn cmd_add(conn: &Connection, url: &str) -> Result<()> {
+    // edit で変えるURLと同じ形にそろえる（同じ商品を別のURLで二重に登録しないように）
+    let url = edit::canonical_url(url)?;
     println!("追加中: {}", url);
     
-    let product = fetch_amazon_price(url).await?;
+    let product = fetch_amazon_price(&url).await?;
+    let Some(price) = product.price else {
+        anyhow::bail!("在庫切れで価格が表示されていないため追加できません: {}", product.name);
+    };
     let product_id = save_product(conn, &product)?;
-    save_price_history(conn, product_id, product.price)?;
+    // アーカイブ済みの商品を追加し直した場合は復元する
+    restore_product(conn, product_id)?;
+    save_price_history(conn, product_id, price)?;
+    scheduler::reschedule(conn, product_id)?;
 
     println!("✅ 商品を追加しました: {}", product.name);
-    println!("   価格: ¥{}", product.price);
+    println!("   価格: ¥{}", price);
+    if !product.in_stock {
+        println!("   🚫 在庫切れ");
+    }
 
     Ok(())
 }
 
-pub fn cmd_list(conn: &Connection) -> Result<()> {
-    let mut stmt = conn.prepare("SELECT id, url, name, current_price FROM products ORDER BY id DESC")?;
+pub fn cmd_list(conn: &Connection, archived: bool, tag: Option<&str>) -> Result<()> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT p.id, p.url, COALESCE(p.display_name, p.name), p.current_price, p.in_stock,
+                p.check_interval_minutes, p.next_check_at,
+                p.needs_attention_at IS NOT NULL, p.consecutive_failures,
+                (SELECT a.error_message FROM check_attempts a WHERE a.product_id = p.id
+                 ORDER BY a.attempted_at DESC, a.id DESC LIMIT 1),
+                p.category, p.note, {}, p.target_price, p.disabled_at IS NOT NULL
+         FROM products p
+         WHERE (p.archived_at IS NOT NULL) = ?1 AND (?2 IS NULL OR {})
+         ORDER BY p.id DESC",
+        tags::TAG_LIST,
+        tags::has_tag(2)
+    ))?;
 
-    let products = stmt.query_map([], |row| {
+    let products = stmt.query_map((archived, tag), |row| {
+        // 要確認の商品は、連続失敗の回数と最後のエラー
+        let attention = if row.get::<_, bool>(7)? {
+            Some((row.get::<_, i64>(8)?, row.get::<_, Option<String>>(9)?))
+        } else {
+            None
+        };
         Ok((
             row.get::<_, i64>(0)?,
             row.get::<_, String>(1)?,
             row.get::<_, String>(2)?,
             row.get::<_, i32>(3)?,
+            row.get::<_, bool>(4)?,
+            row.get::<_, Option<i64>>(5)?,
+            row.get::<_, Option<String>>(6)?,
+            attention,
+            (
+                row.get::<_, Option<String>>(10)?,
+                tags::split_tags(row.get(12)?),
+                row.get::<_, Option<String>>(11)?,
+                row.get::<_, Option<i32>>(13)?,
+                row.get::<_, bool>(14)?,
+            ),
         ))
     })?;
 
-    println!("\n📦 登録商品一覧:");
+    let tag_label = tag.map(|t| format!("（タグ: {}）", t)).unwrap_or_default();
+    if archived {
+        println!("\n🗃️  アーカイブ済み商品一覧{}:", tag_label);
+    } else {
+        println!("\n📦 登録商品一覧{}:", tag_label);
+    }
     println!("{}", "=".repeat(80));
 
+    let mut attention_count = 0;
     for (i, product) in products.enumerate() {
-        if let Ok((id, url, name, price)) = product {
-            println!("{}. [ID:{}] {}", i + 1, id, name);
-            println!("   価格: ¥{}", price);
+        if let Ok((id, url, name, price, in_stock, interval_minutes, next_check_at, attention, details)) = product {
+            let (category, product_tags, note, target_price, disabled) = details;
+            if disabled {
+                println!("{}. [ID:{}] {}（⏸️ チェック停止中）", i + 1, id, name);
+            } else {
+                println!("{}. [ID:{}] {}", i + 1, id, name);
+            }
+            if in_stock {
+                println!("   価格: ¥{}", price);
+            } else {
+                println!("   価格: ¥{}（🚫 在庫切れ）", price);
+            }
+            if let Some(target_price) = target_price {
+                if price <= target_price {
+                    println!("   目標価格: ¥{}（🎯 達成）", target_price);
+                } else {
+                    println!("   目標価格: ¥{}（あと ¥{}）", target_price, price - target_price);
+                }
+            }
             println!("   URL: {}", url);
+            if let Some(category) = category {
+                println!("   カテゴリー: {}", category);
+            }
+            if !product_tags.is_empty() {
+                println!("   タグ: {}", product_tags.join(", "));
+            }
+            if let Some(note) = note {
+                println!("   メモ: {}", note);
+            }
+            if let Some(minutes) = interval_minutes {
+                println!("   チェック間隔: {}ごと", CheckInterval { minutes });
+            }
+            if let Some((failures, last_error)) = attention {
+                attention_count += 1;
+                println!("   ⚠️  要確認: {}回連続で失敗（{}）", failures, last_error.as_deref().unwrap_or("-"));
+            }
+            if let (false, false, Some(next_check_at)) = (archived, disabled, next_check_at) {
+                println!("   次回チェック: {}", timestamp::display(&next_check_at));
+            }
             println!("{}", "-".repeat(80));
         }
     }
 
+    if attention_count > 0 && !archived {
+        println!(
+            "\n⚠️  要確認の商品が {}件あります（商品ページがなくなったか、URLが変わった可能性があります）",
+            attention_count
+        );
+        println!("   次に成功すると元に戻ります。不要な商品は archive でアーカイブしてください");
+    }
+
+    Ok(())
+}
+
+pub fn cmd_search(conn: &Connection, query: &str, archived: bool, limit: usize) -> Result<()> {
+    let hits = search::search_products(conn, query, archived, limit)?;
+
+    if hits.is_empty() {
+        println!("🔍 「{}」に一致する商品はありません", query);
+        return Ok(());
+    }
+
+    println!("\n🔍 「{}」の検索結果（{}件）:", query, hits.len());
+    println!("{}", "=".repeat(80));
+    for (i, hit) in hits.iter().enumerate() {
+        let mut labels = Vec::new();
+        if !hit.in_stock {
+            labels.push("🚫 在庫切れ");
+        }
+        if hit.archived {
+            labels.push("🗃️ アーカイブ済み");
+        }
+        println!("{}. [ID:{}] {}", i + 1, hit.id, hit.name);
+        if labels.is_empty() {
+            println!("   価格: ¥{}", hit.current_price);
+        } else {
+            println!("   価格: ¥{}（{}）", hit.current_price, labels.join("・"));
+        }
+        println!("   URL: {}", hit.url);
+        println!("{}", "-".repeat(80));
+    }
+
+    Ok(())
+}
+
+pub fn cmd_history(conn: &Connection, product_id: i64, from: Option<&str>, to: Option<&str>) -> Result<()> {
+    let from = from.map(timestamp::parse_bound).transpose()?;
+    let to = to.map(timestamp::parse_bound).transpose()?;
+
+    let name: String = conn.query_row(
+        "SELECT COALESCE(display_name, name) FROM products WHERE id = ?1",
+        [product_id],
+        |row| row.get(0),
+    )?;
+    let history = load_price_history(conn, product_id, from.as_deref(), to.as_deref())?;
+
+    println!("\n📈 価格履歴: {} ({})", name, timestamp::timezone());
+    println!("{}", "=".repeat(80));
+
+    for h in history {
+        let period = if h.last_seen_at == h.checked_at {
+            timestamp::display(&h.checked_at)
+        } else {
+            format!("{} 〜 {}", timestamp::display(&h.checked_at), timestamp::display(&h.last_seen_at))
+        };
+
+        match (h.min_price, h.max_price) {
+            (Some(min), Some(max)) => {
+                println!("{}  ¥{} (日次 最安 ¥{} / 最高 ¥{})", period, h.price, min, max)
+            }
+            _ => println!("{}  ¥{}", period, h.price),
+        }
+    }
+
     Ok(())
 }
 
 // 🔧 async fnに変更
-pub async fn cmd_check(conn: &Connection) -> Result<()> {
-    let mut stmt = conn.prepare("SELECT id, url, name, current_price FROM products")?;
+pub async fn cmd_check(conn: &Connection, tag: Option<&str>) -> Result<()> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT p.id, p.url, COALESCE(p.display_name, p.name), p.current_price FROM products p
+         WHERE p.archived_at IS NULL AND p.disabled_at IS NULL AND (?1 IS NULL OR {})",
+        tags::has_tag(1)
+    ))?;
 
     let products: Vec<(i64, String, String, i32)> = stmt
-        .query_map([], |row| {
+        .query_map([tag], |row| {
             Ok((
                 row.get(0)?,
                 row.get(1)?,
@@ -63,17 +235,97 @@ pub async fn cmd_check(conn: &Connection) -> Result<()> {
 
     drop(stmt);
 
+    // serve / daemon と同じDBを使っていても、価格チェックが重ならないように実行権を取る
+    if !scheduler::acquire_lease(conn)? {
+        anyhow::bail!("ほかのプロセス（serve / daemon）で価格チェックが実行中です。終わってからもう一度実行してください");
+    }
+    let result = check_products(conn, products).await;
+    if let Err(e) = scheduler::release_lease(conn) {
+        eprintln!("❌ 価格チェックの実行権の解放エラー: {}", e);
+    }
+    result
+}
+
+/// 実行権を取ってから呼ぶ（Ctrl-C を受け取ったら、残りの商品は次回のスケジューラーの起動時に回す）
+async fn check_products(conn: &Connection, products: Vec<(i64, String, String, i32)>) -> Result<()> {
+    tokio::spawn(async {
+        scheduler::shutdown_signal().await;
+        scheduler::begin_shutdown();
+    });
+
     println!("\n🔄 価格チェック中...\n");
 
-    for (product_id, url, old_name, old_price) in products {
+    let channels = notify::list_channels(conn)?;
+    let ids: Vec<i64> = products.iter().map(|(id, ..)| *id).collect();
+    let run_id = runs::start_run(conn, Trigger::Cli, &ids)?;
+
+    // 静かな時間帯に溜まっていたアラートを先に送る（実行権を持っているので、スケジューラーと二重に送らない）
+    let queued = alerts::queued_alerts(conn).unwrap_or_else(|e| {
+        eprintln!("❌ 溜まっていたアラートの読み込みエラー: {}", e);
+        Vec::new()
+    });
+    if !queued.is_empty() {
+        println!("🔔 静かな時間帯に溜まっていたアラート {}件を送信します\n", queued.len());
+        let deliveries = notify::deliver(&channels, &queued).await;
+        if let Err(e) = notify::record_deliveries(conn, &deliveries) {
+            eprintln!("❌ 送信記録の保存エラー: {}", e);
+        }
+        match alerts::clear_queued(conn, &queued, &deliveries) {
+            Ok(0) => {}
+            Ok(kept) => eprintln!("⚠️  送れなかったアラート {}件は次のチェックで送り直します\n", kept),
+            Err(e) => eprintln!("❌ アラートのキューの更新エラー: {}", e),
+        }
+    }
+
+    // 取得はレート制限の設定に従って先に進め（同時に max_concurrent 件まで）、結果は商品の順に表示する
+    use futures::stream::{self, StreamExt};
+    let urls: Vec<String> = products.iter().map(|(_, url, ..)| url.clone()).collect();
+    let mut fetches = stream::iter(urls)
+        .map(|url| async move { scraper::fetch_throttled(&url).await })
+        .buffered(scraper::rate_limit().max_concurrent);
+
+    // 1件ごとのエラーで中断せず、最後に必ず実行を終える（終えないと実行中のままになる）
+    let total = products.len();
+    for (checked, (product_id, _, old_name, old_price)) in products.iter().enumerate() {
+        let (product_id, old_price) = (*product_id, *old_price);
+        if scheduler::shutting_down() {
+            runs::interrupt_run(conn, run_id)?;
+            println!("⏹️  中断しました（残り {}件は次回のスケジューラーの起動時にチェックします）", total - checked);
+            return Ok(());
+        }
+        if let Err(e) = scheduler::renew_lease(conn) {
+            eprintln!("❌ 価格チェックの実行権の延長エラー: {}", e);
+        }
         println!("チェック中: {} ...", old_name);
 
-        match fetch_amazon_price(&url).await {
-            Ok(product) => {
-                save_product(conn, &product)?;
-                save_price_history(conn, product_id, product.price)?;
+        // アラート評価用に、今回の価格を保存する前の統計を取っておく
+        let stats = HistoryStats::load(conn, product_id);
+
+        let Some((fetched, latency)) = fetches.next().await else {
+            break;
+        };
+        let outcome = match fetched {
+            Ok(product) => save_product(conn, &product)
+                .and_then(|_| match product.price {
+                    Some(price) => save_price_history(conn, product_id, price),
+                    None => Ok(()),
+                })
+                .map(|_| (product.price, product.in_stock)),
+            Err(e) => Err(e),
+        };
+        let needs_attention =
+            runs::record_attempt(conn, run_id, product_id, outcome.as_ref().map(|(price, _)| *price), latency)
+                .unwrap_or_else(|e| {
+                    eprintln!("  ❌ 実行記録の保存エラー: {}", e);
+                    false
+                });
+        if let Err(e) = scheduler::reschedule(conn, product_id) {
+            eprintln!("  ❌ 次回チェック日時の更新エラー: {}", e);
+        }
 
-                let diff = product.price - old_price;
+        match outcome {
+            Ok((Some(price), true)) => {
+                let diff = price - old_price;
                 let status = if diff > 0 {
                     format!("📈 +¥{}", diff)
                 } else if diff < 0 {
@@ -82,44 +334,252 @@ pub async fn cmd_check(conn: &Connection) -> Result<()> {
                     "➡️  変動なし".to_string()
                 };
 
-                println!("  現在価格: ¥{} {}", product.price, status);
+                println!("  現在価格: ¥{} {}", price, status);
+
+                let triggered = stats
+                    .and_then(|stats| alerts::evaluate(conn, product_id, &stats, price))
+                    .unwrap_or_else(|e| {
+                        eprintln!("  ❌ アラート評価エラー: {}", e);
+                        Vec::new()
+                    });
+                for alert in &triggered {
+                    if alert.queued {
+                        println!("  🔕 {}（静かな時間帯のため後で通知）", alert.message);
+                    } else {
+                        println!("  🔔 {}", alert.message);
+                    }
+                }
+
+                let deliveries = notify::deliver(&channels, &triggered).await;
+                if let Err(e) = notify::record_deliveries(conn, &deliveries) {
+                    eprintln!("  ❌ 送信記録の保存エラー: {}", e);
+                }
+            }
+            Ok((price, _)) => {
+                println!("  🚫 在庫切れ（最後の価格: ¥{}）", price.unwrap_or(old_price));
             }
             Err(e) => {
                 eprintln!("  ⚠️  エラー: {}", e);
+                if needs_attention {
+                    eprintln!("  ⚠️  要確認にしました（定期チェックの間隔を空けます）");
+                }
             }
         }
         println!();
     }
 
+    runs::finish_run(conn, run_id)?;
+
     Ok(())
 }
 
-pub fn cmd_export(conn: &Connection, filename: &str) -> Result<()> {
+pub fn cmd_runs(conn: &Connection, run_id: Option<i64>, product_id: Option<i64>, limit: usize) -> Result<()> {
+    if run_id.is_none() && product_id.is_none() {
+        println!("\n🗂️  価格チェックの実行記録:");
+        println!("{}", "=".repeat(80));
+
+        for run in runs::list_runs(conn, limit)? {
+            let mut finished = match (&run.finished_at, run.interrupted) {
+                (Some(finished_at), true) => format!("{}（⏹️  中断）", timestamp::display(finished_at)),
+                (Some(finished_at), false) => timestamp::display(finished_at),
+                (None, _) => "実行中".to_string(),
+            };
+            if let Some(missed_since) = &run.missed_since {
+                finished.push_str(&format!("（⏭️  {}からの取りこぼし）", timestamp::display(missed_since)));
+            }
+            println!(
+                "#{} [{}] {} 〜 {}  成功 {} / 失敗 {} / 対象 {}",
+                run.id,
+                run.trigger,
+                timestamp::display(&run.started_at),
+                finished,
+                run.succeeded,
+                run.failed,
+                run.total
+            );
+        }
+
+        return Ok(());
+    }
+
+    if let Some(run_id) = run_id {
+        let run = runs::get_run(conn, run_id)?
+            .ok_or_else(|| anyhow::anyhow!("実行記録が見つかりません: #{}", run_id))?;
+        println!("\n🗂️  実行 #{} [{}] {}", run.id, run.trigger, timestamp::display(&run.started_at));
+    } else if let Some(product_id) = product_id {
+        println!("\n🗂️  商品 [ID:{}] の試行履歴", product_id);
+    }
+    println!("{}", "=".repeat(80));
+
+    for a in runs::list_attempts(conn, run_id, product_id, limit)? {
+        let result = match (a.price, a.error_kind) {
+            (Some(price), _) => format!("✅ ¥{}", price),
+            (None, None) => "✅ 在庫切れ".to_string(),
+            (None, kind) => {
+                let status = a.http_status.map(|s| format!(" HTTP {}", s)).unwrap_or_default();
+                format!(
+                    "❌ {}{}: {}",
+                    kind.unwrap_or_default(),
+                    status,
+                    a.error_message.unwrap_or_default()
+                )
+            }
+        };
+        println!(
+            "{} #{} [ID:{}] {} ({}ms)  {}",
+            timestamp::display(&a.attempted_at),
+            a.run_id,
+            a.product_id,
+            a.product_name,
+            a.latency_ms,
+            result
+        );
+    }
+
+    Ok(())
+}
+
+pub fn cmd_archive(conn: &Connection, product_id: i64) -> Result<()> {
+    if !archive_product(conn, product_id)? {
+        anyhow::bail!("商品が見つかりません: ID {}", product_id);
+    }
+    println!("🗃️  商品をアーカイブしました (ID: {})", product_id);
+    println!("   元に戻すには: unarchive {}", product_id);
+    Ok(())
+}
+
+pub fn cmd_unarchive(conn: &Connection, product_id: i64) -> Result<()> {
+    if !restore_product(conn, product_id)? {
+        anyhow::bail!("商品が見つかりません: ID {}", product_id);
+    }
+    println!("♻️  商品を復元しました (ID: {})", product_id);
+    Ok(())
+}
+
+pub fn cmd_interval(conn: &Connection, product_id: i64, interval: Option<CheckInterval>) -> Result<()> {
+    if !set_check_interval(conn, product_id, interval.map(|i| i.minutes))? {
+        anyhow::bail!("商品が見つかりません: ID {}", product_id);
+    }
+    scheduler::reschedule(conn, product_id)?;
+    match interval {
+        Some(interval) => println!("⏱️  チェック間隔を設定しました (ID: {}): {}ごと", product_id, interval),
+        None => {
+            let bounds = scheduler::adaptive_bounds();
+            println!(
+                "⏱️  チェック間隔を自動調整に戻しました (ID: {}): 価格の変化の頻度に応じて{}〜{}ごと（{}）",
+                product_id,
+                bounds.min,
+                bounds.max,
+                scheduler::check_schedule()
+            )
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_scheduler_status(conn: &Connection) -> Result<()> {
+    let status = scheduler::status(conn)?;
+
+    println!("\n🕐 スケジューラーの状態:");
+    println!("{}", "=".repeat(80));
+    match &status.paused_at {
+        Some(paused_at) => println!("状態: ⏸️  一時停止中（{}から）", timestamp::display(paused_at)),
+        None => println!("状態: ▶️  動作中"),
+    }
+    println!("スケジュール: {}", status.schedule);
+    match &status.running {
+        Some(run) => println!(
+            "実行中: #{} [{}] {}から（対象 {}件）",
+            run.id,
+            run.trigger,
+            timestamp::display(&run.started_at),
+            run.total
+        ),
+        None => println!("実行中: なし"),
+    }
+    if let Some(requested_at) = &status.run_requested_at {
+        println!("手動実行: {}にリクエスト済み（サーバーか daemon が実行するのを待っています）", timestamp::display(requested_at));
+    }
+    match &status.next_check_at {
+        Some(next) => println!("次回チェック: {}（チェック時刻を過ぎた商品 {}件）", timestamp::display(next), status.due),
+        None => println!("次回チェック: -"),
+    }
+
+    Ok(())
+}
+
+pub fn cmd_scheduler_pause(conn: &Connection) -> Result<()> {
+    if scheduler::pause(conn)? {
+        println!("⏸️  定期価格チェックを一時停止しました（日次レポートと履歴の整理は続けます）");
+    } else {
+        println!("⏸️  すでに一時停止中です");
+    }
+    Ok(())
+}
+
+pub fn cmd_scheduler_resume(conn: &Connection) -> Result<()> {
+    if scheduler::resume(conn)? {
+        println!("▶️  定期価格チェックを再開しました");
+    } else {
+        println!("▶️  一時停止していません");
+    }
+    Ok(())
+}
+
+pub fn cmd_scheduler_run(conn: &Connection) -> Result<()> {
+    scheduler::request_run(conn)?;
+    println!("▶️  全商品の価格チェックをリクエストしました（起動中のサーバーか daemon が1分以内に実行します）");
+    Ok(())
+}
+
+pub fn cmd_delete(conn: &Connection, product_id: i64) -> Result<()> {
+    if !delete_product(conn, product_id)? {
+        anyhow::bail!("商品が見つかりません: ID {}", product_id);
+    }
+    println!("🗑️  商品と価格履歴を完全に削除しました (ID: {})", product_id);
+    Ok(())
+}
+
+pub fn cmd_export(conn: &Connection, filename: &str, tag: Option<&str>) -> Result<()> {
     use std::fs::File;
 
     let mut wtr = csv::Writer::from_writer(File::create(filename)?);
 
-    wtr.write_record(&["id", "name", "url", "current_price"])?;
+    wtr.write_record(["id", "name", "url", "current_price", "category", "tags", "note", "target_price"])?;
 
-    let mut stmt = conn.prepare("SELECT id, name, url, current_price FROM products")?;
-    let products = stmt.query_map([], |row| {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT p.id, COALESCE(p.display_name, p.name), p.url, p.current_price, p.category, {}, p.note,
+                p.target_price
+         FROM products p
+         WHERE p.archived_at IS NULL AND (?1 IS NULL OR {})",
+        tags::TAG_LIST,
+        tags::has_tag(1)
+    ))?;
+    let products = stmt.query_map([tag], |row| {
         Ok((
             row.get::<_, i64>(0)?,
             row.get::<_, String>(1)?,
             row.get::<_, String>(2)?,
             row.get::<_, i32>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            tags::split_tags(row.get(5)?),
+            row.get::<_, Option<String>>(6)?,
+            row.get::<_, Option<i32>>(7)?,
         ))
     })?;
 
-    for product in products {
-        if let Ok((id, name, url, price)) = product {
-            wtr.write_record(&[
-                id.to_string(),
-                name,
-                url,
-                price.to_string(),
-            ])?;
-        }
+    // タグにはカンマを使えないので、カンマ区切りで1列にまとめる
+    for (id, name, url, price, category, product_tags, note, target_price) in products.flatten() {
+        wtr.write_record(&[
+            id.to_string(),
+            name,
+            url,
+            price.to_string(),
+            category.unwrap_or_default(),
+            product_tags.join(","),
+            note.unwrap_or_default(),
+            target_price.map(|p| p.to_string()).unwrap_or_default(),
+        ])?;
     }
 
     wtr.flush()?;
@@ -127,3 +587,482 @@ pub fn cmd_export(conn: &Connection, filename: &str) -> Result<()> {
 
     Ok(())
 }
+
+pub fn cmd_prune(conn: &Connection, days: i64) -> Result<()> {
+    println!("🧹 価格履歴を整理中...（生データ保持: {}日）", days);
+
+    let stats = retention::prune(conn, days)?;
+
+    println!("✅ 整理完了");
+    println!("   同一価格の連続を圧縮: {}行", stats.compacted);
+    println!("   日次データに集約: {}行 → {}日分", stats.downsampled, stats.daily_rows);
+
+    Ok(())
+}
+
+pub fn cmd_backup(conn: &Connection, filename: &str) -> Result<()> {
+    println!("💾 バックアップ中: {}", filename);
+
+    backup::backup_to(conn, Path::new(filename))?;
+
+    println!("✅ バックアップ完了: {}", filename);
+    Ok(())
+}
+
+pub fn cmd_restore(filename: &str) -> Result<()> {
+    println!("♻️  復元中: {}", filename);
+
+    let (version, saved) = backup::restore_from(Path::new(filename))?;
+
+    if let Some(saved) = saved {
+        println!("   復元前のデータベースを退避しました: {}", saved);
+    }
+    if version < SCHEMA_VERSION {
+        println!("   スキーマを v{} から v{} に更新しました", version, SCHEMA_VERSION);
+    }
+    println!("✅ 復元完了: {}", filename);
+
+    Ok(())
+}
+
+pub fn cmd_doctor(conn: &Connection, repair: bool) -> Result<()> {
+    println!("🩺 データベースを診断中...\n");
+
+    let report = doctor::diagnose(conn)?;
+
+    if report.integrity_errors.is_empty() {
+        println!("✅ integrity_check: ok");
+    } else {
+        println!("❌ integrity_check: {}件のエラー", report.integrity_errors.len());
+        for msg in &report.integrity_errors {
+            println!("   {}", msg);
+        }
+    }
+
+    if report.orphaned_history == 0 {
+        println!("✅ 孤立した価格履歴: なし");
+    } else {
+        println!("⚠️  孤立した価格履歴: {}行（商品が存在しない）", report.orphaned_history);
+    }
+
+    if report.products_without_history.is_empty() {
+        println!("✅ 価格履歴のない商品: なし");
+    } else {
+        println!("⚠️  価格履歴のない商品: {}件", report.products_without_history.len());
+        for (id, name) in &report.products_without_history {
+            println!("   [ID:{}] {}", id, name);
+        }
+    }
+
+    if report.is_healthy() {
+        println!("\n🎉 問題は見つかりませんでした");
+        return Ok(());
+    }
+
+    if !repair {
+        println!("\n修復するには: doctor --repair");
+        return Ok(());
+    }
+
+    doctor::repair(conn, &report)?;
+
+    let after = doctor::diagnose(conn)?;
+    if after.is_healthy() {
+        println!("\n🔧 修復しました");
+    } else {
+        println!("\n⚠️  修復できない問題が残っています。バックアップからの復元を検討してください（restore <file>）");
+    }
+
+    Ok(())
+}
+
+/// ルールの通知先の表示
+fn describe_channels(channels: &[i64]) -> String {
+    if channels.is_empty() {
+        return "全ての通知先".to_string();
+    }
+    channels.iter().map(|id| format!("#{}", id)).collect::<Vec<_>>().join(", ")
+}
+
+pub fn cmd_rules_add(
+    conn: &Connection,
+    product_id: i64,
+    kind: RuleKind,
+    threshold: Option<f64>,
+    expression: Option<&str>,
+    options: alerts::RuleOptions,
+    channels: &[i64],
+) -> Result<()> {
+    let rule_id = alerts::add_rule(conn, product_id, kind, threshold, expression, options, channels)?;
+    println!("✅ アラートルールを追加しました (ルールID: {})", rule_id);
+    println!("   商品ID {}: {}", product_id, kind.describe(threshold, expression));
+    println!("   発火: {}", describe_firing(options.rearm, options.cooldown_minutes));
+    println!("   通知先: {}", describe_channels(channels));
+    Ok(())
+}
+
+/// ルールの発火条件（再アーム・クールダウン）の表示
+fn describe_firing(rearm: bool, cooldown_minutes: Option<i64>) -> String {
+    let mut s = if rearm {
+        "条件を外れるまで1回だけ".to_string()
+    } else {
+        "条件を満たすたびに毎回".to_string()
+    };
+    if let Some(minutes) = cooldown_minutes {
+        s.push_str(&format!("、{}分間は再発火しない", minutes));
+    }
+    s
+}
+
+pub fn cmd_rules_channels(conn: &Connection, rule_id: i64, channels: &[i64]) -> Result<()> {
+    if !alerts::set_rule_channels(conn, rule_id, channels)? {
+        anyhow::bail!("アラートルールが見つかりません: {}", rule_id);
+    }
+    println!("✅ 通知先を設定しました (ルールID: {})", rule_id);
+    println!("   通知先: {}", describe_channels(channels));
+    Ok(())
+}
+
+pub fn cmd_rules_list(conn: &Connection, product_id: Option<i64>) -> Result<()> {
+    println!("\n🔔 アラートルール:");
+    println!("{}", "=".repeat(80));
+
+    for rule in alerts::list_rules(conn, product_id)? {
+        println!(
+            "[ルール:{}] [ID:{}] {}{}",
+            rule.id,
+            rule.product_id,
+            rule.product_name,
+            if rule.enabled { "" } else { "（無効）" }
+        );
+        println!("   {}", rule.kind.describe(rule.threshold, rule.expression.as_deref()));
+        println!(
+            "   発火: {}{}",
+            describe_firing(rule.rearm, rule.cooldown_minutes),
+            if rule.rearm && !rule.armed { "（発火済み: 条件を外れると再アーム）" } else { "" }
+        );
+        println!("   通知先: {}", describe_channels(&rule.channels));
+    }
+
+    Ok(())
+}
+
+/// `rules test` で試すルール
+struct RuleUnderTest {
+    label: String,
+    kind: RuleKind,
+    threshold: Option<f64>,
+    expression: Option<String>,
+    options: alerts::RuleOptions,
+}
+
+/// アラートルールを保存済みの価格履歴に当てはめて、いつ発火していたかを表示
+pub fn cmd_rules_test(
+    conn: &Connection,
+    product_id: i64,
+    expression: Option<&str>,
+    rule_id: Option<i64>,
+) -> Result<()> {
+    let (name, seller): (String, Option<String>) = conn
+        .query_row(
+            "SELECT COALESCE(display_name, name), seller FROM products WHERE id = ?1",
+            [product_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| anyhow::anyhow!("商品が見つかりません: {}", product_id))?;
+
+    // 条件式を直接指定したときは、デフォルトの発火設定で試す
+    let rules: Vec<RuleUnderTest> = match expression {
+        Some(src) => vec![RuleUnderTest {
+            label: "条件式".to_string(),
+            kind: RuleKind::Expression,
+            threshold: None,
+            expression: Some(src.trim().to_string()),
+            options: alerts::RuleOptions::default(),
+        }],
+        None => {
+            let rules: Vec<_> = alerts::list_rules(conn, Some(product_id))?
+                .into_iter()
+                .filter(|rule| rule_id.is_none_or(|id| rule.id == id))
+                .map(|rule| RuleUnderTest {
+                    label: format!("ルール:{}", rule.id),
+                    kind: rule.kind,
+                    threshold: rule.threshold,
+                    expression: rule.expression,
+                    options: alerts::RuleOptions {
+                        cooldown_minutes: rule.cooldown_minutes,
+                        rearm: rule.rearm,
+                    },
+                })
+                .collect();
+            if rules.is_empty() {
+                match rule_id {
+                    Some(id) => anyhow::bail!("商品 {} のアラートルールが見つかりません: {}", product_id, id),
+                    None => anyhow::bail!("商品 {} にはアラートルールがありません（--expr で条件式を試せます）", product_id),
+                }
+            }
+            rules
+        }
+    };
+
+    println!("\n🧪 アラートルールのテスト: [ID:{}] {}", product_id, name);
+    println!("   出品者: {}", seller.as_deref().unwrap_or("-"));
+    println!("   ※ 在庫の履歴は残っていないため、各時点で在庫があったものとして評価します");
+    println!("{}", "=".repeat(80));
+
+    for RuleUnderTest { label, kind, threshold, expression, options } in rules {
+        let points = alerts::replay(conn, product_id, kind, threshold, expression.as_deref(), options)?;
+
+        println!("\n[{}] {}", label, kind.describe(threshold, expression.as_deref()));
+        println!("   発火: {}", describe_firing(options.rearm, options.cooldown_minutes));
+
+        let mut matched = 0;
+        let mut fired = 0;
+        for point in &points {
+            let Some(message) = &point.message else {
+                continue;
+            };
+            matched += 1;
+            if point.fired {
+                fired += 1;
+                println!("   {}  🔔 {}", timestamp::display(&point.checked_at), message);
+            } else {
+                println!("   {}  🔕 ¥{}（条件を満たすが、発火済みのため通知しない）", timestamp::display(&point.checked_at), point.price);
+            }
+        }
+        println!(
+            "   → 価格履歴 {}件中、条件を満たしたのは {}件、発火は {}回",
+            points.len(),
+            matched,
+            fired
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn cmd_edit(conn: &Connection, product_id: i64, edit: ProductEdit) -> Result<()> {
+    let result = edit_product(conn, product_id, edit).await;
+    if let Err(e) = result {
+        match e.kind {
+            EditErrorKind::NotFound => anyhow::bail!("商品が見つかりません: ID {}", product_id),
+            EditErrorKind::Invalid if e.field.is_none() => {
+                anyhow::bail!("変更する項目を指定してください（--name, --url, --target など）")
+            }
+            _ => anyhow::bail!("変更できませんでした: {}", e),
+        }
+    }
+
+    let (name, url, enabled): (String, String, bool) = conn.query_row(
+        "SELECT COALESCE(display_name, name), url, disabled_at IS NULL FROM products WHERE id = ?1",
+        [product_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    println!("🔧 商品を変更しました (ID: {}): {}", product_id, name);
+    println!("   URL: {}", url);
+    if !enabled {
+        println!("   ⏸️ 価格チェックは止まっています（再開するには: edit {} --enable）", product_id);
+    }
+    Ok(())
+}
+
+/// URLを変える場合は新しいURLの商品ページを取得してから保存する
+async fn edit_product(conn: &Connection, product_id: i64, edit: ProductEdit) -> Result<(), edit::EditError> {
+    let edit = edit.validate()?;
+    let new_url = match edit.url() {
+        Some(url) => edit::check_url(conn, product_id, url)?,
+        None => None,
+    };
+    let page = match new_url {
+        Some(url) => {
+            println!("🌐 新しいURLを確認中: {}", url);
+            Some(edit::fetch_url(&url).await?)
+        }
+        None => None,
+    };
+    edit::apply(conn, product_id, &edit, page.as_ref())
+}
+
+pub fn cmd_tag_add(conn: &Connection, product_id: i64, new_tags: &[String]) -> Result<()> {
+    if !tags::add_tags(conn, product_id, new_tags)? {
+        anyhow::bail!("商品が見つかりません: {}", product_id);
+    }
+    println!("🏷️  タグをつけました (ID: {}): {}", product_id, tags::product_tags(conn, product_id)?.join(", "));
+    Ok(())
+}
+
+pub fn cmd_tag_remove(conn: &Connection, product_id: i64, old_tags: &[String]) -> Result<()> {
+    if !tags::remove_tags(conn, product_id, old_tags)? {
+        anyhow::bail!("商品が見つかりません: {}", product_id);
+    }
+    let remaining = tags::product_tags(conn, product_id)?;
+    if remaining.is_empty() {
+        println!("🏷️  タグを外しました (ID: {}): タグなし", product_id);
+    } else {
+        println!("🏷️  タグを外しました (ID: {}): {}", product_id, remaining.join(", "));
+    }
+    Ok(())
+}
+
+pub fn cmd_tag_list(conn: &Connection) -> Result<()> {
+    let all = tags::list_tags(conn)?;
+    if all.is_empty() {
+        println!("🏷️  タグはまだありません（tag add <商品ID> <タグ> でつけられます）");
+        return Ok(());
+    }
+
+    println!("\n🏷️  タグ一覧:");
+    println!("{}", "=".repeat(80));
+    for tag in all {
+        println!("{}（{}件）", tag.name, tag.products);
+    }
+
+    Ok(())
+}
+
+pub fn cmd_rules_remove(conn: &Connection, rule_id: i64) -> Result<()> {
+    if !alerts::remove_rule(conn, rule_id)? {
+        anyhow::bail!("アラートルールが見つかりません: {}", rule_id);
+    }
+    println!("🗑️  アラートルールを削除しました (ルールID: {})", rule_id);
+    Ok(())
+}
+
+pub fn cmd_alerts(conn: &Connection, product_id: Option<i64>, limit: usize) -> Result<()> {
+    println!("\n🔔 アラート履歴:");
+    println!("{}", "=".repeat(80));
+
+    for event in alerts::list_events(conn, product_id, limit)? {
+        println!(
+            "{} [ID:{}] {}",
+            timestamp::display(&event.triggered_at),
+            event.product_id,
+            event.product_name
+        );
+        println!("   {}", event.message);
+    }
+
+    Ok(())
+}
+
+pub fn cmd_notify_add(
+    conn: &Connection,
+    kind: ChannelKind,
+    target: &str,
+    secret: Option<&str>,
+    digest: bool,
+) -> Result<()> {
+    let channel_id = notify::add_channel(conn, kind, target, secret, digest)?;
+    println!("✅ 通知先を追加しました (通知先ID: {})", channel_id);
+    if kind == ChannelKind::Line {
+        println!("   line: LINE Notify（トークンは通知先一覧に表示されません）");
+    } else {
+        println!("   {}: {}", kind.as_str(), target);
+    }
+    if secret.is_some() {
+        println!("   本文は HMAC-SHA256 で署名されます（{} ヘッダー）", notify::SIGNATURE_HEADER);
+    }
+    if digest {
+        println!("   毎朝8時に日次レポートを送ります");
+    }
+    Ok(())
+}
+
+pub fn cmd_notify_list(conn: &Connection) -> Result<()> {
+    println!("\n📣 通知先:");
+    println!("{}", "=".repeat(80));
+
+    for channel in notify::list_channels(conn)? {
+        println!(
+            "[通知先:{}] {} {}{}{}{}",
+            channel.id,
+            channel.kind.as_str(),
+            channel.target,
+            if channel.secret.is_some() { " 🔑" } else { "" },
+            if channel.digest { " 📊" } else { "" },
+            if channel.enabled { "" } else { "（無効）" }
+        );
+    }
+
+    Ok(())
+}
+
+pub fn cmd_notify_remove(conn: &Connection, channel_id: i64) -> Result<()> {
+    if !notify::remove_channel(conn, channel_id)? {
+        anyhow::bail!("通知先が見つかりません: {}", channel_id);
+    }
+    println!("🗑️  通知先を削除しました (通知先ID: {})", channel_id);
+    Ok(())
+}
+
+pub async fn cmd_notify_test(conn: &Connection, channel_id: i64) -> Result<()> {
+    let channels: Vec<_> = notify::list_channels(conn)?
+        .into_iter()
+        .filter(|c| c.id == channel_id)
+        .collect();
+    if channels.is_empty() {
+        anyhow::bail!("通知先が見つかりません: {}", channel_id);
+    }
+
+    println!("📣 テスト通知を送信中: {}", channels[0].target);
+
+    for d in notify::deliver(&channels, &[notify::sample_alert()]).await {
+        match (&d.error, d.http_status) {
+            (None, Some(status)) => println!("  ✅ {}回目: 送信成功 (HTTP {})", d.attempt, status),
+            (None, None) => println!("  ✅ {}回目: 送信成功", d.attempt),
+            (Some(error), _) => println!("  ❌ {}回目: {}", d.attempt, error),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn cmd_notify_digest(conn: &Connection, hours: i64, send: bool) -> Result<()> {
+    let digest = digest::build(conn, hours)?;
+
+    println!("\n{}", digest.subject());
+    println!("{}", "=".repeat(80));
+    println!("{}", digest.to_text());
+
+    if !send {
+        return Ok(());
+    }
+
+    let channels = notify::list_channels(conn)?;
+    let results = notify::deliver_digest(&channels, &digest).await;
+    if results.is_empty() {
+        println!("⚠️  日次レポートを受け取る通知先がありません（notify add email <宛先> --digest）");
+    }
+    for (channel_id, result) in results {
+        match result {
+            Ok(()) => println!("✅ 通知先 {} に送信しました", channel_id),
+            Err(e) => println!("❌ 通知先 {}: {}", channel_id, e),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn cmd_notify_log(conn: &Connection, limit: usize) -> Result<()> {
+    println!("\n📣 配信ログ:");
+    println!("{}", "=".repeat(80));
+
+    for d in notify::list_deliveries(conn, limit)? {
+        let result = match (&d.error, d.http_status) {
+            (None, Some(status)) => format!("✅ HTTP {}", status),
+            (None, None) => "✅ 送信成功".to_string(),
+            (Some(error), _) => format!("❌ {}", error),
+        };
+        println!(
+            "{} アラート #{} → 通知先 {} ({}回目)  {}",
+            timestamp::display(&d.attempted_at),
+            d.alert_event_id,
+            d.channel_id,
+            d.attempt,
+            result
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,528 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono_tz::Tz;
+use lettre::message::Mailbox;
+use std::fmt;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::cli::{Cli, Commands};
+use crate::db;
+use crate::mailer::{self, SmtpConfig, SmtpTls};
+use crate::notify::{self, DeliverySettings, QuietHours};
+use crate::retention;
+use crate::scheduler::{self, CheckInterval, CheckSchedule};
+use crate::scraper::{self, HttpSettings, RateLimit};
+use crate::timestamp;
+use crate::web::{self, BasePath, ServerOptions};
+
+// 設定は デフォルト < 設定ファイル（config.toml） < 環境変数 < コマンドライン引数 の順に上書きする
+
+/// 設定ファイルの名前
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// ユーザーの設定ディレクトリ・/etc の下で設定ファイルを置くディレクトリの名前
+const APP_DIR: &str = "rust-price-monitor";
+
+/// 設定の項目
+struct Key {
+    /// 設定ファイルでのキー（"セクション.名前"。セクションなしもある）
+    name: &'static str,
+    /// 上書きする環境変数
+    env: &'static str,
+    /// デフォルト値（None なら未設定）
+    default: Option<String>,
+    /// 設定ファイルでは数値で書く
+    number: bool,
+    /// `config show` で値を伏せる
+    secret: bool,
+}
+
+impl Key {
+    fn new(name: &'static str, env: &'static str, default: Option<String>) -> Self {
+        Self { name, env, default, number: false, secret: false }
+    }
+
+    fn number(name: &'static str, env: &'static str, default: impl ToString) -> Self {
+        Self { number: true, ..Self::new(name, env, Some(default.to_string())) }
+    }
+}
+
+/// 全ての設定の項目（`config show` ではこの順に表示する）
+fn keys() -> Vec<Key> {
+    vec![
+        Key::new("timezone", "PRICE_MONITOR_TZ", Some(timestamp::DEFAULT_TIMEZONE.to_string())),
+        Key::new("database.path", "PRICE_MONITOR_DB_PATH", Some(db::DEFAULT_DB_PATH.to_string())),
+        Key::new("schedule.check", "PRICE_MONITOR_CHECK_SCHEDULE", Some(scheduler::DEFAULT_CHECK_SCHEDULE.to_string())),
+        Key::new("schedule.min_interval", "PRICE_MONITOR_MIN_CHECK_INTERVAL", Some(scheduler::DEFAULT_MIN_CHECK_INTERVAL.to_string())),
+        Key::new("schedule.max_interval", "PRICE_MONITOR_MAX_CHECK_INTERVAL", Some(scheduler::DEFAULT_MAX_CHECK_INTERVAL.to_string())),
+        Key::number("http.timeout_secs", "PRICE_MONITOR_HTTP_TIMEOUT_SECS", scraper::DEFAULT_TIMEOUT_SECS),
+        Key::new("http.user_agent", "PRICE_MONITOR_HTTP_USER_AGENT", Some(scraper::DEFAULT_USER_AGENT.to_string())),
+        Key::number("rate_limit.request_delay_ms", "PRICE_MONITOR_REQUEST_DELAY_MS", scraper::DEFAULT_REQUEST_DELAY_MS),
+        Key::number("rate_limit.max_concurrent", "PRICE_MONITOR_MAX_CONCURRENT", scraper::DEFAULT_MAX_CONCURRENT),
+        Key::number("retention.days", "PRICE_MONITOR_RETENTION_DAYS", retention::DEFAULT_RETENTION_DAYS),
+        Key::new("notify.quiet_hours", "PRICE_MONITOR_QUIET_HOURS", None),
+        Key::number("notify.max_attempts", "PRICE_MONITOR_NOTIFY_MAX_ATTEMPTS", notify::DEFAULT_MAX_ATTEMPTS),
+        Key::number("notify.timeout_secs", "PRICE_MONITOR_NOTIFY_TIMEOUT_SECS", notify::DEFAULT_TIMEOUT_SECS),
+        Key::new("smtp.host", "PRICE_MONITOR_SMTP_HOST", None),
+        Key { number: true, ..Key::new("smtp.port", "PRICE_MONITOR_SMTP_PORT", None) },
+        Key::new("smtp.tls", "PRICE_MONITOR_SMTP_TLS", Some("starttls".to_string())),
+        Key::new("smtp.username", "PRICE_MONITOR_SMTP_USERNAME", None),
+        Key { secret: true, ..Key::new("smtp.password", "PRICE_MONITOR_SMTP_PASSWORD", None) },
+        Key::new("smtp.from", "PRICE_MONITOR_SMTP_FROM", Some(mailer::DEFAULT_FROM.to_string())),
+        Key::new("server.host", "PRICE_MONITOR_HOST", Some(web::DEFAULT_HOST.to_string())),
+        Key::number("server.port", "PRICE_MONITOR_PORT", web::DEFAULT_PORT),
+        Key::new("server.base_path", "PRICE_MONITOR_BASE_PATH", Some("/".to_string())),
+        Key::new("server.static_dir", "PRICE_MONITOR_STATIC_DIR", None),
+        Key::new("server.metrics", "PRICE_MONITOR_METRICS_ADDR", None),
+    ]
+}
+
+/// 設定の値の出どころ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Default,
+    File,
+    Env(&'static str),
+    Arg(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "デフォルト"),
+            Source::File => write!(f, "設定ファイル"),
+            Source::Env(name) => write!(f, "環境変数 {}", name),
+            Source::Arg(flag) => write!(f, "{}", flag),
+        }
+    }
+}
+
+/// 1つの設定の値（空文字列は未設定として扱う）
+struct Entry {
+    key: Key,
+    value: Option<String>,
+    source: Source,
+}
+
+/// 有効な設定
+pub struct Config {
+    /// 読み込んだ設定ファイル
+    file: Option<PathBuf>,
+    entries: Vec<Entry>,
+    pub timezone: Tz,
+    pub db_path: PathBuf,
+    pub check_schedule: CheckSchedule,
+    pub min_check_interval: CheckInterval,
+    pub max_check_interval: CheckInterval,
+    pub http: HttpSettings,
+    pub rate_limit: RateLimit,
+    /// 生の価格履歴を保持する日数
+    pub retention_days: i64,
+    pub quiet_hours: Option<QuietHours>,
+    pub delivery: DeliverySettings,
+    pub smtp: Option<SmtpConfig>,
+    pub server: ServerOptions,
+    /// daemon のメトリクスのアドレス
+    pub metrics: Option<SocketAddr>,
+}
+
+/// 設定ファイルを探す場所（見つかった最初のものを使う）
+fn search_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(CONFIG_FILE)];
+    if let Some(dir) = dirs::config_dir() {
+        paths.push(dir.join(APP_DIR).join(CONFIG_FILE));
+    }
+    if cfg!(unix) {
+        paths.push(Path::new("/etc").join(APP_DIR).join(CONFIG_FILE));
+    }
+    paths
+}
+
+/// 設定ファイルを読み込み、"セクション.名前" ごとの値にする
+fn read_file(path: &Path, errors: &mut Vec<String>) -> Result<Vec<(String, toml::Value)>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("設定ファイル {} を読み込めません", path.display()))?;
+    let table: toml::Table = text
+        .parse()
+        .map_err(|e| anyhow!("設定ファイル {} の書式が正しくありません: {}", path.display(), e))?;
+
+    let mut values = Vec::new();
+    for (name, value) in table {
+        match value {
+            toml::Value::Table(section) => {
+                for (key, value) in section {
+                    if value.is_table() {
+                        errors.push(format!("{}.{}.*: 不明なセクションです", name, key));
+                    } else {
+                        values.push((format!("{}.{}", name, key), value));
+                    }
+                }
+            }
+            value => values.push((name, value)),
+        }
+    }
+    Ok(values)
+}
+
+/// 設定ファイルの値を文字列にする（環境変数・引数と同じように検証するため）
+fn value_to_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// コマンドライン引数で指定した設定（キー, 引数名, 値）
+fn arg_overrides(cli: &Cli) -> Vec<(&'static str, &'static str, Option<&str>)> {
+    let metrics = match &cli.command {
+        Some(Commands::Daemon { metrics }) => metrics.as_deref(),
+        _ => None,
+    };
+    let retention_days = match &cli.command {
+        Some(Commands::Prune { days }) => days.as_deref(),
+        _ => None,
+    };
+    vec![
+        ("timezone", "--tz", cli.tz.as_deref()),
+        ("schedule.check", "--check-schedule", cli.check_schedule.as_deref()),
+        ("schedule.min_interval", "--min-check-interval", cli.min_check_interval.as_deref()),
+        ("schedule.max_interval", "--max-check-interval", cli.max_check_interval.as_deref()),
+        ("retention.days", "--days", retention_days),
+        ("notify.quiet_hours", "--quiet-hours", cli.quiet_hours.as_deref()),
+        ("server.host", "--host", cli.host.as_deref()),
+        ("server.port", "--port", cli.port.as_deref()),
+        ("server.base_path", "--base-path", cli.base_path.as_deref()),
+        ("server.static_dir", "--static-dir", cli.static_dir.as_deref()),
+        ("server.metrics", "--metrics", metrics),
+    ]
+}
+
+/// 値を検証しながら取り出す（誤りはまとめて報告するために `errors` に溜める）
+struct Parser<'a> {
+    entries: &'a [Entry],
+    errors: Vec<String>,
+}
+
+impl Parser<'_> {
+    fn entry(&self, name: &str) -> &Entry {
+        self.entries
+            .iter()
+            .find(|e| e.key.name == name)
+            .unwrap_or_else(|| panic!("unknown config key: {}", name))
+    }
+
+    fn error(&mut self, name: &str, message: impl fmt::Display) {
+        let entry = self.entry(name);
+        let message = format!("{}（{}）: {}", name, entry.source, message);
+        self.errors.push(message);
+    }
+
+    /// 未設定なら None
+    fn optional<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let value = self.entry(name).value.clone()?;
+        match value.parse() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.error(name, e);
+                None
+            }
+        }
+    }
+
+    /// デフォルト値がある項目（誤りがあっても仮の値を返し、誤りは最後にまとめて報告する）
+    fn required<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        if self.entry(name).value.is_none() {
+            self.error(name, "値を指定してください");
+            return None;
+        }
+        self.optional(name)
+    }
+
+    /// 1以上の整数
+    fn positive<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr + Default + PartialOrd,
+        T::Err: fmt::Display,
+    {
+        let value: T = self.required(name)?;
+        if value <= T::default() {
+            self.error(name, "1以上を指定してください");
+            return None;
+        }
+        Some(value)
+    }
+}
+
+impl Config {
+    /// 設定ファイル・環境変数・コマンドライン引数から設定を読み込む
+    ///
+    /// 誤りのある項目は1つずつではなく、まとめてエラーにする。
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let mut errors = Vec::new();
+
+        let file = match &cli.config {
+            Some(path) => Some(path.clone()),
+            None => search_paths().into_iter().find(|path| path.is_file()),
+        };
+        let file_values = match &file {
+            Some(path) => read_file(path, &mut errors)?,
+            None => Vec::new(),
+        };
+
+        let keys = keys();
+        for (name, _) in &file_values {
+            if !keys.iter().any(|k| k.name == name) {
+                errors.push(format!("{}: 不明な設定です", name));
+            }
+        }
+
+        let args = arg_overrides(cli);
+        let mut entries = Vec::new();
+        for key in keys {
+            let mut value = key.default.clone();
+            let mut source = Source::Default;
+
+            if let Some((_, file_value)) = file_values.iter().find(|(name, _)| name == key.name) {
+                match value_to_string(file_value) {
+                    Some(v) => {
+                        value = Some(v);
+                        source = Source::File;
+                    }
+                    None => errors.push(format!("{}（設定ファイル）: 文字列か数値で指定してください", key.name)),
+                }
+            }
+            if let Ok(v) = std::env::var(key.env) {
+                value = Some(v);
+                source = Source::Env(key.env);
+            }
+            if let Some((_, flag, Some(v))) = args.iter().find(|(name, _, _)| *name == key.name) {
+                value = Some(v.to_string());
+                source = Source::Arg(flag);
+            }
+
+            let value = value.filter(|v| !v.trim().is_empty());
+            entries.push(Entry { key, value, source });
+        }
+
+        let mut p = Parser { entries: &entries, errors };
+
+        let timezone = p.required("timezone");
+        let db_path = p.required::<PathBuf>("database.path");
+        let check_schedule = p.required("schedule.check");
+        let min_check_interval: Option<CheckInterval> = p.required("schedule.min_interval");
+        let max_check_interval: Option<CheckInterval> = p.required("schedule.max_interval");
+        if let (Some(min), Some(max)) = (min_check_interval, max_check_interval)
+            && min.minutes > max.minutes
+        {
+            p.error("schedule.min_interval", format!("schedule.max_interval（{}）より長くできません", max));
+        }
+
+        let timeout_secs = p.positive::<u64>("http.timeout_secs");
+        let user_agent = p.required::<String>("http.user_agent");
+        let request_delay_ms = p.required::<u64>("rate_limit.request_delay_ms");
+        let max_concurrent = p.positive::<usize>("rate_limit.max_concurrent");
+        let retention_days = p.positive::<i64>("retention.days");
+
+        let quiet_hours = p.optional("notify.quiet_hours");
+        let max_attempts = p.positive::<u32>("notify.max_attempts");
+        let notify_timeout_secs = p.positive::<u64>("notify.timeout_secs");
+
+        let smtp_host = p.optional::<String>("smtp.host");
+        let smtp_port = p.optional::<u16>("smtp.port");
+        let smtp_tls = p.required::<SmtpTls>("smtp.tls");
+        let smtp_username = p.optional::<String>("smtp.username");
+        let smtp_password = p.optional::<String>("smtp.password");
+        let smtp_from = p.required::<Mailbox>("smtp.from");
+
+        let host = p.required::<String>("server.host");
+        let port = p.required::<u16>("server.port");
+        let base_path = p.required::<BasePath>("server.base_path");
+        let static_dir = p.optional::<PathBuf>("server.static_dir");
+        let metrics = p.optional("server.metrics");
+
+        if !p.errors.is_empty() {
+            let mut message = String::from("設定に誤りがあります:");
+            for error in &p.errors {
+                let _ = write!(message, "\n  - {}", error);
+            }
+            if let Some(path) = &file {
+                let _ = write!(message, "\n（設定ファイル: {}）", path.display());
+            }
+            bail!(message);
+        }
+
+        // ここまでで誤りがなければ、全ての必須の値が揃っている
+        let missing = || anyhow!("config value missing after validation");
+        let smtp_tls = smtp_tls.ok_or_else(missing)?;
+        let smtp = match smtp_host {
+            Some(host) => Some(SmtpConfig {
+                host,
+                port: smtp_port.unwrap_or_else(|| smtp_tls.default_port()),
+                tls: smtp_tls,
+                credentials: smtp_username.map(|user| (user, smtp_password.unwrap_or_default())),
+                from: smtp_from.ok_or_else(missing)?,
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            timezone: timezone.ok_or_else(missing)?,
+            db_path: db_path.ok_or_else(missing)?,
+            check_schedule: check_schedule.ok_or_else(missing)?,
+            min_check_interval: min_check_interval.ok_or_else(missing)?,
+            max_check_interval: max_check_interval.ok_or_else(missing)?,
+            http: HttpSettings {
+                timeout: Duration::from_secs(timeout_secs.ok_or_else(missing)?),
+                user_agent: user_agent.ok_or_else(missing)?,
+            },
+            rate_limit: RateLimit {
+                request_delay: Duration::from_millis(request_delay_ms.ok_or_else(missing)?),
+                max_concurrent: max_concurrent.ok_or_else(missing)?,
+            },
+            retention_days: retention_days.ok_or_else(missing)?,
+            quiet_hours,
+            delivery: DeliverySettings {
+                max_attempts: max_attempts.ok_or_else(missing)?,
+                timeout: Duration::from_secs(notify_timeout_secs.ok_or_else(missing)?),
+            },
+            smtp,
+            server: ServerOptions {
+                host: host.ok_or_else(missing)?,
+                port: port.ok_or_else(missing)?,
+                base_path: base_path.ok_or_else(missing)?,
+                static_dir,
+            },
+            metrics,
+            file,
+            entries,
+        })
+    }
+
+    /// 設定を各モジュールに反映する（起動時に1回だけ）
+    pub fn apply(&self) -> Result<()> {
+        timestamp::set_timezone(self.timezone);
+        db::set_db_path(self.db_path.clone());
+        scheduler::set_check_schedule(self.check_schedule.clone());
+        scheduler::set_adaptive_bounds(self.min_check_interval, self.max_check_interval)?;
+        scraper::set_http_settings(self.http.clone(), self.rate_limit);
+        retention::set_retention_days(self.retention_days);
+        notify::set_quiet_hours(self.quiet_hours);
+        notify::set_delivery_settings(self.delivery);
+        mailer::set_smtp_config(self.smtp.clone());
+        Ok(())
+    }
+
+    /// 有効な設定を、設定ファイルにそのまま書ける形式で表示する（値の出どころをコメントで添える）
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        match &self.file {
+            Some(path) => {
+                let _ = writeln!(out, "# 設定ファイル: {}", path.display());
+            }
+            None => {
+                let _ = writeln!(out, "# 設定ファイル: なし（探した場所: {}）", search_paths()
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "));
+            }
+        }
+
+        let mut section = "";
+        for entry in &self.entries {
+            let (entry_section, name) = entry.key.name.split_once('.').unwrap_or(("", entry.key.name));
+            if entry_section != section {
+                section = entry_section;
+                let _ = writeln!(out, "\n[{}]", section);
+            }
+
+            let line = match &entry.value {
+                Some(_) if entry.key.secret => format!("{} = \"********\"", name),
+                Some(value) if entry.key.number && value.parse::<i64>().is_ok() => format!("{} = {}", name, value),
+                Some(value) => format!("{} = {}", name, toml::Value::String(value.clone())),
+                None => format!("# {} =", name),
+            };
+            let note = match (&entry.value, entry.source) {
+                (None, Source::Default) => format!("未設定（環境変数 {}）", entry.key.env),
+                (None, source) => format!("未設定（{} で空にしています）", source),
+                (Some(_), Source::Default) => format!("デフォルト（環境変数 {}）", entry.key.env),
+                (Some(_), source) => source.to_string(),
+            };
+            let _ = writeln!(out, "{:<40} # {}", line, note);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser as _;
+
+    fn load(name: &str, toml: &str, args: &[&str]) -> Result<Config> {
+        let path = std::env::temp_dir().join(format!("price-monitor-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, toml).unwrap();
+        let mut argv = vec!["rust-price-monitor", "--config", path.to_str().unwrap()];
+        argv.extend_from_slice(args);
+        let config = Config::load(&Cli::parse_from(argv));
+        std::fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn file_values_are_overridden_by_args() {
+        let config = load(
+            "override",
+            "[server]\nport = 8080\nhost = \"0.0.0.0\"\n[rate_limit]\nmax_concurrent = 2\n",
+            &["--port", "9000", "list"],
+        )
+        .unwrap();
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.rate_limit.max_concurrent, 2);
+        assert!(config.smtp.is_none());
+    }
+
+    #[test]
+    fn retention_days_must_be_positive_and_can_be_set_per_prune() {
+        let config = load("retention", "[retention]\ndays = 30\n", &["list"]).unwrap();
+        assert_eq!(config.retention_days, 30);
+        let config = load("retention-arg", "[retention]\ndays = 30\n", &["prune", "--days", "7"]).unwrap();
+        assert_eq!(config.retention_days, 7);
+
+        let Err(err) = load("retention-zero", "[retention]\ndays = 0\n", &["list"]) else {
+            panic!("zero retention was accepted");
+        };
+        assert!(err.to_string().contains("retention.days（設定ファイル）: 1以上を指定してください"), "{}", err);
+    }
+
+    #[test]
+    fn all_bad_keys_are_reported_together() {
+        let Err(err) = load(
+            "errors",
+            "[schedule]\ncheck = \"bad\"\nunknown = 1\n[server]\nport = \"http\"\n",
+            &["list"],
+        ) else {
+            panic!("invalid config was accepted");
+        };
+        let err = err.to_string();
+        assert!(err.contains("schedule.check（設定ファイル）"), "{}", err);
+        assert!(err.contains("schedule.unknown: 不明な設定です"), "{}", err);
+        assert!(err.contains("server.port（設定ファイル）"), "{}", err);
+    }
+}
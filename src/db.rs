@@ -1,65 +1,711 @@
 use anyhow::Result;
-use rusqlite::Connection;
-use chrono::Utc;
+use rusqlite::{Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use crate::scraper::Product;
+use crate::timestamp;
+
+// スキーマのマイグレーション（PRAGMA user_version で適用済みバージョンを管理）
+// 既存のDBは user_version = 0 なので、v1 は IF NOT EXISTS で冪等にしておく
+const MIGRATIONS: &[&str] = &[
+    // v1: 初期スキーマ
+    "CREATE TABLE IF NOT EXISTS products (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        url TEXT NOT NULL UNIQUE,
+        name TEXT NOT NULL,
+        current_price INTEGER NOT NULL,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS price_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        product_id INTEGER NOT NULL,
+        price INTEGER NOT NULL,
+        checked_at TEXT NOT NULL,
+        FOREIGN KEY (product_id) REFERENCES products(id)
+    );",
+    // v2: 価格変化イベント（最終確認日時）と日次ダウンサンプリング
+    "ALTER TABLE price_history ADD COLUMN last_seen_at TEXT;
+    UPDATE price_history SET last_seen_at = checked_at;
+    CREATE TABLE price_history_daily (
+        product_id INTEGER NOT NULL,
+        day TEXT NOT NULL,
+        min_price INTEGER NOT NULL,
+        max_price INTEGER NOT NULL,
+        close_price INTEGER NOT NULL,
+        closed_at TEXT NOT NULL,
+        PRIMARY KEY (product_id, day),
+        FOREIGN KEY (product_id) REFERENCES products(id)
+    );",
+    // v3: 日時を 'YYYY-MM-DD HH:MM:SS'（UTC）から RFC3339（UTC）に変換し、範囲検索用の索引を追加
+    "UPDATE products SET created_at = replace(created_at, ' ', 'T') || 'Z'
+        WHERE created_at NOT LIKE '%T%';
+    UPDATE price_history SET checked_at = replace(checked_at, ' ', 'T') || 'Z'
+        WHERE checked_at NOT LIKE '%T%';
+    UPDATE price_history SET last_seen_at = replace(last_seen_at, ' ', 'T') || 'Z'
+        WHERE last_seen_at NOT LIKE '%T%';
+    UPDATE price_history_daily SET closed_at = replace(closed_at, ' ', 'T') || 'Z'
+        WHERE closed_at NOT LIKE '%T%';
+    CREATE INDEX idx_price_history_checked_at ON price_history(product_id, checked_at);
+    CREATE INDEX idx_price_history_last_seen_at
+        ON price_history(product_id, COALESCE(last_seen_at, checked_at));
+    CREATE INDEX idx_price_history_daily_closed_at ON price_history_daily(product_id, closed_at);",
+    // v4: 商品のアーカイブ（論理削除）と、履歴テーブルの外部キーに ON DELETE CASCADE を追加
+    // SQLite は外部キーを ALTER できないので、テーブルを作り直してコピーする
+    "ALTER TABLE products ADD COLUMN archived_at TEXT;
+    CREATE TABLE price_history_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        product_id INTEGER NOT NULL,
+        price INTEGER NOT NULL,
+        checked_at TEXT NOT NULL,
+        last_seen_at TEXT,
+        FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE
+    );
+    INSERT INTO price_history_new (id, product_id, price, checked_at, last_seen_at)
+        SELECT id, product_id, price, checked_at, last_seen_at FROM price_history;
+    DROP TABLE price_history;
+    ALTER TABLE price_history_new RENAME TO price_history;
+    CREATE INDEX idx_price_history_checked_at ON price_history(product_id, checked_at);
+    CREATE INDEX idx_price_history_last_seen_at
+        ON price_history(product_id, COALESCE(last_seen_at, checked_at));
+    CREATE TABLE price_history_daily_new (
+        product_id INTEGER NOT NULL,
+        day TEXT NOT NULL,
+        min_price INTEGER NOT NULL,
+        max_price INTEGER NOT NULL,
+        close_price INTEGER NOT NULL,
+        closed_at TEXT NOT NULL,
+        PRIMARY KEY (product_id, day),
+        FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE
+    );
+    INSERT INTO price_history_daily_new SELECT * FROM price_history_daily;
+    DROP TABLE price_history_daily;
+    ALTER TABLE price_history_daily_new RENAME TO price_history_daily;
+    CREATE INDEX idx_price_history_daily_closed_at ON price_history_daily(product_id, closed_at);",
+    // v5: 価格チェックの実行記録（失敗も含めて商品ごとの試行を残す）
+    "CREATE TABLE check_runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        trigger_source TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        finished_at TEXT,
+        total INTEGER NOT NULL,
+        succeeded INTEGER NOT NULL DEFAULT 0,
+        failed INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX idx_check_runs_started_at ON check_runs(started_at);
+    CREATE TABLE check_attempts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        run_id INTEGER NOT NULL,
+        product_id INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        error_kind TEXT,
+        error_message TEXT,
+        http_status INTEGER,
+        latency_ms INTEGER NOT NULL,
+        price INTEGER,
+        attempted_at TEXT NOT NULL,
+        FOREIGN KEY (run_id) REFERENCES check_runs(id) ON DELETE CASCADE,
+        FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE
+    );
+    CREATE INDEX idx_check_attempts_run ON check_attempts(run_id);
+    CREATE INDEX idx_check_attempts_product ON check_attempts(product_id, attempted_at);",
+    // v6: 価格アラートのルールと発火履歴
+    "CREATE TABLE alert_rules (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        product_id INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        threshold REAL,
+        enabled INTEGER NOT NULL DEFAULT 1,
+        created_at TEXT NOT NULL,
+        FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE
+    );
+    CREATE INDEX idx_alert_rules_product ON alert_rules(product_id);
+    CREATE TABLE alert_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        rule_id INTEGER NOT NULL,
+        product_id INTEGER NOT NULL,
+        old_price INTEGER,
+        new_price INTEGER NOT NULL,
+        message TEXT NOT NULL,
+        triggered_at TEXT NOT NULL,
+        FOREIGN KEY (rule_id) REFERENCES alert_rules(id) ON DELETE CASCADE,
+        FOREIGN KEY (product_id) REFERENCES products(id) ON DELETE CASCADE
+    );
+    CREATE INDEX idx_alert_events_triggered_at ON alert_events(triggered_at);",
+    // v7: アラートの通知先と配信ログ
+    "CREATE TABLE notify_channels (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        kind TEXT NOT NULL,
+        target TEXT NOT NULL,
+        secret TEXT,
+        enabled INTEGER NOT NULL DEFAULT 1,
+        created_at TEXT NOT NULL
+    );
+    CREATE TABLE notify_deliveries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        alert_event_id INTEGER NOT NULL,
+        channel_id INTEGER NOT NULL,
+        attempt INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        http_status INTEGER,
+        error TEXT,
+        attempted_at TEXT NOT NULL,
+        FOREIGN KEY (alert_event_id) REFERENCES alert_events(id) ON DELETE CASCADE,
+        FOREIGN KEY (channel_id) REFERENCES notify_channels(id) ON DELETE CASCADE
+    );
+    CREATE INDEX idx_notify_deliveries_event ON notify_deliveries(alert_event_id);",
+    // v8: 通知に使う商品画像と、アラートルールごとの通知先（未設定なら全ての通知先）
+    "ALTER TABLE products ADD COLUMN image_url TEXT;
+    CREATE TABLE alert_rule_channels (
+        rule_id INTEGER NOT NULL,
+        channel_id INTEGER NOT NULL,
+        PRIMARY KEY (rule_id, channel_id),
+        FOREIGN KEY (rule_id) REFERENCES alert_rules(id) ON DELETE CASCADE,
+        FOREIGN KEY (channel_id) REFERENCES notify_channels(id) ON DELETE CASCADE
+    );",
+    // v9: メールの通知先に日次レポートを送るかどうか
+    "ALTER TABLE notify_channels ADD COLUMN digest INTEGER NOT NULL DEFAULT 0;",
+    // v10: 在庫状況（在庫切れの間は current_price に最後に取得できた価格を残す）
+    "ALTER TABLE products ADD COLUMN in_stock INTEGER NOT NULL DEFAULT 1;
+    ALTER TABLE products ADD COLUMN stock_changed_at TEXT;",
+    // v11: アラートのクールダウン・再アームと、静かな時間帯に溜めた通知
+    "ALTER TABLE alert_rules ADD COLUMN cooldown_minutes INTEGER;
+    ALTER TABLE alert_rules ADD COLUMN rearm INTEGER NOT NULL DEFAULT 1;
+    ALTER TABLE alert_rules ADD COLUMN armed INTEGER NOT NULL DEFAULT 1;
+    ALTER TABLE alert_events ADD COLUMN queued INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX idx_alert_events_queued ON alert_events(queued) WHERE queued = 1;
+    CREATE INDEX idx_alert_events_rule ON alert_events(rule_id, triggered_at);",
+    // v12: 条件式のアラートルールと、条件式から参照する出品者
+    "ALTER TABLE alert_rules ADD COLUMN expression TEXT;
+    ALTER TABLE products ADD COLUMN seller TEXT;",
+    // v13: 商品ごとのチェック間隔（NULL なら全体のスケジュールに従う）
+    "ALTER TABLE products ADD COLUMN check_interval_minutes INTEGER;",
+    // v14: 次にチェックする日時（スケジューラーが価格の変化の頻度から決める）
+    "ALTER TABLE products ADD COLUMN next_check_at TEXT;",
+    // v15: スケジューラーの一時停止と手動実行のリクエスト（CLI からサーバーを操作できるようにDBに置く）
+    "CREATE TABLE scheduler_state (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        paused_at TEXT,
+        run_requested_at TEXT
+    );
+    INSERT INTO scheduler_state (id) VALUES (1);",
+    // v16: 中断した価格チェックの記録と、チェックし終えていない商品（次の起動時に再開する）
+    "ALTER TABLE check_runs ADD COLUMN interrupted INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE products ADD COLUMN pending_run_id INTEGER;",
+    // v17: 連続で失敗した回数と「要確認」の状態（既存の商品は試行の記録から連続失敗の回数を数える）
+    "ALTER TABLE products ADD COLUMN consecutive_failures INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE products ADD COLUMN needs_attention_at TEXT;
+    UPDATE products SET consecutive_failures = (
+        SELECT COUNT(*) FROM check_attempts a
+        WHERE a.product_id = products.id AND a.status != 'ok'
+          AND a.attempted_at > COALESCE(
+              (SELECT MAX(b.attempted_at) FROM check_attempts b
+               WHERE b.product_id = products.id AND b.status = 'ok'), '')
+    );",
+    // v18: 停止・スリープ中に取りこぼしたチェックを後から実行したとき、データがない期間の始まり
+    "ALTER TABLE check_runs ADD COLUMN missed_since TEXT;",
+    // v19: 価格チェックの実行権（serve と daemon を別プロセスで動かしても、チェックが重ならないように）
+    "ALTER TABLE scheduler_state ADD COLUMN lock_owner TEXT;
+    ALTER TABLE scheduler_state ADD COLUMN lock_expires_at TEXT;",
+    // v20: 商品名の全文検索（日本語は単語で区切れないので trigram。商品の保存・削除はトリガーで反映）
+    "CREATE VIRTUAL TABLE products_fts USING fts5(
+        name, content='products', content_rowid='id', tokenize='trigram'
+    );
+    INSERT INTO products_fts (products_fts) VALUES ('rebuild');
+    CREATE TRIGGER products_fts_insert AFTER INSERT ON products BEGIN
+        INSERT INTO products_fts (rowid, name) VALUES (new.id, new.name);
+    END;
+    CREATE TRIGGER products_fts_delete AFTER DELETE ON products BEGIN
+        INSERT INTO products_fts (products_fts, rowid, name) VALUES ('delete', old.id, old.name);
+    END;
+    CREATE TRIGGER products_fts_update AFTER UPDATE OF name ON products BEGIN
+        INSERT INTO products_fts (products_fts, rowid, name) VALUES ('delete', old.id, old.name);
+        INSERT INTO products_fts (rowid, name) VALUES (new.id, new.name);
+    END;",
+    // v21: 商品のカテゴリー・メモと、タグ（1つの商品に複数、1つのタグを複数の商品に）
+    "ALTER TABLE products ADD COLUMN category TEXT;
+    ALTER TABLE products ADD COLUMN note TEXT;
+    CREATE TABLE tags (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE COLLATE NOCASE
+    );
+    CREATE TABLE product_tags (
+        product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+        tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY (product_id, tag_id)
+    );
+    CREATE INDEX idx_product_tags_tag ON product_tags (tag_id);",
+    // v22: 商品の編集（表示名・目標価格・チェックの停止）。
+    // 全文検索は表示名があればそちらを対象にするため、products の列を参照しない contentless の索引に作り直す
+    "ALTER TABLE products ADD COLUMN display_name TEXT;
+    ALTER TABLE products ADD COLUMN target_price INTEGER;
+    ALTER TABLE products ADD COLUMN disabled_at TEXT;
+    DROP TRIGGER products_fts_insert;
+    DROP TRIGGER products_fts_delete;
+    DROP TRIGGER products_fts_update;
+    DROP TABLE products_fts;
+    CREATE VIRTUAL TABLE products_fts USING fts5(
+        name, content='', contentless_delete=1, tokenize='trigram'
+    );
+    INSERT INTO products_fts (rowid, name) SELECT id, COALESCE(display_name, name) FROM products;
+    CREATE TRIGGER products_fts_insert AFTER INSERT ON products BEGIN
+        INSERT INTO products_fts (rowid, name) VALUES (new.id, COALESCE(new.display_name, new.name));
+    END;
+    CREATE TRIGGER products_fts_delete AFTER DELETE ON products BEGIN
+        DELETE FROM products_fts WHERE rowid = old.id;
+    END;
+    CREATE TRIGGER products_fts_update AFTER UPDATE OF name, display_name ON products BEGIN
+        DELETE FROM products_fts WHERE rowid = old.id;
+        INSERT INTO products_fts (rowid, name) VALUES (new.id, COALESCE(new.display_name, new.name));
+    END;",
+    // v23: 実行記録を始めたプロセス（別のプロセスが実行中のチェックを中断として閉じないように）
+    "ALTER TABLE check_runs ADD COLUMN owner TEXT;",
+];
+
+/// データベースファイルのデフォルトのパス
+pub const DEFAULT_DB_PATH: &str = "products.db";
+
+static DB_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// データベースファイルのパスを設定（起動時に1回だけ）
+pub fn set_db_path(path: PathBuf) {
+    let _ = DB_PATH.set(path);
+}
+
+/// データベースファイルのパス
+pub fn db_path() -> &'static Path {
+    DB_PATH.get_or_init(|| PathBuf::from(DEFAULT_DB_PATH))
+}
+
+/// このバイナリが扱うスキーマのバージョン（PRAGMA user_version）
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
 
 pub fn init_db() -> Result<Connection> {
-    let conn = Connection::open("products.db")?;
-    
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS products (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            url TEXT NOT NULL UNIQUE,
-            name TEXT NOT NULL,
-            current_price INTEGER NOT NULL,
-            created_at TEXT NOT NULL
-        )",
-        [],
-    )?;
-    
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS price_history (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            product_id INTEGER NOT NULL,
-            price INTEGER NOT NULL,
-            checked_at TEXT NOT NULL,
-            FOREIGN KEY (product_id) REFERENCES products(id)
-        )",
-        [],
-    )?;
-    
+    let conn = Connection::open(db_path())?;
+
+    // serve と daemon を別プロセスで動かすときに、読み込みと書き込みが互いを待たないように WAL にする。
+    // 書き込みが重なったときは、すぐにエラーにせず少し待つ
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+
+    migrate(&conn)?;
+
+    // 外部キー制約は接続ごとに有効化が必要（テーブルを作り直すマイグレーションの後に有効化する）
+    conn.pragma_update(None, "foreign_keys", true)?;
+
     Ok(conn)
 }
 
+/// テスト用のメモリ上のDB（マイグレーション済み）
+#[cfg(test)]
+pub fn open_in_memory() -> Result<Connection> {
+    let conn = Connection::open_in_memory()?;
+    migrate(&conn)?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(conn)
+}
+
+/// DBのスキーマバージョンを取得
+pub fn schema_version(conn: &Connection) -> Result<usize> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+fn migrate(conn: &Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "Database schema version {} is newer than supported version {}",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// 商品を保存（URLが同じなら更新）
+///
+/// 在庫切れで価格が取れなかった場合は、最後に取得できた価格を残す。
 pub fn save_product(conn: &Connection, product: &Product) -> Result<i64> {
-    let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    
+    let now = timestamp::now();
+
     conn.execute(
-        "INSERT INTO products (url, name, current_price, created_at)
-         VALUES (?1, ?2, ?3, ?4)
+        "INSERT INTO products (url, name, current_price, image_url, seller, in_stock, created_at)
+         VALUES (?1, ?2, COALESCE(?3, (SELECT current_price FROM products WHERE url = ?1)), ?4, ?5, ?6, ?7)
          ON CONFLICT(url) DO UPDATE SET
          name = excluded.name,
-         current_price = excluded.current_price",
-        (&product.url, &product.name, product.price, &now),
+         current_price = excluded.current_price,
+         image_url = COALESCE(excluded.image_url, image_url),
+         seller = COALESCE(excluded.seller, seller),
+         stock_changed_at = CASE WHEN in_stock != excluded.in_stock THEN excluded.created_at
+                                 ELSE stock_changed_at END,
+         in_stock = excluded.in_stock",
+        (
+            &product.url,
+            &product.name,
+            product.price,
+            &product.image_url,
+            &product.seller,
+            product.in_stock,
+            &now,
+        ),
     )?;
-    
+
     let product_id: i64 = conn.query_row(
         "SELECT id FROM products WHERE url = ?1",
         [&product.url],
         |row| row.get(0),
     )?;
-    
+
     Ok(product_id)
 }
 
-pub fn save_price_history(conn: &Connection, product_id: i64, price: i32) -> Result<()> {
-    let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    
-    conn.execute(
-        "INSERT INTO price_history (product_id, price, checked_at)
-         VALUES (?1, ?2, ?3)",
-        (product_id, price, &now),
+/// 商品が存在するか
+pub fn product_exists(conn: &Connection, product_id: i64) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM products WHERE id = ?1)",
+        [product_id],
+        |row| row.get(0),
+    )?)
+}
+
+/// 商品をアーカイブ（一覧・価格チェックの対象外にするが、履歴は残す）
+///
+/// 対象の商品が存在しない場合は `false` を返す。
+pub fn archive_product(conn: &Connection, product_id: i64) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE products SET archived_at = COALESCE(archived_at, ?1) WHERE id = ?2",
+        (timestamp::now(), product_id),
+    )?;
+    Ok(updated > 0)
+}
+
+/// 商品ごとのチェック間隔を設定（None で全体のスケジュールに戻す）
+///
+/// 対象の商品が存在しない場合は `false` を返す。
+pub fn set_check_interval(conn: &Connection, product_id: i64, minutes: Option<i64>) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE products SET check_interval_minutes = ?1 WHERE id = ?2",
+        (minutes, product_id),
+    )?;
+    Ok(updated > 0)
+}
+
+/// 商品のカテゴリーを設定（None で解除）
+///
+/// 対象の商品が存在しない場合は `false` を返す。
+pub fn set_category(conn: &Connection, product_id: i64, category: Option<&str>) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE products SET category = ?1 WHERE id = ?2",
+        (category, product_id),
+    )?;
+    Ok(updated > 0)
+}
+
+/// 商品のメモを設定（None で解除）
+///
+/// 対象の商品が存在しない場合は `false` を返す。
+pub fn set_note(conn: &Connection, product_id: i64, note: Option<&str>) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE products SET note = ?1 WHERE id = ?2",
+        (note, product_id),
+    )?;
+    Ok(updated > 0)
+}
+
+/// 商品の表示名を設定（None で商品ページの名前に戻す）
+pub fn set_display_name(conn: &Connection, product_id: i64, display_name: Option<&str>) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE products SET display_name = ?1 WHERE id = ?2",
+        (display_name, product_id),
     )?;
-    
+    Ok(updated > 0)
+}
+
+/// 商品の目標価格を設定（None で解除）
+pub fn set_target_price(conn: &Connection, product_id: i64, target_price: Option<i32>) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE products SET target_price = ?1 WHERE id = ?2",
+        (target_price, product_id),
+    )?;
+    Ok(updated > 0)
+}
+
+/// 商品の価格チェックを再開・停止する（停止した商品は一覧に残るが、チェックしない）
+pub fn set_enabled(conn: &Connection, product_id: i64, enabled: bool) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE products SET disabled_at = CASE WHEN ?1 THEN NULL ELSE COALESCE(disabled_at, ?2) END
+         WHERE id = ?3",
+        (enabled, timestamp::now(), product_id),
+    )?;
+    Ok(updated > 0)
+}
+
+/// 商品のURLを変更し、新しいURLで取得した内容で更新する（価格履歴などはそのまま引き継ぐ）
+///
+/// 新しいURLで取得できているので、連続失敗の回数と要確認は元に戻す。
+pub fn change_url(conn: &Connection, product_id: i64, product: &Product) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE products SET
+         url = ?1,
+         name = ?2,
+         current_price = COALESCE(?3, current_price),
+         image_url = COALESCE(?4, image_url),
+         seller = COALESCE(?5, seller),
+         stock_changed_at = CASE WHEN in_stock != ?6 THEN ?7 ELSE stock_changed_at END,
+         in_stock = ?6,
+         consecutive_failures = 0,
+         needs_attention_at = NULL
+         WHERE id = ?8",
+        (
+            &product.url,
+            &product.name,
+            product.price,
+            &product.image_url,
+            &product.seller,
+            product.in_stock,
+            timestamp::now(),
+            product_id,
+        ),
+    )?;
+    Ok(updated > 0)
+}
+
+/// アーカイブした商品を元に戻す
+pub fn restore_product(conn: &Connection, product_id: i64) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE products SET archived_at = NULL WHERE id = ?1",
+        [product_id],
+    )?;
+    Ok(updated > 0)
+}
+
+/// 商品を完全に削除（価格履歴も ON DELETE CASCADE で削除される）
+pub fn delete_product(conn: &Connection, product_id: i64) -> Result<bool> {
+    let tx = conn.unchecked_transaction()?;
+    let deleted = tx.execute("DELETE FROM products WHERE id = ?1", [product_id])?;
+    tx.commit()?;
+    Ok(deleted > 0)
+}
+
+/// 価格履歴を保存
+///
+/// 直前の記録と同じ価格なら新しい行は作らず、`last_seen_at` だけ更新する。
+/// 1行 = 「その価格だった期間」を表す変化イベントになる。
+pub fn save_price_history(conn: &Connection, product_id: i64, price: i32) -> Result<()> {
+    let now = timestamp::now();
+
+    let last: Option<(i64, i32)> = conn
+        .query_row(
+            "SELECT id, price FROM price_history
+             WHERE product_id = ?1
+             ORDER BY checked_at DESC, id DESC
+             LIMIT 1",
+            [product_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    match last {
+        Some((id, last_price)) if last_price == price => {
+            conn.execute(
+                "UPDATE price_history SET last_seen_at = ?1 WHERE id = ?2",
+                (&now, id),
+            )?;
+        }
+        _ => {
+            conn.execute(
+                "INSERT INTO price_history (product_id, price, checked_at, last_seen_at)
+                 VALUES (?1, ?2, ?3, ?3)",
+                (product_id, price, &now),
+            )?;
+        }
+    }
+
     Ok(())
 }
+
+/// 価格履歴の1件（日次に集約済みのものは最安値・最高値つき）
+pub struct HistoryEntry {
+    pub price: i32,
+    pub checked_at: String,
+    pub last_seen_at: String,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+}
+
+/// 価格履歴を時系列で取得
+///
+/// 日次に集約された古いデータ（終値）と直近の変化イベントをまとめて返す。
+/// `from` 以降に確認され、`to` より前に始まった記録が対象（どちらもDB保存形式）。
+pub fn load_price_history(
+    conn: &Connection,
+    product_id: i64,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<HistoryEntry>> {
+    // 索引を使えるように、未指定の境界は常に真になる値で埋める
+    let from = from.unwrap_or("");
+    let to = to.unwrap_or("9999");
+
+    let mut stmt = conn.prepare(
+        "SELECT close_price, closed_at, closed_at, min_price, max_price
+         FROM price_history_daily
+         WHERE product_id = ?1 AND closed_at >= ?2 AND closed_at < ?3
+         UNION ALL
+         SELECT price, checked_at, COALESCE(last_seen_at, checked_at), NULL, NULL
+         FROM price_history
+         WHERE product_id = ?1
+           AND COALESCE(last_seen_at, checked_at) >= ?2
+           AND checked_at < ?3
+         ORDER BY 2 ASC",
+    )?;
+
+    let history = stmt
+        .query_map((product_id, from, to), |row| {
+            Ok(HistoryEntry {
+                price: row.get(0)?,
+                checked_at: row.get(1)?,
+                last_seen_at: row.get(2)?,
+                min_price: row.get(3)?,
+                max_price: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(price: Option<i32>, in_stock: bool) -> Product {
+        Product {
+            name: "テスト商品".to_string(),
+            price,
+            in_stock,
+            url: "https://example.com/item".to_string(),
+            image_url: None,
+            seller: None,
+        }
+    }
+
+    #[test]
+    fn sold_out_product_keeps_its_last_price() {
+        let conn = open_in_memory().unwrap();
+        let product_id = save_product(&conn, &product(Some(2480), true)).unwrap();
+
+        // 在庫切れで価格が取れなくても、最後の価格は残して在庫だけ切り替える
+        assert_eq!(save_product(&conn, &product(None, false)).unwrap(), product_id);
+        let (price, in_stock, stock_changed_at): (Option<i32>, bool, Option<String>) = conn
+            .query_row(
+                "SELECT current_price, in_stock, stock_changed_at FROM products WHERE id = ?1",
+                [product_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((price, in_stock), (Some(2480), false));
+        assert!(stock_changed_at.is_some());
+    }
+
+    /// 商品に紐づく行の件数（テーブルごと）
+    fn dependent_rows(conn: &Connection, product_id: i64) -> Vec<i64> {
+        ["price_history", "price_history_daily", "alert_rules", "alert_events", "check_attempts", "product_tags"]
+            .iter()
+            .map(|table| {
+                conn.query_row(
+                    &format!("SELECT COUNT(*) FROM {} WHERE product_id = ?1", table),
+                    [product_id],
+                    |row| row.get(0),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn delete_product_cascades_to_history_rules_and_attempts() {
+        let conn = open_in_memory().unwrap();
+        let product_id = save_product(&conn, &product(Some(2480), true)).unwrap();
+        let other_id = save_product(
+            &conn,
+            &Product {
+                url: "https://example.com/other".to_string(),
+                ..product(Some(980), true)
+            },
+        )
+        .unwrap();
+        for id in [product_id, other_id] {
+            save_price_history(&conn, id, 2480).unwrap();
+            conn.execute_batch(&format!(
+                "INSERT INTO price_history_daily (product_id, day, min_price, max_price, close_price, closed_at)
+                    VALUES ({id}, '2026-01-01', 2400, 2600, 2500, '2026-01-01T14:59:59Z');
+                INSERT INTO alert_rules (product_id, kind, threshold, created_at)
+                    VALUES ({id}, 'below_price', 2500, '2026-10-19T00:00:00Z');
+                INSERT INTO alert_events (rule_id, product_id, new_price, message, triggered_at)
+                    VALUES (last_insert_rowid(), {id}, 2480, 'テスト', '2026-10-19T00:00:00Z');
+                INSERT INTO check_runs (trigger_source, started_at, total) VALUES ('cli', '2026-10-19T00:00:00Z', 1);
+                INSERT INTO check_attempts (run_id, product_id, status, latency_ms, attempted_at)
+                    VALUES (last_insert_rowid(), {id}, 'ok', 100, '2026-10-19T00:00:00Z');
+                INSERT OR IGNORE INTO tags (name) VALUES ('飲料');
+                INSERT INTO product_tags (product_id, tag_id) SELECT {id}, id FROM tags WHERE name = '飲料';",
+            ))
+            .unwrap();
+        }
+        assert_eq!(dependent_rows(&conn, product_id), [1, 1, 1, 1, 1, 1]);
+
+        assert!(delete_product(&conn, product_id).unwrap());
+        assert_eq!(dependent_rows(&conn, product_id), [0, 0, 0, 0, 0, 0]);
+        // ほかの商品の行は残る
+        assert_eq!(dependent_rows(&conn, other_id), [1, 1, 1, 1, 1, 1]);
+        assert!(!delete_product(&conn, product_id).unwrap());
+    }
+
+    #[test]
+    fn v3_rewrites_timestamps_to_rfc3339() {
+        // v2 までのスキーマで、'YYYY-MM-DD HH:MM:SS'（UTC）の日時を持つDB
+        let conn = Connection::open_in_memory().unwrap();
+        for sql in &MIGRATIONS[..2] {
+            conn.execute_batch(sql).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 2).unwrap();
+        conn.execute_batch(
+            "INSERT INTO products (id, url, name, current_price, created_at)
+                VALUES (1, 'https://example.com/item', 'テスト商品', 2480, '2026-10-01 09:00:00');
+            INSERT INTO price_history (product_id, price, checked_at, last_seen_at)
+                VALUES (1, 2480, '2026-10-01 09:00:00', '2026-10-02 10:30:00');
+            INSERT INTO price_history_daily (product_id, day, min_price, max_price, close_price, closed_at)
+                VALUES (1, '2026-09-30', 2400, 2600, 2500, '2026-09-30 14:59:59');",
+        )
+        .unwrap();
+
+        migrate(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        let timestamps: (String, String, String, String) = conn
+            .query_row(
+                "SELECT p.created_at, h.checked_at, h.last_seen_at, d.closed_at
+                 FROM products p
+                 JOIN price_history h ON h.product_id = p.id
+                 JOIN price_history_daily d ON d.product_id = p.id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            timestamps,
+            (
+                "2026-10-01T09:00:00Z".to_string(),
+                "2026-10-01T09:00:00Z".to_string(),
+                "2026-10-02T10:30:00Z".to_string(),
+                "2026-09-30T14:59:59Z".to_string(),
+            )
+        );
+    }
+}
@@ -0,0 +1,437 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::mailer::escape_html;
+use crate::notify::price_change;
+use crate::timestamp;
+
+/// 日次レポートの対象期間のデフォルト（時間）
+pub const DEFAULT_DIGEST_HOURS: i64 = 24;
+
+/// 期間中に価格が変わった商品
+#[derive(Serialize)]
+pub struct PriceChange {
+    pub product_id: i64,
+    pub name: String,
+    pub url: String,
+    /// 期間が始まる前の価格
+    pub old_price: i32,
+    pub new_price: i32,
+    /// 期間中の変化の回数
+    pub changes: usize,
+}
+
+/// 期間中に過去最安値を更新した商品
+#[derive(Serialize)]
+pub struct NewLow {
+    pub product_id: i64,
+    pub name: String,
+    pub url: String,
+    pub price: i32,
+    pub previous_low: i32,
+}
+
+/// 直近の価格チェックが失敗している商品
+#[derive(Serialize)]
+pub struct FailingProduct {
+    pub product_id: i64,
+    pub name: String,
+    pub url: String,
+    /// 期間中の失敗回数
+    pub failures: usize,
+    pub error_kind: Option<String>,
+    pub error_message: Option<String>,
+}
+
+/// 日次レポート（直近の価格変動・最安値更新・失敗している商品のまとめ）
+#[derive(Serialize)]
+pub struct Digest {
+    pub since: String,
+    pub until: String,
+    pub changes: Vec<PriceChange>,
+    pub new_lows: Vec<NewLow>,
+    pub failing: Vec<FailingProduct>,
+    /// 期間中に発火したアラートの件数
+    pub alerts: usize,
+}
+
+/// 直近 `hours` 時間のレポートを作成（アーカイブした商品は含めない）
+pub fn build(conn: &Connection, hours: i64) -> Result<Digest> {
+    let until = Utc::now();
+    let since = timestamp::to_db(until - Duration::hours(hours));
+
+    let mut changes = Vec::new();
+    let mut new_lows = Vec::new();
+    {
+        let mut stmt = conn.prepare(
+            "SELECT p.id, COALESCE(p.display_name, p.name), p.url,
+                    (SELECT price FROM price_history h
+                     WHERE h.product_id = p.id AND h.checked_at < ?1
+                     ORDER BY h.checked_at DESC, h.id DESC LIMIT 1),
+                    (SELECT price FROM price_history h
+                     WHERE h.product_id = p.id
+                     ORDER BY h.checked_at DESC, h.id DESC LIMIT 1),
+                    (SELECT COUNT(*) FROM price_history h
+                     WHERE h.product_id = p.id AND h.checked_at >= ?1),
+                    (SELECT MIN(price) FROM price_history h
+                     WHERE h.product_id = p.id AND h.checked_at >= ?1),
+                    (SELECT MIN(price) FROM price_history h
+                     WHERE h.product_id = p.id AND h.checked_at < ?1),
+                    (SELECT MIN(min_price) FROM price_history_daily d
+                     WHERE d.product_id = p.id AND d.closed_at < ?1)
+             FROM products p
+             WHERE p.archived_at IS NULL
+             ORDER BY p.id",
+        )?;
+
+        let rows = stmt.query_map([&since], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<i32>>(3)?,
+                row.get::<_, Option<i32>>(4)?,
+                row.get::<_, usize>(5)?,
+                row.get::<_, Option<i32>>(6)?,
+                row.get::<_, Option<i32>>(7)?,
+                row.get::<_, Option<i32>>(8)?,
+            ))
+        })?;
+
+        for row in rows {
+            let (product_id, name, url, old_price, new_price, count, low_in_period, raw_low, daily_low) = row?;
+
+            // 期間より前の履歴がない商品（新しく追加した商品）は変動・最安値として扱わない
+            let (Some(old_price), Some(new_price)) = (old_price, new_price) else {
+                continue;
+            };
+
+            if count > 0 {
+                changes.push(PriceChange {
+                    product_id,
+                    name: name.clone(),
+                    url: url.clone(),
+                    old_price,
+                    new_price,
+                    changes: count,
+                });
+            }
+
+            let previous_low = match (raw_low, daily_low) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            if let (Some(price), Some(previous_low)) = (low_in_period, previous_low)
+                && price < previous_low
+            {
+                new_lows.push(NewLow {
+                    product_id,
+                    name,
+                    url,
+                    price,
+                    previous_low,
+                });
+            }
+        }
+    }
+
+    let failing = {
+        let mut stmt = conn.prepare(
+            "SELECT p.id, COALESCE(p.display_name, p.name), p.url,
+                    (SELECT COUNT(*) FROM check_attempts b
+                     WHERE b.product_id = p.id AND b.status != 'ok' AND b.attempted_at >= ?1),
+                    a.error_kind, a.error_message
+             FROM check_attempts a
+             JOIN products p ON p.id = a.product_id
+             WHERE a.id = (SELECT c.id FROM check_attempts c
+                           WHERE c.product_id = a.product_id AND c.attempted_at >= ?1
+                           ORDER BY c.attempted_at DESC, c.id DESC LIMIT 1)
+               AND a.status != 'ok'
+               AND p.archived_at IS NULL
+             ORDER BY p.id",
+        )?;
+        stmt.query_map([&since], |row| {
+            Ok(FailingProduct {
+                product_id: row.get(0)?,
+                name: row.get(1)?,
+                url: row.get(2)?,
+                failures: row.get(3)?,
+                error_kind: row.get(4)?,
+                error_message: row.get(5)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?
+    };
+
+    let alerts = conn.query_row(
+        "SELECT COUNT(*) FROM alert_events e
+         JOIN products p ON p.id = e.product_id
+         WHERE e.triggered_at >= ?1 AND p.archived_at IS NULL",
+        [&since],
+        |row| row.get(0),
+    )?;
+
+    Ok(Digest {
+        since,
+        until: timestamp::to_db(until),
+        changes,
+        new_lows,
+        failing,
+        alerts,
+    })
+}
+
+impl Digest {
+    /// 報告することがない
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.new_lows.is_empty() && self.failing.is_empty() && self.alerts == 0
+    }
+
+    pub fn subject(&self) -> String {
+        format!(
+            "📊 価格モニター日次レポート {}（変動 {}件・最安値更新 {}件・エラー {}件）",
+            timestamp::local_day(&self.until),
+            self.changes.len(),
+            self.new_lows.len(),
+            self.failing.len()
+        )
+    }
+
+    fn period(&self) -> String {
+        format!("{} 〜 {}", timestamp::display(&self.since), timestamp::display(&self.until))
+    }
+
+    /// テキスト形式（メールのプレーンテキスト部分と CLI のプレビューに使う）
+    pub fn to_text(&self) -> String {
+        let mut out = format!("期間: {}\n発火したアラート: {}件\n", self.period(), self.alerts);
+
+        out.push_str(&format!("\n📈 価格変動（{}件）\n", self.changes.len()));
+        if self.changes.is_empty() {
+            out.push_str("  なし\n");
+        }
+        for c in &self.changes {
+            out.push_str(&format!(
+                "  [ID:{}] {}\n    {}（{}回変動）\n    {}\n",
+                c.product_id,
+                c.name,
+                price_change(Some(c.old_price), c.new_price),
+                c.changes,
+                c.url
+            ));
+        }
+
+        out.push_str(&format!("\n🏆 最安値更新（{}件）\n", self.new_lows.len()));
+        if self.new_lows.is_empty() {
+            out.push_str("  なし\n");
+        }
+        for l in &self.new_lows {
+            out.push_str(&format!(
+                "  [ID:{}] {}\n    ¥{}（これまでの最安値 ¥{}）\n    {}\n",
+                l.product_id, l.name, l.price, l.previous_low, l.url
+            ));
+        }
+
+        out.push_str(&format!("\n⚠️  チェックに失敗している商品（{}件）\n", self.failing.len()));
+        if self.failing.is_empty() {
+            out.push_str("  なし\n");
+        }
+        for f in &self.failing {
+            out.push_str(&format!(
+                "  [ID:{}] {}\n    {}回失敗: {}\n",
+                f.product_id,
+                f.name,
+                f.failures,
+                f.error_message.as_deref().unwrap_or("-")
+            ));
+        }
+
+        out
+    }
+
+    /// HTML形式
+    pub fn to_html(&self) -> String {
+        let link = |name: &str, url: &str| {
+            format!("<a href=\"{}\">{}</a>", escape_html(url), escape_html(name))
+        };
+        let section = |title: &str, rows: Vec<String>| {
+            let body = if rows.is_empty() {
+                "<p style=\"color:#888\">なし</p>".to_string()
+            } else {
+                format!("<ul>{}</ul>", rows.concat())
+            };
+            format!("<h3>{}</h3>{}", title, body)
+        };
+
+        let changes = self
+            .changes
+            .iter()
+            .map(|c| {
+                format!(
+                    "<li>{}<br>{}（{}回変動）</li>",
+                    link(&c.name, &c.url),
+                    escape_html(&price_change(Some(c.old_price), c.new_price)),
+                    c.changes
+                )
+            })
+            .collect();
+        let new_lows = self
+            .new_lows
+            .iter()
+            .map(|l| {
+                format!(
+                    "<li>{}<br><strong>¥{}</strong>（これまでの最安値 ¥{}）</li>",
+                    link(&l.name, &l.url),
+                    l.price,
+                    l.previous_low
+                )
+            })
+            .collect();
+        let failing = self
+            .failing
+            .iter()
+            .map(|f| {
+                format!(
+                    "<li>{}<br>{}回失敗: <code>{}</code></li>",
+                    link(&f.name, &f.url),
+                    f.failures,
+                    escape_html(f.error_message.as_deref().unwrap_or("-"))
+                )
+            })
+            .collect();
+
+        format!(
+            "<html><body style=\"font-family:sans-serif\">\
+             <h2>📊 価格モニター日次レポート</h2>\
+             <p>期間: {}<br>発火したアラート: {}件</p>\
+             {}{}{}\
+             </body></html>",
+            escape_html(&self.period()),
+            self.alerts,
+            section(&format!("📈 価格変動（{}件）", self.changes.len()), changes),
+            section(&format!("🏆 最安値更新（{}件）", self.new_lows.len()), new_lows),
+            section(&format!("⚠️ チェックに失敗している商品（{}件）", self.failing.len()), failing),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::runs::{self, Trigger};
+
+    /// 現在から `hours` 時間前（DB保存形式）
+    fn hours_ago(hours: i64) -> String {
+        timestamp::to_db(Utc::now() - Duration::hours(hours))
+    }
+
+    fn insert_product(conn: &Connection, name: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at) VALUES (?1, ?2, 0, ?3)",
+            (format!("https://example.com/{}", name), name, hours_ago(24 * 30)),
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn insert_history(conn: &Connection, product_id: i64, price: i32, hours: i64) {
+        conn.execute(
+            "INSERT INTO price_history (product_id, price, checked_at, last_seen_at) VALUES (?1, ?2, ?3, ?3)",
+            (product_id, price, hours_ago(hours)),
+        )
+        .unwrap();
+    }
+
+    /// 値下がりして最安値を更新した商品・値下がりしたが日次データに安値がある商品・失敗している商品
+    fn seed(conn: &Connection) -> (i64, i64, i64) {
+        let new_low = insert_product(conn, "天然水 <2L> & 9本");
+        insert_history(conn, new_low, 1200, 72);
+        insert_history(conn, new_low, 1000, 48);
+        insert_history(conn, new_low, 980, 5);
+        insert_history(conn, new_low, 900, 1);
+
+        let cheaper_before = insert_product(conn, "緑茶");
+        conn.execute(
+            "INSERT INTO price_history_daily (product_id, day, min_price, max_price, close_price, closed_at)
+             VALUES (?1, '2020-01-01', 400, 500, 500, '2020-01-01T14:59:59Z')",
+            [cheaper_before],
+        )
+        .unwrap();
+        insert_history(conn, cheaper_before, 500, 240);
+        insert_history(conn, cheaper_before, 450, 2);
+
+        let failing = insert_product(conn, "紅茶");
+        insert_history(conn, failing, 300, 100);
+        let run_id = runs::start_run(conn, Trigger::Cli, &[failing]).unwrap();
+        for _ in 0..2 {
+            let error = anyhow::anyhow!("HTTP 503");
+            runs::record_attempt(conn, run_id, failing, Err(&error), std::time::Duration::from_millis(10)).unwrap();
+        }
+
+        (new_low, cheaper_before, failing)
+    }
+
+    #[test]
+    fn build_collects_changes_new_lows_and_failures() {
+        let conn = db::open_in_memory().unwrap();
+        let (new_low, cheaper_before, failing) = seed(&conn);
+
+        let digest = build(&conn, 24).unwrap();
+
+        let changes: Vec<_> = digest
+            .changes
+            .iter()
+            .map(|c| (c.product_id, c.old_price, c.new_price, c.changes))
+            .collect();
+        assert_eq!(changes, [(new_low, 1000, 900, 2), (cheaper_before, 500, 450, 1)]);
+
+        // 日次に集約された安値（400円）より高いので、緑茶は最安値更新に含めない
+        let new_lows: Vec<_> = digest
+            .new_lows
+            .iter()
+            .map(|l| (l.product_id, l.price, l.previous_low))
+            .collect();
+        assert_eq!(new_lows, [(new_low, 900, 1000)]);
+
+        assert_eq!(digest.failing.len(), 1);
+        assert_eq!(digest.failing[0].product_id, failing);
+        assert_eq!(digest.failing[0].failures, 2);
+        assert_eq!(digest.failing[0].error_message.as_deref(), Some("HTTP 503"));
+        assert_eq!(digest.alerts, 0);
+        assert!(!digest.is_empty());
+        assert!(digest.subject().contains("変動 2件・最安値更新 1件・エラー 1件"));
+    }
+
+    #[test]
+    fn text_and_html_render_every_section() {
+        let conn = db::open_in_memory().unwrap();
+        seed(&conn);
+        let digest = build(&conn, 24).unwrap();
+
+        let text = digest.to_text();
+        assert!(text.contains("📈 価格変動（2件）"));
+        assert!(text.contains("天然水 <2L> & 9本"));
+        assert!(text.contains("¥900（これまでの最安値 ¥1000）"));
+        assert!(text.contains("⚠️  チェックに失敗している商品（1件）"));
+        assert!(text.contains("2回失敗: HTTP 503"));
+
+        // 商品名はエスケープする
+        let html = digest.to_html();
+        assert!(html.contains("天然水 &lt;2L&gt; &amp; 9本"));
+        assert!(!html.contains("<2L>"));
+        assert!(html.contains("<strong>¥900</strong>（これまでの最安値 ¥1000）"));
+        assert!(html.contains("2回失敗: <code>HTTP 503</code>"));
+    }
+
+    #[test]
+    fn empty_period_renders_none() {
+        let conn = db::open_in_memory().unwrap();
+        let digest = build(&conn, 24).unwrap();
+
+        assert!(digest.is_empty());
+        assert_eq!(digest.to_text().matches("  なし").count(), 3);
+        assert_eq!(digest.to_html().matches("なし</p>").count(), 3);
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::db::save_price_history;
+
+pub struct DoctorReport {
+    /// PRAGMA integrity_check の結果（問題なければ空）
+    pub integrity_errors: Vec<String>,
+    /// 存在しない商品を指している価格履歴の行数
+    pub orphaned_history: usize,
+    /// 価格履歴が1件もない商品
+    pub products_without_history: Vec<(i64, String)>,
+}
+
+impl DoctorReport {
+    pub fn is_healthy(&self) -> bool {
+        self.integrity_errors.is_empty()
+            && self.orphaned_history == 0
+            && self.products_without_history.is_empty()
+    }
+}
+
+/// DBの整合性を診断
+pub fn diagnose(conn: &Connection) -> Result<DoctorReport> {
+    let integrity_errors: Vec<String> = {
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        stmt.query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|msg| msg != "ok")
+            .collect()
+    };
+
+    let orphaned_history: usize = conn.query_row(
+        "SELECT
+            (SELECT COUNT(*) FROM price_history
+             WHERE product_id NOT IN (SELECT id FROM products))
+          + (SELECT COUNT(*) FROM price_history_daily
+             WHERE product_id NOT IN (SELECT id FROM products))",
+        [],
+        |row| row.get(0),
+    )?;
+
+    let products_without_history = {
+        let mut stmt = conn.prepare(
+            "SELECT id, name FROM products p
+             WHERE NOT EXISTS (SELECT 1 FROM price_history h WHERE h.product_id = p.id)
+               AND NOT EXISTS (SELECT 1 FROM price_history_daily d WHERE d.product_id = p.id)
+             ORDER BY id",
+        )?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?
+    };
+
+    Ok(DoctorReport {
+        integrity_errors,
+        orphaned_history,
+        products_without_history,
+    })
+}
+
+/// 見つかった問題を修復
+///
+/// 孤立した価格履歴は削除し、履歴のない商品には現在価格で履歴を1件作る。
+/// integrity_check のエラーは索引の再構築を試みるだけなので、直らなければバックアップから復元する。
+pub fn repair(conn: &Connection, report: &DoctorReport) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+
+    if !report.integrity_errors.is_empty() {
+        tx.execute_batch("REINDEX")?;
+    }
+
+    tx.execute(
+        "DELETE FROM price_history WHERE product_id NOT IN (SELECT id FROM products)",
+        [],
+    )?;
+    tx.execute(
+        "DELETE FROM price_history_daily WHERE product_id NOT IN (SELECT id FROM products)",
+        [],
+    )?;
+
+    for (product_id, _name) in &report.products_without_history {
+        let price: i32 = tx.query_row(
+            "SELECT current_price FROM products WHERE id = ?1",
+            [product_id],
+            |row| row.get(0),
+        )?;
+        save_price_history(&tx, *product_id, price)?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn finds_and_repairs_orphans_and_products_without_history() {
+        let conn = db::open_in_memory().unwrap();
+        conn.execute_batch(
+            "INSERT INTO products (id, url, name, current_price, created_at) VALUES
+                (1, 'https://example.com/1', '履歴あり', 1000, '2026-10-01T00:00:00Z'),
+                (2, 'https://example.com/2', '履歴なし', 2480, '2026-10-01T00:00:00Z');
+            INSERT INTO price_history (product_id, price, checked_at, last_seen_at)
+                VALUES (1, 1000, '2026-10-01T00:00:00Z', '2026-10-01T00:00:00Z');",
+        )
+        .unwrap();
+        // 外部キー制約がなかった頃の DB で削除された商品の履歴
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute_batch(
+            "INSERT INTO price_history (product_id, price, checked_at, last_seen_at)
+                VALUES (99, 500, '2026-10-01T00:00:00Z', '2026-10-01T00:00:00Z');
+            INSERT INTO price_history_daily (product_id, day, min_price, max_price, close_price, closed_at)
+                VALUES (99, '2026-01-01', 400, 600, 500, '2026-01-01T14:59:59Z');",
+        )
+        .unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+
+        let report = diagnose(&conn).unwrap();
+        assert!(report.integrity_errors.is_empty());
+        assert_eq!(report.orphaned_history, 2);
+        assert_eq!(report.products_without_history, [(2, "履歴なし".to_string())]);
+        assert!(!report.is_healthy());
+
+        repair(&conn, &report).unwrap();
+        assert!(diagnose(&conn).unwrap().is_healthy());
+        // 履歴のなかった商品には現在価格の履歴ができる
+        let price: i32 = conn
+            .query_row("SELECT price FROM price_history WHERE product_id = 2", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(price, 2480);
+    }
+}
@@ -0,0 +1,421 @@
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+
+use crate::db;
+use crate::scheduler;
+use crate::scraper::{self, fetch_amazon_price};
+use crate::tags;
+
+/// 表示名の最大の長さ（文字数）
+const MAX_DISPLAY_NAME_LEN: usize = 200;
+
+/// 商品の変更（省略した項目は変更しない。null を指定できる項目は null で解除）
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProductEdit {
+    /// 表示名（null か空文字で商品ページの名前に戻す）
+    #[serde(default, deserialize_with = "nullable")]
+    pub display_name: Option<Option<String>>,
+    /// 商品ページのURL（変更すると新しいURLで取得し直して確かめる）
+    pub url: Option<String>,
+    /// 目標価格（円）
+    #[serde(default, deserialize_with = "nullable")]
+    pub target_price: Option<Option<i32>>,
+    /// タグ（指定したもので置き換える。[] で全部外す）
+    pub tags: Option<Vec<String>>,
+    /// チェック間隔（分。null で自動調整に戻す）
+    #[serde(default, deserialize_with = "nullable")]
+    pub check_interval_minutes: Option<Option<i64>>,
+    /// false で価格チェックを止める
+    pub enabled: Option<bool>,
+    /// カテゴリー（null か空文字で解除）
+    #[serde(default, deserialize_with = "nullable")]
+    pub category: Option<Option<String>>,
+    /// メモ（null か空文字で解除）
+    #[serde(default, deserialize_with = "nullable")]
+    pub note: Option<Option<String>>,
+}
+
+/// 省略（変更しない）と null（解除する）を区別する
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// 商品を変更できなかった理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditErrorKind {
+    /// 値が正しくない
+    Invalid,
+    NotFound,
+    /// 新しいURLがほかの商品で使われている
+    Conflict,
+    /// 新しいURLの商品ページを取得できない
+    Unreachable,
+    /// DBのエラーなど
+    Internal,
+}
+
+#[derive(Debug)]
+pub struct EditError {
+    pub kind: EditErrorKind,
+    /// 問題のある項目（ProductEdit のフィールド名）
+    pub field: Option<&'static str>,
+    pub message: String,
+}
+
+impl EditError {
+    fn new(kind: EditErrorKind, field: Option<&'static str>, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            field,
+            message: message.into(),
+        }
+    }
+
+    fn invalid(field: &'static str, message: impl fmt::Display) -> Self {
+        Self::new(EditErrorKind::Invalid, Some(field), message.to_string())
+    }
+
+    fn not_found(product_id: i64) -> Self {
+        Self::new(EditErrorKind::NotFound, None, format!("Product not found: {}", product_id))
+    }
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.field {
+            Some(field) => write!(f, "{}: {}", field, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+impl From<anyhow::Error> for EditError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(EditErrorKind::Internal, None, e.to_string())
+    }
+}
+
+/// 確かめて整えた変更（`validate` で作る）
+pub struct ValidEdit {
+    display_name: Option<Option<String>>,
+    url: Option<String>,
+    target_price: Option<Option<i32>>,
+    tags: Option<Vec<String>>,
+    check_interval_minutes: Option<Option<i64>>,
+    enabled: Option<bool>,
+    category: Option<Option<String>>,
+    note: Option<Option<String>>,
+}
+
+impl ValidEdit {
+    /// URLの変更を含むか（含むなら `fetch_url` で取得し直してから `apply` する）
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+}
+
+impl ProductEdit {
+    pub fn is_empty(&self) -> bool {
+        self.display_name.is_none()
+            && self.url.is_none()
+            && self.target_price.is_none()
+            && self.tags.is_none()
+            && self.check_interval_minutes.is_none()
+            && self.enabled.is_none()
+            && self.category.is_none()
+            && self.note.is_none()
+    }
+
+    /// 値を確かめて整える（途中まで変更してから失敗しないように、DBを変更する前に全部確かめる）
+    pub fn validate(self) -> Result<ValidEdit, EditError> {
+        if self.is_empty() {
+            return Err(EditError::new(EditErrorKind::Invalid, None, "Nothing to update"));
+        }
+
+        let display_name = self
+            .display_name
+            .map(|name| normalize_display_name(name.as_deref().unwrap_or_default()))
+            .transpose()
+            .map_err(|e| EditError::invalid("display_name", e))?;
+        let url = self
+            .url
+            .map(|url| canonical_url(&url))
+            .transpose()
+            .map_err(|e| EditError::invalid("url", e))?;
+        if let Some(Some(price)) = self.target_price
+            && price <= 0
+        {
+            return Err(EditError::invalid("target_price", "Target price must be a positive number of yen"));
+        }
+        let tags = self
+            .tags
+            .map(|t| t.iter().map(|t| tags::normalize_tag(t)).collect::<anyhow::Result<Vec<_>>>())
+            .transpose()
+            .map_err(|e| EditError::invalid("tags", e))?;
+        if let Some(Some(minutes)) = self.check_interval_minutes {
+            if minutes <= 0 {
+                return Err(EditError::invalid("check_interval_minutes", "Interval must be at least 1 minute"));
+            }
+            if minutes > scheduler::MAX_CHECK_INTERVAL_MINUTES {
+                return Err(EditError::invalid("check_interval_minutes", "Interval must be at most 365 days"));
+            }
+        }
+        let category = self
+            .category
+            .map(|c| tags::normalize_category(c.as_deref().unwrap_or_default()))
+            .transpose()
+            .map_err(|e| EditError::invalid("category", e))?;
+        let note = self
+            .note
+            .map(|n| tags::normalize_note(n.as_deref().unwrap_or_default()))
+            .transpose()
+            .map_err(|e| EditError::invalid("note", e))?;
+
+        Ok(ValidEdit {
+            display_name,
+            url,
+            target_price: self.target_price,
+            tags,
+            check_interval_minutes: self.check_interval_minutes,
+            enabled: self.enabled,
+            category,
+            note,
+        })
+    }
+}
+
+fn normalize_display_name(name: &str) -> anyhow::Result<Option<String>> {
+    let name = name.trim();
+    if name.chars().count() > MAX_DISPLAY_NAME_LEN {
+        anyhow::bail!("Display name must be at most {} characters", MAX_DISPLAY_NAME_LEN);
+    }
+    Ok((!name.is_empty()).then(|| name.to_string()))
+}
+
+/// 商品ページのURLを正規化する
+///
+/// Amazon の商品ページは ASIN だけの `https://<ホスト>/dp/<ASIN>` にする（追跡用のパラメータや商品名の部分を除く）。
+/// それ以外のURLはフラグメントだけ除く。
+pub fn canonical_url(url: &str) -> anyhow::Result<String> {
+    let mut parsed = reqwest::Url::parse(url.trim()).map_err(|e| anyhow::anyhow!("Invalid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        anyhow::bail!("URL must start with http:// or https://");
+    }
+    parsed.set_fragment(None);
+
+    let host = parsed.host_str().unwrap_or_default().to_string();
+    if host.contains("amazon.") {
+        let segments: Vec<&str> = parsed.path_segments().map(|s| s.collect()).unwrap_or_default();
+        let asin = segments.windows(2).find_map(|pair| match pair {
+            ["dp", asin] | ["product", asin] | ["d", asin] if is_asin(asin) => Some(*asin),
+            _ => None,
+        });
+        if let Some(asin) = asin {
+            return Ok(format!("https://{}/dp/{}", host, asin.to_ascii_uppercase()));
+        }
+    }
+
+    Ok(parsed.to_string())
+}
+
+fn is_asin(s: &str) -> bool {
+    s.len() == 10 && s.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// 新しいURLがほかの商品で使われていないか確かめる（同じ商品のURLなら変更なしとして None）
+pub fn check_url(conn: &Connection, product_id: i64, url: &str) -> Result<Option<String>, EditError> {
+    let current: Option<String> = conn
+        .query_row("SELECT url FROM products WHERE id = ?1", [product_id], |row| row.get(0))
+        .optional()
+        .map_err(anyhow::Error::from)?;
+    let current = current.ok_or_else(|| EditError::not_found(product_id))?;
+    if current == url {
+        return Ok(None);
+    }
+
+    let other: Option<i64> = conn
+        .query_row("SELECT id FROM products WHERE url = ?1", [url], |row| row.get(0))
+        .optional()
+        .map_err(anyhow::Error::from)?;
+    if let Some(other) = other {
+        return Err(EditError::new(
+            EditErrorKind::Conflict,
+            Some("url"),
+            format!("URL is already used by product {}", other),
+        ));
+    }
+
+    Ok(Some(url.to_string()))
+}
+
+/// 新しいURLの商品ページを取得して、商品ページであることを確かめる
+pub async fn fetch_url(url: &str) -> Result<scraper::Product, EditError> {
+    fetch_amazon_price(url).await.map_err(|e| {
+        EditError::new(
+            EditErrorKind::Unreachable,
+            Some("url"),
+            format!("Could not fetch a product page from the new URL: {}", e),
+        )
+    })
+}
+
+/// 変更を保存する（URLを変える場合は `page` に新しいURLで取得した内容を渡す）
+///
+/// 全部の項目を1つのトランザクションで保存する（途中で失敗したら何も変えない）。
+pub fn apply(
+    conn: &Connection,
+    product_id: i64,
+    edit: &ValidEdit,
+    page: Option<&scraper::Product>,
+) -> Result<(), EditError> {
+    let tx = conn.unchecked_transaction().map_err(anyhow::Error::from)?;
+    if !db::product_exists(&tx, product_id)? {
+        return Err(EditError::not_found(product_id));
+    }
+
+    if let Some(page) = page {
+        // 取得している間に、ほかの商品が同じURLになっていないか確かめ直す
+        check_url(&tx, product_id, &page.url)?;
+        db::change_url(&tx, product_id, page)?;
+        if let Some(price) = page.price {
+            db::save_price_history(&tx, product_id, price)?;
+        }
+    }
+    if let Some(display_name) = &edit.display_name {
+        db::set_display_name(&tx, product_id, display_name.as_deref())?;
+    }
+    if let Some(target_price) = edit.target_price {
+        db::set_target_price(&tx, product_id, target_price)?;
+    }
+    if let Some(new_tags) = &edit.tags {
+        tags::replace_tags(&tx, product_id, new_tags)?;
+    }
+    if let Some(minutes) = edit.check_interval_minutes {
+        db::set_check_interval(&tx, product_id, minutes)?;
+    }
+    if let Some(enabled) = edit.enabled {
+        db::set_enabled(&tx, product_id, enabled)?;
+    }
+    if let Some(category) = &edit.category {
+        db::set_category(&tx, product_id, category.as_deref())?;
+    }
+    if let Some(note) = &edit.note {
+        db::set_note(&tx, product_id, note.as_deref())?;
+    }
+    scheduler::reschedule(&tx, product_id)?;
+    tx.commit().map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn amazon_urls_are_reduced_to_asin() {
+        assert_eq!(
+            canonical_url("https://www.amazon.co.jp/Some-Item/dp/b08cf1rxd9/ref=sr_1_1?keywords=x#top").unwrap(),
+            "https://www.amazon.co.jp/dp/B08CF1RXD9"
+        );
+        assert_eq!(
+            canonical_url("https://www.amazon.co.jp/gp/product/B08CF1RXD9?psc=1").unwrap(),
+            "https://www.amazon.co.jp/dp/B08CF1RXD9"
+        );
+        assert_eq!(canonical_url("http://127.0.0.1:8765/item.html#x").unwrap(), "http://127.0.0.1:8765/item.html");
+        assert!(canonical_url("ftp://example.com/").is_err());
+        assert!(canonical_url("not a url").is_err());
+    }
+
+    #[test]
+    fn omitted_null_and_values_are_distinguished() {
+        let edit: ProductEdit = serde_json::from_str(r#"{"display_name": null, "target_price": 2500}"#).unwrap();
+        assert_eq!(edit.display_name, Some(None));
+        assert_eq!(edit.target_price, Some(Some(2500)));
+        assert_eq!(edit.note, None);
+
+        let err = ProductEdit {
+            check_interval_minutes: Some(Some(0)),
+            ..Default::default()
+        }
+        .validate()
+        .err()
+        .unwrap();
+        assert_eq!((err.kind, err.field), (EditErrorKind::Invalid, Some("check_interval_minutes")));
+        let err = ProductEdit {
+            check_interval_minutes: Some(Some(scheduler::MAX_CHECK_INTERVAL_MINUTES + 1)),
+            ..Default::default()
+        }
+        .validate()
+        .err()
+        .unwrap();
+        assert_eq!((err.kind, err.field), (EditErrorKind::Invalid, Some("check_interval_minutes")));
+        assert!(ProductEdit::default().validate().is_err());
+        assert!(serde_json::from_str::<ProductEdit>(r#"{"nmae": "x"}"#).is_err());
+    }
+
+    fn insert(conn: &Connection, url: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at)
+             VALUES (?1, 'テスト商品', 1000, '2026-10-19T00:00:00Z')",
+            [url],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    #[test]
+    fn apply_saves_everything_or_nothing() {
+        let conn = db::open_in_memory().unwrap();
+        let id = insert(&conn, "https://example.com/a");
+        let other = insert(&conn, "https://example.com/b");
+
+        let edit = ProductEdit {
+            display_name: Some(Some("いつもの".to_string())),
+            tags: Some(vec!["キャンプ".to_string()]),
+            ..Default::default()
+        }
+        .validate()
+        .unwrap();
+        apply(&conn, id, &edit, None).unwrap();
+        assert_eq!(tags::product_tags(&conn, id).unwrap(), ["キャンプ"]);
+
+        // 取得している間にほかの商品が同じURLになっていたら、衝突として何も変えない
+        let edit = ProductEdit {
+            url: Some("https://example.com/b".to_string()),
+            display_name: Some(None),
+            tags: Some(Vec::new()),
+            ..Default::default()
+        }
+        .validate()
+        .unwrap();
+        let page = scraper::Product {
+            name: "新しい商品".to_string(),
+            price: Some(900),
+            in_stock: true,
+            url: "https://example.com/b".to_string(),
+            image_url: None,
+            seller: None,
+        };
+        let err = apply(&conn, id, &edit, Some(&page)).unwrap_err();
+        assert_eq!((err.kind, err.field), (EditErrorKind::Conflict, Some("url")));
+        assert!(err.message.contains(&format!("product {}", other)));
+
+        let (url, display_name): (String, Option<String>) = conn
+            .query_row("SELECT url, display_name FROM products WHERE id = ?1", [id], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((url.as_str(), display_name.as_deref()), ("https://example.com/a", Some("いつもの")));
+        assert_eq!(tags::product_tags(&conn, id).unwrap(), ["キャンプ"]);
+    }
+}
@@ -0,0 +1,700 @@
+use std::fmt;
+
+// アラートルールの条件式
+//
+//   price < 2500 and price < min_30d * 0.95 and seller contains 'Amazon'
+//
+// 演算子（優先順位の低い順）:
+//   or / and / not
+//   == != < <= > >= contains（比較は連結できない）
+//   + -
+//   * /
+//   -（単項）
+// 履歴がなく値がない変数（例: 初回チェックの last_price）を使った比較は成り立たない。
+
+/// 条件式で使える変数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Var {
+    Price,
+    LastPrice,
+    Avg30d,
+    Min30d,
+    Max30d,
+    AllTimeLow,
+    WasInStock,
+    Seller,
+    Name,
+    Target,
+}
+
+impl Var {
+    pub const ALL: &'static [Var] = &[
+        Var::Price,
+        Var::LastPrice,
+        Var::Avg30d,
+        Var::Min30d,
+        Var::Max30d,
+        Var::AllTimeLow,
+        Var::WasInStock,
+        Var::Seller,
+        Var::Name,
+        Var::Target,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Var::Price => "price",
+            Var::LastPrice => "last_price",
+            Var::Avg30d => "avg_30d",
+            Var::Min30d => "min_30d",
+            Var::Max30d => "max_30d",
+            Var::AllTimeLow => "all_time_low",
+            Var::WasInStock => "was_in_stock",
+            Var::Seller => "seller",
+            Var::Name => "name",
+            Var::Target => "target",
+        }
+    }
+
+    fn ty(&self) -> Type {
+        match self {
+            Var::WasInStock => Type::Bool,
+            Var::Seller | Var::Name => Type::Text,
+            _ => Type::Number,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|v| v.name() == name)
+    }
+}
+
+/// 条件式の評価に使う値（今回の観測値と、それより前の履歴の統計）
+pub struct Context<'a> {
+    pub price: i32,
+    pub last_price: Option<i32>,
+    pub avg_30d: Option<f64>,
+    pub min_30d: Option<i32>,
+    pub max_30d: Option<i32>,
+    pub all_time_low: Option<i32>,
+    pub was_in_stock: bool,
+    pub seller: Option<&'a str>,
+    pub name: &'a str,
+    /// 商品に設定した目標価格
+    pub target: Option<i32>,
+}
+
+/// 条件式の構文・型のエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprError {
+    /// 問題のある位置（1始まりの文字数）
+    pub column: usize,
+    pub message: String,
+}
+
+impl ExprError {
+    fn new(column: usize, message: impl Into<String>) -> Self {
+        Self {
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid expression at column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Number,
+    Text,
+    Bool,
+}
+
+impl Type {
+    fn name(&self) -> &'static str {
+        match self {
+            Type::Number => "number",
+            Type::Text => "string",
+            Type::Bool => "condition",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Ident(String),
+    LParen,
+    RParen,
+    Arith(ArithOp),
+    Cmp(CmpOp),
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(n) => format!("number {}", n),
+            Token::Text(s) => format!("string '{}'", s),
+            Token::Ident(s) => format!("'{}'", s),
+            Token::LParen => "'('".to_string(),
+            Token::RParen => "')'".to_string(),
+            Token::Arith(_) | Token::Cmp(_) => "operator".to_string(),
+            Token::End => "end of expression".to_string(),
+        }
+    }
+}
+
+/// 字句に分ける（位置は1始まりの文字数）
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, ExprError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let next = chars.get(i + 1).copied();
+        let token = match c {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '+' => Token::Arith(ArithOp::Add),
+            '-' => Token::Arith(ArithOp::Sub),
+            '*' => Token::Arith(ArithOp::Mul),
+            '/' => Token::Arith(ArithOp::Div),
+            '=' if next == Some('=') => Token::Cmp(CmpOp::Eq),
+            '=' => return Err(ExprError::new(column, "use '==' to compare")),
+            '!' if next == Some('=') => Token::Cmp(CmpOp::Ne),
+            '!' => return Err(ExprError::new(column, "use 'not' to negate a condition")),
+            '<' if next == Some('=') => Token::Cmp(CmpOp::Le),
+            '<' => Token::Cmp(CmpOp::Lt),
+            '>' if next == Some('=') => Token::Cmp(CmpOp::Ge),
+            '>' => Token::Cmp(CmpOp::Gt),
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .ok_or_else(|| ExprError::new(column, "unterminated string"))?;
+                let text: String = chars[i + 1..i + 1 + end].iter().collect();
+                tokens.push((Token::Text(text), column));
+                i += end + 2;
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|ch| ch.is_ascii_digit() || **ch == '.' || **ch == '_')
+                    .count();
+                let text: String = chars[i..i + len].iter().filter(|&&ch| ch != '_').collect();
+                let n = text
+                    .parse()
+                    .map_err(|_| ExprError::new(column, format!("invalid number '{}'", text)))?;
+                tokens.push((Token::Number(n), column));
+                i += len;
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|ch| ch.is_alphanumeric() || **ch == '_')
+                    .count();
+                let word: String = chars[i..i + len].iter().collect();
+                let token = match word.as_str() {
+                    "contains" => Token::Cmp(CmpOp::Contains),
+                    _ => Token::Ident(word),
+                };
+                tokens.push((token, column));
+                i += len;
+                continue;
+            }
+            c => return Err(ExprError::new(column, format!("unexpected character '{}'", c))),
+        };
+
+        let len = match token {
+            Token::Cmp(CmpOp::Eq | CmpOp::Ne | CmpOp::Le | CmpOp::Ge) => 2,
+            _ => 1,
+        };
+        tokens.push((token, column));
+        i += len;
+    }
+
+    tokens.push((Token::End, chars.len() + 1));
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    Var(Var),
+    Neg(Box<Node>),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Arith(ArithOp, Box<Node>, Box<Node>),
+    Compare(CmpOp, Box<Node>, Box<Node>),
+}
+
+/// 括弧・not・単項マイナスの入れ子の上限（深すぎる式でスタックを使い切らないように）
+const MAX_DEPTH: usize = 64;
+
+/// 再帰下降パーサー（構文と同時に型も検査する）
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn column(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn advance(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.pos].clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Ident(word) if word == keyword)
+    }
+
+    /// 入れ子を1段深くして f を実行する
+    fn nested<T>(
+        &mut self,
+        column: usize,
+        f: impl FnOnce(&mut Self) -> Result<T, ExprError>,
+    ) -> Result<T, ExprError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(ExprError::new(
+                column,
+                format!("expression is nested too deeply (max {})", MAX_DEPTH),
+            ));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn expect_type(&self, actual: Type, expected: Type, column: usize, what: &str) -> Result<(), ExprError> {
+        if actual != expected {
+            return Err(ExprError::new(
+                column,
+                format!("{} needs a {}, found a {}", what, expected.name(), actual.name()),
+            ));
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<(Node, Type), ExprError> {
+        let (mut left, mut ty) = self.and()?;
+        while self.at_keyword("or") {
+            let column = self.advance().1;
+            let (right, right_ty) = self.and()?;
+            self.expect_type(ty, Type::Bool, column, "'or'")?;
+            self.expect_type(right_ty, Type::Bool, column, "'or'")?;
+            left = Node::Or(Box::new(left), Box::new(right));
+            ty = Type::Bool;
+        }
+        Ok((left, ty))
+    }
+
+    fn and(&mut self) -> Result<(Node, Type), ExprError> {
+        let (mut left, mut ty) = self.not()?;
+        while self.at_keyword("and") {
+            let column = self.advance().1;
+            let (right, right_ty) = self.not()?;
+            self.expect_type(ty, Type::Bool, column, "'and'")?;
+            self.expect_type(right_ty, Type::Bool, column, "'and'")?;
+            left = Node::And(Box::new(left), Box::new(right));
+            ty = Type::Bool;
+        }
+        Ok((left, ty))
+    }
+
+    fn not(&mut self) -> Result<(Node, Type), ExprError> {
+        if self.at_keyword("not") {
+            let column = self.advance().1;
+            let (operand, ty) = self.nested(column, Self::not)?;
+            self.expect_type(ty, Type::Bool, column, "'not'")?;
+            return Ok((Node::Not(Box::new(operand)), Type::Bool));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<(Node, Type), ExprError> {
+        let (left, left_ty) = self.sum()?;
+        let Token::Cmp(op) = *self.peek() else {
+            return Ok((left, left_ty));
+        };
+        let column = self.advance().1;
+        let (right, right_ty) = self.sum()?;
+
+        match op {
+            CmpOp::Eq | CmpOp::Ne => {
+                if left_ty != right_ty {
+                    return Err(ExprError::new(
+                        column,
+                        format!("cannot compare a {} with a {}", left_ty.name(), right_ty.name()),
+                    ));
+                }
+            }
+            CmpOp::Contains => {
+                self.expect_type(left_ty, Type::Text, column, "'contains'")?;
+                self.expect_type(right_ty, Type::Text, column, "'contains'")?;
+            }
+            _ => {
+                self.expect_type(left_ty, Type::Number, column, "comparison")?;
+                self.expect_type(right_ty, Type::Number, column, "comparison")?;
+            }
+        }
+
+        if matches!(self.peek(), Token::Cmp(_)) {
+            return Err(ExprError::new(
+                self.column(),
+                "comparisons cannot be chained (use 'and')",
+            ));
+        }
+
+        Ok((Node::Compare(op, Box::new(left), Box::new(right)), Type::Bool))
+    }
+
+    fn sum(&mut self) -> Result<(Node, Type), ExprError> {
+        let (mut left, mut ty) = self.term()?;
+        while let Token::Arith(op @ (ArithOp::Add | ArithOp::Sub)) = *self.peek() {
+            let column = self.advance().1;
+            let (right, right_ty) = self.term()?;
+            self.expect_type(ty, Type::Number, column, "arithmetic")?;
+            self.expect_type(right_ty, Type::Number, column, "arithmetic")?;
+            left = Node::Arith(op, Box::new(left), Box::new(right));
+            ty = Type::Number;
+        }
+        Ok((left, ty))
+    }
+
+    fn term(&mut self) -> Result<(Node, Type), ExprError> {
+        let (mut left, mut ty) = self.unary()?;
+        while let Token::Arith(op @ (ArithOp::Mul | ArithOp::Div)) = *self.peek() {
+            let column = self.advance().1;
+            let (right, right_ty) = self.unary()?;
+            self.expect_type(ty, Type::Number, column, "arithmetic")?;
+            self.expect_type(right_ty, Type::Number, column, "arithmetic")?;
+            left = Node::Arith(op, Box::new(left), Box::new(right));
+            ty = Type::Number;
+        }
+        Ok((left, ty))
+    }
+
+    fn unary(&mut self) -> Result<(Node, Type), ExprError> {
+        if *self.peek() == Token::Arith(ArithOp::Sub) {
+            let column = self.advance().1;
+            let (operand, ty) = self.nested(column, Self::unary)?;
+            self.expect_type(ty, Type::Number, column, "'-'")?;
+            return Ok((Node::Neg(Box::new(operand)), Type::Number));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<(Node, Type), ExprError> {
+        let (token, column) = self.advance();
+        match token {
+            Token::Number(n) => Ok((Node::Number(n), Type::Number)),
+            Token::Text(s) => Ok((Node::Text(s), Type::Text)),
+            Token::LParen => {
+                let inner = self.nested(column, Self::or)?;
+                if *self.peek() != Token::RParen {
+                    return Err(ExprError::new(
+                        self.column(),
+                        format!("expected ')', found {}", self.peek().describe()),
+                    ));
+                }
+                self.advance();
+                Ok(inner)
+            }
+            Token::Ident(word) => match word.as_str() {
+                "true" => Ok((Node::Bool(true), Type::Bool)),
+                "false" => Ok((Node::Bool(false), Type::Bool)),
+                "and" | "or" | "not" => Err(ExprError::new(column, format!("expected a value, found '{}'", word))),
+                _ => {
+                    let var = Var::from_name(&word).ok_or_else(|| {
+                        let names: Vec<&str> = Var::ALL.iter().map(Var::name).collect();
+                        ExprError::new(
+                            column,
+                            format!("unknown variable '{}' (available: {})", word, names.join(", ")),
+                        )
+                    })?;
+                    Ok((Node::Var(var), var.ty()))
+                }
+            },
+            other => Err(ExprError::new(column, format!("expected a value, found {}", other.describe()))),
+        }
+    }
+}
+
+/// 評価中の値（履歴がない統計などは Missing）
+enum Value<'a> {
+    Number(f64),
+    Text(&'a str),
+    Bool(bool),
+    Missing,
+}
+
+fn eval<'a>(node: &'a Node, ctx: &Context<'a>) -> Value<'a> {
+    let number = |n: Option<i32>| n.map_or(Value::Missing, |n| Value::Number(n as f64));
+
+    match node {
+        Node::Number(n) => Value::Number(*n),
+        Node::Text(s) => Value::Text(s),
+        Node::Bool(b) => Value::Bool(*b),
+        Node::Var(var) => match var {
+            Var::Price => Value::Number(ctx.price as f64),
+            Var::LastPrice => number(ctx.last_price),
+            Var::Avg30d => ctx.avg_30d.map_or(Value::Missing, Value::Number),
+            Var::Min30d => number(ctx.min_30d),
+            Var::Max30d => number(ctx.max_30d),
+            Var::AllTimeLow => number(ctx.all_time_low),
+            Var::WasInStock => Value::Bool(ctx.was_in_stock),
+            Var::Seller => ctx.seller.map_or(Value::Missing, Value::Text),
+            Var::Name => Value::Text(ctx.name),
+            Var::Target => number(ctx.target),
+        },
+        Node::Neg(operand) => match eval(operand, ctx) {
+            Value::Number(n) => Value::Number(-n),
+            _ => Value::Missing,
+        },
+        // 値がないものは not を通しても成り立たない。and / or は片方で結果が決まるときだけ値を持つ
+        Node::Not(operand) => match eval(operand, ctx) {
+            Value::Bool(b) => Value::Bool(!b),
+            _ => Value::Missing,
+        },
+        Node::And(left, right) => match (eval(left, ctx), eval(right, ctx)) {
+            (Value::Bool(false), _) | (_, Value::Bool(false)) => Value::Bool(false),
+            (Value::Bool(true), Value::Bool(true)) => Value::Bool(true),
+            _ => Value::Missing,
+        },
+        Node::Or(left, right) => match (eval(left, ctx), eval(right, ctx)) {
+            (Value::Bool(true), _) | (_, Value::Bool(true)) => Value::Bool(true),
+            (Value::Bool(false), Value::Bool(false)) => Value::Bool(false),
+            _ => Value::Missing,
+        },
+        Node::Arith(op, left, right) => match (eval(left, ctx), eval(right, ctx)) {
+            (Value::Number(a), Value::Number(b)) => match op {
+                ArithOp::Add => Value::Number(a + b),
+                ArithOp::Sub => Value::Number(a - b),
+                ArithOp::Mul => Value::Number(a * b),
+                ArithOp::Div if b == 0.0 => Value::Missing,
+                ArithOp::Div => Value::Number(a / b),
+            },
+            _ => Value::Missing,
+        },
+        // 値がないものとの比較は成り立たない（not で反転しても成り立たない）
+        Node::Compare(op, left, right) => Value::Bool(match (eval(left, ctx), eval(right, ctx)) {
+            (Value::Missing, _) | (_, Value::Missing) => return Value::Missing,
+            (Value::Number(a), Value::Number(b)) => match op {
+                CmpOp::Eq => a == b,
+                CmpOp::Ne => a != b,
+                CmpOp::Lt => a < b,
+                CmpOp::Le => a <= b,
+                CmpOp::Gt => a > b,
+                CmpOp::Ge => a >= b,
+                CmpOp::Contains => false,
+            },
+            (Value::Text(a), Value::Text(b)) => match op {
+                CmpOp::Eq => a == b,
+                CmpOp::Ne => a != b,
+                CmpOp::Contains => a.to_lowercase().contains(&b.to_lowercase()),
+                _ => false,
+            },
+            (Value::Bool(a), Value::Bool(b)) => match op {
+                CmpOp::Eq => a == b,
+                CmpOp::Ne => a != b,
+                _ => false,
+            },
+            _ => false,
+        }),
+    }
+}
+
+fn truthy(value: Value) -> bool {
+    matches!(value, Value::Bool(true))
+}
+
+/// 検証済みの条件式
+#[derive(Debug, Clone)]
+pub struct Expr {
+    root: Node,
+}
+
+impl Expr {
+    /// 構文と型を検査して読み込む（全体が条件になっていなければエラー）
+    pub fn parse(src: &str) -> Result<Self, ExprError> {
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+            depth: 0,
+        };
+        if *parser.peek() == Token::End {
+            return Err(ExprError::new(1, "expression is empty"));
+        }
+
+        let (root, ty) = parser.or()?;
+        if *parser.peek() != Token::End {
+            return Err(ExprError::new(
+                parser.column(),
+                format!("unexpected {}", parser.peek().describe()),
+            ));
+        }
+        if ty != Type::Bool {
+            return Err(ExprError::new(
+                1,
+                format!("expression must be a condition, found a {}", ty.name()),
+            ));
+        }
+
+        Ok(Self { root })
+    }
+
+    /// 条件を満たすか（値がなく決まらないときは満たさない）
+    pub fn matches(&self, ctx: &Context) -> bool {
+        truthy(eval(&self.root, ctx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> Context<'static> {
+        Context {
+            price: 2400,
+            last_price: Some(2980),
+            avg_30d: Some(2800.0),
+            min_30d: Some(2600),
+            max_30d: Some(3200),
+            all_time_low: Some(2500),
+            was_in_stock: true,
+            seller: Some("Amazon.co.jp"),
+            name: "テスト商品",
+            target: None,
+        }
+    }
+
+    fn check(src: &str) -> bool {
+        Expr::parse(src).unwrap().matches(&ctx())
+    }
+
+    fn error(src: &str) -> ExprError {
+        Expr::parse(src).unwrap_err()
+    }
+
+    #[test]
+    fn evaluates_conditions() {
+        assert!(check("price < 2500 and price < min_30d * 0.95 and seller contains 'amazon'"));
+        assert!(check("(last_price - price) / last_price * 100 >= 15"));
+        assert!(check("price < all_time_low or not was_in_stock"));
+        assert!(check("seller == \"Amazon.co.jp\" and name != 'x'"));
+        assert!(!check("price >= avg_30d"));
+        assert!(check("-price < 0 and 1 + 2 * 3 == 7"));
+    }
+
+    #[test]
+    fn missing_values_never_match() {
+        let mut c = ctx();
+        c.last_price = None;
+        c.seller = None;
+        let cheaper = Expr::parse("price < last_price").unwrap();
+        let not_cheaper = Expr::parse("price >= last_price").unwrap();
+        let seller = Expr::parse("seller != 'Amazon.co.jp'").unwrap();
+        assert!(!cheaper.matches(&c));
+        assert!(!not_cheaper.matches(&c));
+        assert!(!seller.matches(&c));
+        assert!(!Expr::parse("price / 0 < 1").unwrap().matches(&c));
+    }
+
+    #[test]
+    fn missing_values_propagate_through_logic() {
+        let mut c = ctx();
+        c.last_price = None;
+        let matches = |src: &str| Expr::parse(src).unwrap().matches(&c);
+        assert!(!matches("not (price < last_price)"));
+        assert!(!matches("not not (price < last_price)"));
+        assert!(!matches("price < 2500 and price < last_price"));
+        assert!(!matches("not (price < 2500 and price < last_price)"));
+        assert!(!matches("price > 9999 or price < last_price"));
+        assert!(!matches("not (price > 9999 or price < last_price)"));
+        // 片方で決まるときは値がなくても結果が出る
+        assert!(matches("price < 2500 or price < last_price"));
+        assert!(matches("not (price > 9999 and price < last_price)"));
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let nested = |depth: usize| format!("{}price < 1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Expr::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(error(&nested(MAX_DEPTH + 1)).message.contains("nested too deeply"));
+        assert!(error(&nested(200_000)).message.contains("nested too deeply"));
+        assert!(error(&format!("{}true", "not ".repeat(200_000))).message.contains("nested too deeply"));
+        assert!(error(&format!("{}price < 1", "-".repeat(200_000))).message.contains("nested too deeply"));
+    }
+
+    #[test]
+    fn reports_errors_with_column() {
+        assert_eq!(error("prise < 2500").column, 1);
+        assert!(error("prise < 2500").message.contains("unknown variable 'prise'"));
+        assert_eq!(error("price < 'x'").column, 7);
+        assert_eq!(error("price = 2500").column, 7);
+        assert_eq!(error("price < 2500 and").column, 17);
+        assert_eq!(error("(price < 2500").column, 14);
+        assert_eq!(error("1 < price < 3").column, 11);
+        assert_eq!(error("seller == 'Amazon").column, 11);
+    }
+
+    #[test]
+    fn rejects_non_conditions() {
+        assert!(error("price * 2").message.contains("must be a condition"));
+        assert!(error("price and true").message.contains("'and'"));
+        assert!(error("price contains 'a'").message.contains("'contains'"));
+        assert!(error("  ").message.contains("empty"));
+    }
+}
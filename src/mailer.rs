@@ -0,0 +1,262 @@
+use anyhow::{anyhow, bail, Result};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// SMTPサーバーの設定は config.toml の [smtp]（または環境変数 PRICE_MONITOR_SMTP_*）で指定する
+
+/// デフォルトの送信元
+pub const DEFAULT_FROM: &str = "Price Monitor <price-monitor@localhost>";
+const SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// SMTP接続の暗号化方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// 平文で接続してから STARTTLS
+    StartTls,
+    /// 最初から TLS（SMTPS）
+    Tls,
+    /// 暗号化しない（ローカルのテスト用サーバー向け）
+    None,
+}
+
+impl SmtpTls {
+    /// ポートを省略したときに使うポート
+    pub fn default_port(self) -> u16 {
+        match self {
+            SmtpTls::StartTls => 587,
+            SmtpTls::Tls => 465,
+            SmtpTls::None => 25,
+        }
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "starttls" => Ok(SmtpTls::StartTls),
+            "tls" => Ok(SmtpTls::Tls),
+            "none" => Ok(SmtpTls::None),
+            other => bail!("Invalid SMTP TLS mode '{}' (expected starttls, tls or none)", other),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub credentials: Option<(String, String)>,
+    pub from: Mailbox,
+}
+
+static SMTP_CONFIG: OnceLock<Option<SmtpConfig>> = OnceLock::new();
+
+/// SMTPサーバーを設定（起動時に1回だけ。None ならメールは送れない）
+pub fn set_smtp_config(config: Option<SmtpConfig>) {
+    let _ = SMTP_CONFIG.set(config);
+}
+
+impl SmtpConfig {
+    /// 起動時に設定したSMTPサーバー
+    pub fn current() -> Result<Self> {
+        SMTP_CONFIG
+            .get()
+            .cloned()
+            .flatten()
+            .ok_or_else(|| anyhow!("SMTP server is not configured (set smtp.host in config.toml or PRICE_MONITOR_SMTP_HOST)"))
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let builder = match self.tls {
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host),
+        };
+
+        let builder = builder.port(self.port).timeout(Some(SMTP_TIMEOUT));
+        let builder = match &self.credentials {
+            Some((user, password)) => builder.credentials(Credentials::new(user.clone(), password.clone())),
+            None => builder,
+        };
+
+        Ok(builder.build())
+    }
+}
+
+/// 宛先（カンマ区切りで複数指定可）を検証
+pub fn parse_recipients(to: &str) -> Result<Vec<Mailbox>> {
+    let recipients: Vec<Mailbox> = to
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(|addr| {
+            addr.parse()
+                .map_err(|e| anyhow!("Invalid email address '{}': {}", addr, e))
+        })
+        .collect::<Result<_>>()?;
+
+    if recipients.is_empty() {
+        bail!("Email address is required");
+    }
+
+    Ok(recipients)
+}
+
+/// メール送信の失敗
+#[derive(Debug)]
+pub struct SendError {
+    pub message: String,
+    /// 一時的な失敗（接続できない・4xx応答など）で、再試行する価値があるか
+    pub transient: bool,
+}
+
+/// テキストとHTMLの両方を含むメールを送る
+pub async fn send(config: &SmtpConfig, to: &str, subject: &str, text: String, html: String) -> Result<(), SendError> {
+    let permanent = |message: String| SendError {
+        message,
+        transient: false,
+    };
+
+    let mut builder = Message::builder().from(config.from.clone()).subject(subject);
+    for mailbox in parse_recipients(to).map_err(|e| permanent(e.to_string()))? {
+        builder = builder.to(mailbox);
+    }
+    let message = builder
+        .multipart(MultiPart::alternative_plain_html(text, html))
+        .map_err(|e| permanent(e.to_string()))?;
+
+    let transport = config.transport().map_err(|e| permanent(e.to_string()))?;
+    transport.send(message).await.map_err(|e| SendError {
+        // 5xx 応答は送り直しても同じ
+        transient: !e.is_permanent(),
+        message: e.to_string(),
+    })?;
+
+    Ok(())
+}
+
+/// HTMLに埋め込む文字列をエスケープ
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// 受け取ったメール（エンベロープの宛先と DATA の内容）
+    #[derive(Default)]
+    struct Received {
+        recipients: Vec<String>,
+        data: String,
+    }
+
+    /// ローカルのSMTPの受け口（1通だけ受け取る）。`rcpt_reply` を RCPT TO への応答にする
+    async fn smtp_sink(rcpt_reply: &'static str) -> (u16, tokio::task::JoinHandle<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let mut received = Received::default();
+
+            write.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let command = line.to_ascii_uppercase();
+                let reply = if command.starts_with("RCPT TO:") {
+                    if rcpt_reply.starts_with("250") {
+                        received.recipients.push(line[8..].trim().to_string());
+                    }
+                    rcpt_reply
+                } else if command.starts_with("DATA") {
+                    write.write_all(b"354 go ahead\r\n").await.unwrap();
+                    while let Some(data) = lines.next_line().await.unwrap() {
+                        if data == "." {
+                            break;
+                        }
+                        received.data.push_str(&data);
+                        received.data.push('\n');
+                    }
+                    "250 queued\r\n"
+                } else if command.starts_with("QUIT") {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    "250 ok\r\n"
+                };
+                write.write_all(reply.as_bytes()).await.unwrap();
+            }
+            received
+        });
+
+        (port, handle)
+    }
+
+    fn config(port: u16) -> SmtpConfig {
+        SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::None,
+            credentials: None,
+            from: DEFAULT_FROM.parse().unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_text_and_html_to_every_recipient() {
+        let (port, sink) = smtp_sink("250 ok\r\n").await;
+
+        send(
+            &config(port),
+            "a@example.com, b@example.com",
+            "Price alert",
+            "plain body".to_string(),
+            "<p>html body</p>".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let received = sink.await.unwrap();
+        assert_eq!(received.recipients, ["<a@example.com>", "<b@example.com>"]);
+        assert!(received.data.contains("Subject: Price alert"));
+        assert!(received.data.contains("<price-monitor@localhost>"));
+        assert!(received.data.contains("multipart/alternative"));
+        assert!(received.data.contains("plain body"));
+        assert!(received.data.contains("<p>html body</p>"));
+    }
+
+    #[tokio::test]
+    async fn rejected_recipient_is_a_permanent_failure() {
+        let (port, _sink) = smtp_sink("550 no such user\r\n").await;
+
+        let error = send(&config(port), "nobody@example.com", "Price alert", "text".to_string(), "<p>html</p>".to_string())
+            .await
+            .unwrap_err();
+
+        assert!(!error.transient, "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn invalid_recipient_is_rejected_before_connecting() {
+        let error = send(&config(1), "not an address", "Price alert", "text".to_string(), "html".to_string())
+            .await
+            .unwrap_err();
+
+        assert!(!error.transient);
+        assert!(error.message.contains("Invalid email address"));
+    }
+}
@@ -4,10 +4,25 @@ mod db;
 mod scraper;
 mod web;
 mod scheduler;
+mod retention;
+mod timestamp;
+mod backup;
+mod doctor;
+mod runs;
+mod alerts;
+mod notify;
+mod mailer;
+mod digest;
+mod expr;
+mod metrics;
+mod config;
+mod search;
+mod tags;
+mod edit;
 
 use clap::Parser;
-use cli::{Cli, Commands};
-use tracing_subscriber;
+use cli::{Cli, Commands, ConfigCommands, NotifyCommands, RulesCommands, SchedulerCommands, TagCommands};
+use edit::ProductEdit;
 
 fn main() -> anyhow::Result<()> {
     // 🆕 ログ設定を初期化
@@ -18,6 +33,8 @@ fn main() -> anyhow::Result<()> {
         .init();
 
     let cli = Cli::parse();
+    let config = config::Config::load(&cli)?;
+    config.apply()?;
     let rt = tokio::runtime::Runtime::new()?;
 
     match cli.command {
@@ -27,24 +44,182 @@ fn main() -> anyhow::Result<()> {
                 commands::cmd_add(&conn, &url).await
             })?;
         }
-        Some(Commands::List) => {
+        Some(Commands::List { archived, tag }) => {
             let conn = db::init_db()?;
-            commands::cmd_list(&conn)?;
+            commands::cmd_list(&conn, archived, tag.as_deref())?;
         }
-        Some(Commands::Check) => {
+        Some(Commands::Search { query, archived, limit }) => {
+            let conn = db::init_db()?;
+            commands::cmd_search(&conn, &query, archived, limit)?;
+        }
+        Some(Commands::History { id, from, to }) => {
+            let conn = db::init_db()?;
+            commands::cmd_history(&conn, id, from.as_deref(), to.as_deref())?;
+        }
+        Some(Commands::Check { tag }) => {
             let conn = db::init_db()?;
             rt.block_on(async {
-                commands::cmd_check(&conn).await
+                commands::cmd_check(&conn, tag.as_deref()).await
             })?;
         }
-        Some(Commands::Export { filename }) => {
+        Some(Commands::Tag { action }) => {
+            let conn = db::init_db()?;
+            match action {
+                TagCommands::Add { id, tags } => commands::cmd_tag_add(&conn, id, &tags)?,
+                TagCommands::Remove { id, tags } => commands::cmd_tag_remove(&conn, id, &tags)?,
+                TagCommands::List => commands::cmd_tag_list(&conn)?,
+            }
+        }
+        Some(Commands::Rules { action }) => {
+            let conn = db::init_db()?;
+            match action {
+                RulesCommands::Add { product, kind, threshold, expression, channels, cooldown, no_rearm } => {
+                    let options = alerts::RuleOptions {
+                        cooldown_minutes: cooldown,
+                        rearm: !no_rearm,
+                    };
+                    commands::cmd_rules_add(&conn, product, kind, threshold, expression.as_deref(), options, &channels)?
+                }
+                RulesCommands::Channels { id, channels } => {
+                    commands::cmd_rules_channels(&conn, id, &channels)?
+                }
+                RulesCommands::List { product } => commands::cmd_rules_list(&conn, product)?,
+                RulesCommands::Remove { id } => commands::cmd_rules_remove(&conn, id)?,
+                RulesCommands::Test { product, expression, rule } => {
+                    commands::cmd_rules_test(&conn, product, expression.as_deref(), rule)?
+                }
+            }
+        }
+        Some(Commands::Notify { action }) => {
+            let conn = db::init_db()?;
+            match action {
+                NotifyCommands::Add { kind, target, secret, digest } => {
+                    commands::cmd_notify_add(&conn, kind, &target, secret.as_deref(), digest)?
+                }
+                NotifyCommands::List => commands::cmd_notify_list(&conn)?,
+                NotifyCommands::Remove { id } => commands::cmd_notify_remove(&conn, id)?,
+                NotifyCommands::Test { id } => {
+                    rt.block_on(async { commands::cmd_notify_test(&conn, id).await })?
+                }
+                NotifyCommands::Digest { hours, send } => {
+                    rt.block_on(async { commands::cmd_notify_digest(&conn, hours, send).await })?
+                }
+                NotifyCommands::Log { limit } => commands::cmd_notify_log(&conn, limit)?,
+            }
+        }
+        Some(Commands::Scheduler { action }) => {
+            let conn = db::init_db()?;
+            match action {
+                SchedulerCommands::Status => commands::cmd_scheduler_status(&conn)?,
+                SchedulerCommands::Pause => commands::cmd_scheduler_pause(&conn)?,
+                SchedulerCommands::Resume => commands::cmd_scheduler_resume(&conn)?,
+                SchedulerCommands::Run => commands::cmd_scheduler_run(&conn)?,
+            }
+        }
+        Some(Commands::Alerts { product, limit }) => {
+            let conn = db::init_db()?;
+            commands::cmd_alerts(&conn, product, limit)?;
+        }
+        Some(Commands::Archive { id }) => {
+            let conn = db::init_db()?;
+            commands::cmd_archive(&conn, id)?;
+        }
+        Some(Commands::Unarchive { id }) => {
+            let conn = db::init_db()?;
+            commands::cmd_unarchive(&conn, id)?;
+        }
+        Some(Commands::Delete { id }) => {
+            let conn = db::init_db()?;
+            commands::cmd_delete(&conn, id)?;
+        }
+        Some(Commands::Runs { id, product, limit }) => {
             let conn = db::init_db()?;
-            commands::cmd_export(&conn, &filename)?;
+            commands::cmd_runs(&conn, id, product, limit)?;
+        }
+        Some(Commands::Export { filename, tag }) => {
+            let conn = db::init_db()?;
+            commands::cmd_export(&conn, &filename, tag.as_deref())?;
+        }
+        Some(Commands::Backup { file }) => {
+            let conn = db::init_db()?;
+            commands::cmd_backup(&conn, &file)?;
+        }
+        Some(Commands::Restore { file }) => {
+            commands::cmd_restore(&file)?;
+        }
+        Some(Commands::Doctor { repair }) => {
+            let conn = db::init_db()?;
+            commands::cmd_doctor(&conn, repair)?;
+        }
+        Some(Commands::Interval { id, interval }) => {
+            let conn = db::init_db()?;
+            commands::cmd_interval(&conn, id, interval)?;
+        }
+        Some(Commands::Edit {
+            id,
+            name,
+            url,
+            target,
+            no_target,
+            tags,
+            interval,
+            auto_interval,
+            enable,
+            disable,
+            category,
+            note,
+        }) => {
+            let conn = db::init_db()?;
+            // 指定されなかった項目は None（変更しない）、解除は Some(None)
+            let edit = ProductEdit {
+                display_name: name.map(Some),
+                url,
+                target_price: if no_target { Some(None) } else { target.map(Some) },
+                tags: tags.map(|t| {
+                    t.split(',')
+                        .map(str::trim)
+                        .filter(|t| !t.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }),
+                check_interval_minutes: if auto_interval {
+                    Some(None)
+                } else {
+                    interval.map(|i| Some(i.minutes))
+                },
+                enabled: (enable || disable).then_some(enable),
+                category: category.map(Some),
+                note: note.map(Some),
+            };
+            rt.block_on(commands::cmd_edit(&conn, id, edit))?;
+        }
+        Some(Commands::Prune { .. }) => {
+            let conn = db::init_db()?;
+            commands::cmd_prune(&conn, retention::retention_days())?;
+        }
+        Some(Commands::Serve { no_scheduler }) => {
+            if no_scheduler {
+                println!("🌐 Starting Web UI...");
+            } else {
+                println!("🌐 Starting Web UI with auto price check...");
+            }
+            rt.block_on(async {
+                web::run_server(config.server, !no_scheduler).await
+            })?;
+        }
+        Some(Commands::Daemon { .. }) => {
+            println!("🕐 Starting price check daemon...");
+            rt.block_on(async {
+                scheduler::run_daemon(config.metrics).await
+            })?;
         }
+        Some(Commands::Config { action }) => match action {
+            ConfigCommands::Show => print!("{}", config.render()),
+        },
         None => {
             println!("🌐 Starting Web UI with auto price check...");
             rt.block_on(async {
-                web::run_server().await
+                web::run_server(config.server, true).await
             })?;
         }
     }
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use axum::{extract::State, http::{header, StatusCode}, response::IntoResponse, routing::get, Router};
+use rusqlite::{Connection, OptionalExtension};
+use std::fmt::Write;
+use tracing::error;
+
+use crate::scheduler::{self, SharedDb};
+use crate::timestamp;
+
+/// メトリクスのエンドポイント（GET /metrics、Prometheus のテキスト形式）
+pub fn create_router(db: SharedDb) -> Router {
+    Router::new().route("/metrics", get(get_metrics)).with_state(db)
+}
+
+// GET /metrics - 商品・スケジューラー・価格チェックの状態
+async fn get_metrics(State(db): State<SharedDb>) -> Result<impl IntoResponse, StatusCode> {
+    let conn = db.lock().unwrap();
+    let body = render(&conn).map_err(|e| {
+        error!("❌ メトリクスの作成エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body))
+}
+
+/// 1つのメトリクス（HELP と TYPE の行と、ラベルごとの値）を書き出す
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(String, f64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+}
+
+fn bool_value(value: bool) -> f64 {
+    if value { 1.0 } else { 0.0 }
+}
+
+/// メトリクスをテキスト形式で作る
+pub fn render(conn: &Connection) -> Result<String> {
+    let mut out = String::new();
+
+    let (active, needs_attention, out_of_stock, archived): (i64, i64, i64, i64) = conn.query_row(
+        "SELECT COUNT(*) FILTER (WHERE archived_at IS NULL),
+                COUNT(*) FILTER (WHERE archived_at IS NULL AND needs_attention_at IS NOT NULL),
+                COUNT(*) FILTER (WHERE archived_at IS NULL AND in_stock = 0),
+                COUNT(*) FILTER (WHERE archived_at IS NOT NULL)
+         FROM products",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    metric(&mut out, "price_monitor_products", "gauge", "Number of products by state", &[
+        ("{state=\"active\"}".to_string(), active as f64),
+        ("{state=\"needs_attention\"}".to_string(), needs_attention as f64),
+        ("{state=\"out_of_stock\"}".to_string(), out_of_stock as f64),
+        ("{state=\"archived\"}".to_string(), archived as f64),
+    ]);
+
+    let status = scheduler::status(conn)?;
+    metric(&mut out, "price_monitor_products_due", "gauge", "Number of products whose next check is due", &[
+        (String::new(), status.due as f64),
+    ]);
+    metric(&mut out, "price_monitor_scheduler_paused", "gauge", "Whether scheduled price checks are paused", &[
+        (String::new(), bool_value(status.paused_at.is_some())),
+    ]);
+    metric(&mut out, "price_monitor_check_running", "gauge", "Whether a price check run is in progress", &[
+        (String::new(), bool_value(status.running.is_some())),
+    ]);
+    if let Some(next_check_at) = status.next_check_at.as_deref().and_then(|s| timestamp::parse(s).ok()) {
+        metric(&mut out, "price_monitor_next_check_timestamp_seconds", "gauge", "Time of the next scheduled product check", &[
+            (String::new(), next_check_at.timestamp() as f64),
+        ]);
+    }
+
+    let last_run: Option<(String, i64, i64, bool)> = conn
+        .query_row(
+            "SELECT finished_at, succeeded, failed, interrupted FROM check_runs
+             WHERE finished_at IS NOT NULL
+             ORDER BY finished_at DESC, id DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+    if let Some((finished_at, succeeded, failed, interrupted)) = last_run {
+        metric(&mut out, "price_monitor_last_run_timestamp_seconds", "gauge", "Time the last price check run finished", &[
+            (String::new(), timestamp::parse(&finished_at)?.timestamp() as f64),
+        ]);
+        metric(&mut out, "price_monitor_last_run_products", "gauge", "Products checked in the last price check run by result", &[
+            ("{result=\"succeeded\"}".to_string(), succeeded as f64),
+            ("{result=\"failed\"}".to_string(), failed as f64),
+        ]);
+        metric(&mut out, "price_monitor_last_run_interrupted", "gauge", "Whether the last price check run was interrupted", &[
+            (String::new(), bool_value(interrupted)),
+        ]);
+    }
+
+    let mut stmt = conn.prepare("SELECT status, COUNT(*) FROM check_attempts GROUP BY status ORDER BY status")?;
+    let attempts = stmt
+        .query_map([], |row| {
+            let status: String = row.get(0)?;
+            let count: i64 = row.get(1)?;
+            Ok((format!("{{status=\"{}\"}}", status), count as f64))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    metric(&mut out, "price_monitor_check_attempts_total", "counter", "Product check attempts by status", &attempts);
+
+    let alerts: i64 = conn.query_row("SELECT COUNT(*) FROM alert_events", [], |row| row.get(0))?;
+    metric(&mut out, "price_monitor_alerts_total", "counter", "Alerts triggered by alert rules", &[
+        (String::new(), alerts as f64),
+    ]);
+
+    Ok(out)
+}
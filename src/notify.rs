@@ -0,0 +1,1002 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, NaiveTime, Utc};
+use hmac::{Hmac, Mac};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::alerts::{RuleKind, TriggeredAlert};
+use crate::digest::Digest;
+use crate::mailer::{self, escape_html, SmtpConfig};
+use crate::timestamp;
+
+/// 1件の通知を送るデフォルトの最大試行回数
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+/// 通知のリクエストを諦めるまでのデフォルトの時間（秒）
+pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+/// 再試行までの待ち時間（試行ごとに倍にする）
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// 署名ヘッダー（`sha256=<本文のHMAC-SHA256の16進数>`）
+pub const SIGNATURE_HEADER: &str = "X-Price-Monitor-Signature";
+
+/// LINE Notify の送信先（アクセストークンは `secret` に保存する）
+const LINE_NOTIFY_URL: &str = "https://notify-api.line.me/api/notify";
+/// Discord の埋め込みの色（オレンジ）
+const DISCORD_EMBED_COLOR: u32 = 0xE67E22;
+
+/// 通知を送らない時間帯（例: `22:00-07:00`、表示用のタイムゾーンの時刻）
+///
+/// この間に発火したアラートは記録だけして、時間帯が終わってからまとめて送る。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    /// 時刻が静かな時間帯に入っているか（日付をまたぐ指定にも対応）
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Invalid quiet hours '{}' (expected HH:MM-HH:MM)", s))?;
+        let parse = |t: &str| {
+            NaiveTime::parse_from_str(t.trim(), "%H:%M")
+                .map_err(|_| anyhow!("Invalid time '{}' in quiet hours (expected HH:MM)", t.trim()))
+        };
+
+        let quiet = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if quiet.start == quiet.end {
+            bail!("Quiet hours start and end must differ: {}", s);
+        }
+        Ok(quiet)
+    }
+}
+
+impl std::fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+static QUIET_HOURS: OnceLock<Option<QuietHours>> = OnceLock::new();
+
+/// 静かな時間帯を設定（起動時に1回だけ）
+pub fn set_quiet_hours(quiet: Option<QuietHours>) {
+    let _ = QUIET_HOURS.set(quiet);
+}
+
+/// 通知の送り方の設定
+#[derive(Debug, Clone, Copy)]
+pub struct DeliverySettings {
+    /// 1件の通知を送る最大試行回数
+    pub max_attempts: u32,
+    pub timeout: Duration,
+}
+
+static DELIVERY_SETTINGS: OnceLock<DeliverySettings> = OnceLock::new();
+
+/// 通知の送り方を設定（起動時に1回だけ）
+pub fn set_delivery_settings(settings: DeliverySettings) {
+    let _ = DELIVERY_SETTINGS.set(settings);
+}
+
+fn delivery_settings() -> DeliverySettings {
+    *DELIVERY_SETTINGS.get_or_init(|| DeliverySettings {
+        max_attempts: DEFAULT_MAX_ATTEMPTS,
+        timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+    })
+}
+
+/// 指定した時刻が静かな時間帯か
+pub fn is_quiet_at(now: DateTime<Utc>) -> bool {
+    QUIET_HOURS
+        .get()
+        .copied()
+        .flatten()
+        .is_some_and(|quiet| quiet.contains(now.with_timezone(&timestamp::timezone()).time()))
+}
+
+/// 通知先の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelKind {
+    /// JSON を POST する汎用 Webhook
+    Webhook,
+    /// Slack の Incoming Webhook（Block Kit）
+    Slack,
+    /// Discord の Webhook（埋め込み）
+    Discord,
+    /// LINE Notify（target にアクセストークンを指定）
+    Line,
+    /// メール（target に宛先アドレス。カンマ区切りで複数指定可）
+    Email,
+}
+
+impl ChannelKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChannelKind::Webhook => "webhook",
+            ChannelKind::Slack => "slack",
+            ChannelKind::Discord => "discord",
+            ChannelKind::Line => "line",
+            ChannelKind::Email => "email",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "webhook" => ChannelKind::Webhook,
+            "slack" => ChannelKind::Slack,
+            "discord" => ChannelKind::Discord,
+            "line" => ChannelKind::Line,
+            "email" => ChannelKind::Email,
+            _ => bail!("Unknown channel kind: {}", s),
+        })
+    }
+}
+
+impl rusqlite::types::FromSql for ChannelKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        ChannelKind::parse(value.as_str()?).map_err(|e| rusqlite::types::FromSqlError::Other(e.into()))
+    }
+}
+
+#[derive(Serialize)]
+pub struct Channel {
+    pub id: i64,
+    pub kind: ChannelKind,
+    pub target: String,
+    #[serde(skip)]
+    pub secret: Option<String>,
+    pub enabled: bool,
+    /// 日次レポートも送る（メールのみ）
+    pub digest: bool,
+    pub created_at: String,
+}
+
+#[derive(Serialize)]
+pub struct DeliveryLog {
+    pub id: i64,
+    pub alert_event_id: i64,
+    pub channel_id: i64,
+    pub attempt: u32,
+    pub status: String,
+    pub http_status: Option<u16>,
+    pub error: Option<String>,
+    pub attempted_at: String,
+}
+
+/// 1回の送信試行の結果
+pub struct Delivery {
+    pub alert_event_id: i64,
+    pub channel_id: i64,
+    pub attempt: u32,
+    pub http_status: Option<u16>,
+    pub error: Option<String>,
+    pub attempted_at: String,
+}
+
+impl Delivery {
+    pub fn delivered(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Webhook に POST する JSON
+#[derive(Serialize)]
+struct AlertPayload<'a> {
+    event: &'static str,
+    alert_id: i64,
+    message: &'a str,
+    rule: RulePayload<'a>,
+    product: ProductPayload<'a>,
+    old_price: Option<i32>,
+    new_price: i32,
+    triggered_at: String,
+}
+
+#[derive(Serialize)]
+struct RulePayload<'a> {
+    id: i64,
+    kind: RuleKind,
+    threshold: Option<f64>,
+    expression: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ProductPayload<'a> {
+    id: i64,
+    name: &'a str,
+    url: &'a str,
+}
+
+fn payload(alert: &TriggeredAlert) -> AlertPayload<'_> {
+    AlertPayload {
+        event: "price_alert",
+        alert_id: alert.event_id,
+        message: &alert.message,
+        rule: RulePayload {
+            id: alert.rule_id,
+            kind: alert.kind,
+            threshold: alert.threshold,
+            expression: alert.expression.as_deref(),
+        },
+        product: ProductPayload {
+            id: alert.product_id,
+            name: &alert.product_name,
+            url: &alert.url,
+        },
+        old_price: alert.old_price,
+        new_price: alert.new_price,
+        triggered_at: timestamp::render(&alert.triggered_at),
+    }
+}
+
+/// 価格の変化（例: `¥2480 → ¥1980（-¥500 / -20.2%）`）
+pub fn price_change(old_price: Option<i32>, new_price: i32) -> String {
+    match old_price {
+        Some(old) if old > 0 && old != new_price => {
+            let diff = new_price - old;
+            format!(
+                "¥{} → ¥{}（{}¥{} / {:+.1}%）",
+                old,
+                new_price,
+                if diff < 0 { "-" } else { "+" },
+                diff.abs(),
+                diff as f64 / old as f64 * 100.0
+            )
+        }
+        Some(_) => format!("¥{}（変動なし）", new_price),
+        None => format!("¥{}", new_price),
+    }
+}
+
+/// 文字数の上限を超えたら末尾を省略
+fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        return s.to_string();
+    }
+    let mut truncated: String = s.chars().take(max_chars - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Slack の mrkdwn で特別な意味を持つ文字をエスケープ
+fn slack_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Slack の `<url|name>` 形式のリンク。`|` はリンクの区切りになるので、
+/// URL ではパーセントエンコードし、表示名では全角に置き換える
+fn slack_link(url: &str, name: &str) -> String {
+    format!(
+        "<{}|{}>",
+        slack_escape(url).replace('|', "%7C"),
+        slack_escape(name).replace('|', "｜")
+    )
+}
+
+/// Slack の Block Kit メッセージ
+fn slack_payload(alert: &TriggeredAlert) -> serde_json::Value {
+    let text = format!(
+        "*{}*\n{}\n{}",
+        slack_link(&alert.url, &truncate(&alert.product_name, 200)),
+        slack_escape(&alert.message),
+        price_change(alert.old_price, alert.new_price)
+    );
+
+    let mut section = serde_json::json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": text },
+    });
+    if let Some(image_url) = &alert.image_url {
+        section["accessory"] = serde_json::json!({
+            "type": "image",
+            "image_url": image_url,
+            "alt_text": truncate(&alert.product_name, 100),
+        });
+    }
+
+    serde_json::json!({
+        // 通知のプレビューなど、ブロックを表示できない場所で使われる
+        "text": format!("🔔 {}", alert.message),
+        "blocks": [
+            section,
+            {
+                "type": "context",
+                "elements": [{
+                    "type": "mrkdwn",
+                    "text": format!(
+                        "{} ・ {}",
+                        alert.kind.describe(alert.threshold, alert.expression.as_deref()),
+                        timestamp::display(&alert.triggered_at)
+                    ),
+                }],
+            },
+        ],
+    })
+}
+
+/// Discord の埋め込みメッセージ
+fn discord_payload(alert: &TriggeredAlert) -> serde_json::Value {
+    let mut embed = serde_json::json!({
+        "title": truncate(&alert.product_name, 256),
+        "url": alert.url,
+        "description": alert.message,
+        "color": DISCORD_EMBED_COLOR,
+        "fields": [
+            { "name": "価格", "value": price_change(alert.old_price, alert.new_price) },
+        ],
+        "footer": { "text": alert.kind.describe(alert.threshold, alert.expression.as_deref()) },
+        "timestamp": timestamp::render(&alert.triggered_at),
+    });
+    if let Some(image_url) = &alert.image_url {
+        embed["thumbnail"] = serde_json::json!({ "url": image_url });
+    }
+
+    serde_json::json!({ "embeds": [embed] })
+}
+
+/// LINE Notify のフォーム（メッセージは1000文字まで）
+fn line_form(alert: &TriggeredAlert) -> Vec<(&'static str, String)> {
+    // LINE ではトークン名の後に続けて表示されるので改行から始める
+    let message = format!(
+        "\n🔔 {}\n{}\n{}\n{}",
+        alert.message,
+        price_change(alert.old_price, alert.new_price),
+        truncate(&alert.product_name, 200),
+        alert.url
+    );
+
+    let mut form = vec![("message", truncate(&message, 1000))];
+    if let Some(image_url) = &alert.image_url {
+        form.push(("imageThumbnail", image_url.clone()));
+        form.push(("imageFullsize", image_url.clone()));
+    }
+    form
+}
+
+/// アラートのメール（件名, テキスト, HTML）
+fn alert_email(alert: &TriggeredAlert) -> (String, String, String) {
+    let subject = format!("🔔 {} - {}", alert.message, truncate(&alert.product_name, 40));
+    let change = price_change(alert.old_price, alert.new_price);
+    let condition = alert.kind.describe(alert.threshold, alert.expression.as_deref());
+    let triggered_at = timestamp::display(&alert.triggered_at);
+
+    let text = format!(
+        "{}\n\n商品: {}\n価格: {}\n条件: {}\n日時: {}\n{}\n",
+        alert.message, alert.product_name, change, condition, triggered_at, alert.url
+    );
+
+    let image = alert
+        .image_url
+        .as_ref()
+        .map(|url| format!("<p><img src=\"{}\" alt=\"\" width=\"160\"></p>", escape_html(url)))
+        .unwrap_or_default();
+    let html = format!(
+        "<html><body style=\"font-family:sans-serif\">\
+         <h2>🔔 {}</h2>\
+         <p><a href=\"{}\">{}</a></p>{}\
+         <p>価格: <strong>{}</strong><br>条件: {}<br>日時: {}</p>\
+         </body></html>",
+        escape_html(&alert.message),
+        escape_html(&alert.url),
+        escape_html(&alert.product_name),
+        image,
+        escape_html(&change),
+        escape_html(&condition),
+        triggered_at
+    );
+
+    (subject, text, html)
+}
+
+/// 本文の HMAC-SHA256 署名（16進数）
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// 1回送信する。失敗したら (HTTPステータス, エラー内容, 再試行するか) を返す
+async fn send_once(
+    client: &reqwest::Client,
+    smtp: &Result<SmtpConfig, String>,
+    channel: &Channel,
+    alert: &TriggeredAlert,
+) -> Result<Option<u16>, (Option<u16>, String, bool)> {
+    if channel.kind != ChannelKind::Email {
+        return send_http(client, channel, alert).await.map(Some);
+    }
+
+    let smtp = smtp.as_ref().map_err(|e| (None, e.clone(), false))?;
+    let (subject, text, html) = alert_email(alert);
+    mailer::send(smtp, &channel.target, &subject, text, html)
+        .await
+        .map(|_| None)
+        .map_err(|e| (None, e.message, e.transient))
+}
+
+async fn send_http(
+    client: &reqwest::Client,
+    channel: &Channel,
+    alert: &TriggeredAlert,
+) -> Result<u16, (Option<u16>, String, bool)> {
+    let json = |value: &serde_json::Value| -> Result<Vec<u8>, (Option<u16>, String, bool)> {
+        serde_json::to_vec(value).map_err(|e| (None, e.to_string(), false))
+    };
+    let json_request = |body: Vec<u8>| {
+        client
+            .post(&channel.target)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+    };
+
+    let request = match channel.kind {
+        ChannelKind::Webhook => {
+            let body = serde_json::to_vec(&payload(alert)).map_err(|e| (None, e.to_string(), false))?;
+            let signature = channel.secret.as_ref().map(|secret| format!("sha256={}", sign(secret, &body)));
+            let request = json_request(body);
+            match signature {
+                Some(signature) => request.header(SIGNATURE_HEADER, signature),
+                None => request,
+            }
+        }
+        ChannelKind::Slack => json_request(json(&slack_payload(alert))?),
+        ChannelKind::Discord => json_request(json(&discord_payload(alert))?),
+        ChannelKind::Line => client
+            .post(&channel.target)
+            .bearer_auth(channel.secret.as_deref().unwrap_or_default())
+            .form(&line_form(alert)),
+        ChannelKind::Email => unreachable!("email channels are sent over SMTP"),
+    };
+
+    let resp = request
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string(), true))?;
+
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(status.as_u16());
+    }
+
+    // 4xx は送り直しても同じなので、429 以外は再試行しない
+    let retry = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+    Err((Some(status.as_u16()), format!("HTTP {}", status), retry))
+}
+
+/// 発火したアラートを全ての通知先に送る（失敗したら間隔をあけて再試行）
+///
+/// DBには触らないので、ロックを持たずに呼べる。結果は `record_deliveries` で保存する。
+/// 静かな時間帯に溜めたアラートはここでは送らない（`alerts::queued_alerts` で後から取り出す）。
+pub async fn deliver(channels: &[Channel], alerts: &[TriggeredAlert]) -> Vec<Delivery> {
+    let alerts: Vec<&TriggeredAlert> = alerts.iter().filter(|a| !a.queued).collect();
+    let mut deliveries = Vec::new();
+    if channels.is_empty() || alerts.is_empty() {
+        return deliveries;
+    }
+
+    let settings = delivery_settings();
+    let client = match reqwest::Client::builder().timeout(settings.timeout).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("⚠️  通知用のHTTPクライアントを作成できません: {}", e);
+            return deliveries;
+        }
+    };
+    // SMTPの設定はメールの通知先があるときだけ読み込む
+    let smtp = smtp_config(channels);
+
+    for alert in alerts {
+        // ルールに通知先が設定されていればそれだけに送る
+        let targets = channels
+            .iter()
+            .filter(|c| c.enabled)
+            .filter(|c| alert.channel_ids.is_empty() || alert.channel_ids.contains(&c.id));
+
+        for channel in targets {
+            for attempt in 1..=settings.max_attempts {
+                let result = send_once(&client, &smtp, channel, alert).await;
+                let (http_status, error, retry) = match result {
+                    Ok(status) => (status, None, false),
+                    Err((status, error, retry)) => (status, Some(error), retry),
+                };
+
+                if let Some(error) = &error {
+                    warn!("⚠️  通知失敗 (通知先 {} / {}回目): {}", channel.id, attempt, error);
+                }
+
+                deliveries.push(Delivery {
+                    alert_event_id: alert.event_id,
+                    channel_id: channel.id,
+                    attempt,
+                    http_status,
+                    error,
+                    attempted_at: timestamp::now(),
+                });
+
+                if !retry || attempt == settings.max_attempts {
+                    break;
+                }
+                tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+            }
+        }
+    }
+
+    deliveries
+}
+
+fn smtp_config(channels: &[Channel]) -> Result<SmtpConfig, String> {
+    if !channels.iter().any(|c| c.enabled && c.kind == ChannelKind::Email) {
+        return Err("No email channels".to_string());
+    }
+    SmtpConfig::current().map_err(|e| e.to_string())
+}
+
+/// 日次レポートを、レポートを受け取るメールの通知先に送る
+///
+/// 戻り値は通知先ごとの結果。DBには触らない。
+pub async fn deliver_digest(channels: &[Channel], digest: &Digest) -> Vec<(i64, Result<(), String>)> {
+    let targets: Vec<&Channel> = channels
+        .iter()
+        .filter(|c| c.enabled && c.digest && c.kind == ChannelKind::Email)
+        .collect();
+    if targets.is_empty() {
+        return Vec::new();
+    }
+
+    let smtp = match SmtpConfig::current() {
+        Ok(smtp) => smtp,
+        Err(e) => return targets.iter().map(|c| (c.id, Err(e.to_string()))).collect(),
+    };
+
+    let subject = digest.subject();
+    let max_attempts = delivery_settings().max_attempts;
+    let mut results = Vec::new();
+
+    for channel in targets {
+        let mut result = Ok(());
+        for attempt in 1..=max_attempts {
+            match mailer::send(&smtp, &channel.target, &subject, digest.to_text(), digest.to_html()).await {
+                Ok(()) => {
+                    result = Ok(());
+                    break;
+                }
+                Err(e) => {
+                    warn!("⚠️  日次レポートの送信失敗 (通知先 {} / {}回目): {}", channel.id, attempt, e.message);
+                    result = Err(e.message);
+                    if !e.transient || attempt == max_attempts {
+                        break;
+                    }
+                }
+            }
+            tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+        }
+        results.push((channel.id, result));
+    }
+
+    results
+}
+
+/// 送信結果を配信ログに保存
+pub fn record_deliveries(conn: &Connection, deliveries: &[Delivery]) -> Result<()> {
+    for d in deliveries {
+        conn.execute(
+            "INSERT INTO notify_deliveries
+                 (alert_event_id, channel_id, attempt, status, http_status, error, attempted_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                d.alert_event_id,
+                d.channel_id,
+                d.attempt,
+                if d.delivered() { "delivered" } else { "failed" },
+                d.http_status,
+                d.error,
+                d.attempted_at,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// 通知先を追加
+///
+/// `target` は送信先のURL。LINE Notify の場合はアクセストークンを指定する。
+/// `secret`（HMAC署名）は汎用 Webhook、`digest`（日次レポート）はメールのみ対応。
+pub fn add_channel(
+    conn: &Connection,
+    kind: ChannelKind,
+    target: &str,
+    secret: Option<&str>,
+    digest: bool,
+) -> Result<i64> {
+    if secret.is_some() && kind != ChannelKind::Webhook {
+        bail!("Signing secret is only supported for webhook channels");
+    }
+    if digest && kind != ChannelKind::Email {
+        bail!("Daily digest is only supported for email channels");
+    }
+
+    let (target, secret) = match kind {
+        ChannelKind::Line => {
+            if target.trim().is_empty() {
+                bail!("LINE Notify access token is required");
+            }
+            (LINE_NOTIFY_URL, Some(target.trim()))
+        }
+        ChannelKind::Email => {
+            mailer::parse_recipients(target)?;
+            (target, None)
+        }
+        _ => {
+            let url = reqwest::Url::parse(target)
+                .map_err(|e| anyhow::anyhow!("Invalid URL '{}': {}", target, e))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                bail!("Webhook URL must be http or https: {}", target);
+            }
+            (target, secret)
+        }
+    };
+
+    conn.execute(
+        "INSERT INTO notify_channels (kind, target, secret, digest, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        (kind.as_str(), target, secret, digest, timestamp::now()),
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn remove_channel(conn: &Connection, channel_id: i64) -> Result<bool> {
+    let deleted = conn.execute("DELETE FROM notify_channels WHERE id = ?1", [channel_id])?;
+    Ok(deleted > 0)
+}
+
+pub fn list_channels(conn: &Connection) -> Result<Vec<Channel>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, kind, target, secret, enabled, digest, created_at FROM notify_channels ORDER BY id",
+    )?;
+
+    let channels = stmt
+        .query_map([], |row| {
+            Ok(Channel {
+                id: row.get(0)?,
+                kind: row.get(1)?,
+                target: row.get(2)?,
+                secret: row.get(3)?,
+                enabled: row.get(4)?,
+                digest: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(channels)
+}
+
+/// 配信ログ（新しい順）
+pub fn list_deliveries(conn: &Connection, limit: usize) -> Result<Vec<DeliveryLog>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, alert_event_id, channel_id, attempt, status, http_status, error, attempted_at
+         FROM notify_deliveries
+         ORDER BY attempted_at DESC, id DESC
+         LIMIT ?1",
+    )?;
+
+    let logs = stmt
+        .query_map([limit], |row| {
+            Ok(DeliveryLog {
+                id: row.get(0)?,
+                alert_event_id: row.get(1)?,
+                channel_id: row.get(2)?,
+                attempt: row.get(3)?,
+                status: row.get(4)?,
+                http_status: row.get(5)?,
+                error: row.get(6)?,
+                attempted_at: row.get(7)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(logs)
+}
+
+/// 動作確認用のサンプルアラート（配信ログには残さない）
+pub fn sample_alert() -> TriggeredAlert {
+    TriggeredAlert {
+        event_id: 0,
+        rule_id: 0,
+        kind: RuleKind::BelowPrice,
+        threshold: Some(2500.0),
+        expression: None,
+        product_id: 0,
+        product_name: "テスト通知".to_string(),
+        url: "https://www.amazon.co.jp/dp/B08CF1RXD9".to_string(),
+        image_url: None,
+        old_price: Some(2980),
+        new_price: 2480,
+        message: "¥2480 が目標価格 ¥2500 を下回りました（テスト通知）".to_string(),
+        triggered_at: timestamp::now(),
+        channel_ids: Vec::new(),
+        queued: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::db;
+    use axum::{body::Bytes, extract::State, http::HeaderMap, routing::post, Router};
+    use std::sync::{Arc, Mutex};
+
+    fn time(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    /// Webhook の受け口が受け取ったリクエスト（パス, 署名ヘッダー, 本文）
+    type Received = Arc<Mutex<Vec<(String, Option<String>, Bytes)>>>;
+
+    /// ローカルに Webhook の受け口を立てる（`/ok` は 200、`/fail` は 503 を返す）
+    async fn webhook_stand_in() -> (String, Received) {
+        fn receive(path: &str, received: Received, headers: HeaderMap, body: Bytes) {
+            let signature = headers
+                .get(SIGNATURE_HEADER)
+                .map(|v| v.to_str().unwrap().to_string());
+            received.lock().unwrap().push((path.to_string(), signature, body));
+        }
+
+        let received = Received::default();
+        let app = Router::new()
+            .route(
+                "/ok",
+                post(|State(r): State<Received>, headers: HeaderMap, body: Bytes| async move {
+                    receive("/ok", r, headers, body);
+                    axum::http::StatusCode::OK
+                }),
+            )
+            .route(
+                "/fail",
+                post(|State(r): State<Received>, headers: HeaderMap, body: Bytes| async move {
+                    receive("/fail", r, headers, body);
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE
+                }),
+            )
+            .with_state(received.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{}", addr), received)
+    }
+
+    /// アラートを1件発火させた状態にして、そのイベントIDを返す
+    fn insert_alert_event(conn: &Connection) -> i64 {
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at)
+             VALUES ('https://example.com/item', 'テスト商品', 2980, '2026-10-19T00:00:00Z')",
+            [],
+        )
+        .unwrap();
+        let product_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO alert_rules (product_id, kind, threshold, created_at)
+             VALUES (?1, 'below_price', 2500, '2026-10-19T00:00:00Z')",
+            [product_id],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO alert_events (rule_id, product_id, old_price, new_price, message, triggered_at)
+             VALUES (?1, ?2, 2980, 2480, 'テスト', '2026-10-19T00:00:00Z')",
+            (conn.last_insert_rowid(), product_id),
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    #[tokio::test]
+    async fn webhook_is_signed_retried_and_logged() {
+        let (base, received) = webhook_stand_in().await;
+        let conn = db::open_in_memory().unwrap();
+        let event_id = insert_alert_event(&conn);
+        let ok = add_channel(&conn, ChannelKind::Webhook, &format!("{}/ok", base), Some("s3cret"), false).unwrap();
+        let fail = add_channel(&conn, ChannelKind::Webhook, &format!("{}/fail", base), None, false).unwrap();
+
+        let alert = TriggeredAlert {
+            event_id,
+            ..sample_alert()
+        };
+        let deliveries = deliver(&list_channels(&conn).unwrap(), &[alert]).await;
+        record_deliveries(&conn, &deliveries).unwrap();
+
+        // 署名は本文の HMAC-SHA256
+        let received = received.lock().unwrap();
+        let (_, signature, body) = received.iter().find(|(path, ..)| path == "/ok").unwrap();
+        let signature = signature.as_deref().unwrap().strip_prefix("sha256=").unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"s3cret").unwrap();
+        mac.update(body);
+        mac.verify_slice(&hex::decode(signature).unwrap()).unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(payload["alert_id"], event_id);
+
+        // 5xx は最大試行回数まで送り直す。秘密鍵のない通知先には署名をつけない
+        let failed: Vec<_> = received.iter().filter(|(path, ..)| path == "/fail").collect();
+        assert_eq!(failed.len(), DEFAULT_MAX_ATTEMPTS as usize);
+        assert!(failed.iter().all(|(_, signature, _)| signature.is_none()));
+
+        // 配信ログは新しい順なので、送った順に並べ直す
+        let mut logs = list_deliveries(&conn, 10).unwrap();
+        logs.reverse();
+        let logs: Vec<_> = logs
+            .into_iter()
+            .map(|d| (d.alert_event_id, d.channel_id, d.attempt, d.status, d.http_status, d.error))
+            .collect();
+        let mut expected = vec![(event_id, ok, 1, "delivered".to_string(), Some(200), None)];
+        for attempt in 1..=DEFAULT_MAX_ATTEMPTS {
+            expected.push((
+                event_id,
+                fail,
+                attempt,
+                "failed".to_string(),
+                Some(503),
+                Some("HTTP 503 Service Unavailable".to_string()),
+            ));
+        }
+        assert_eq!(logs, expected);
+    }
+
+    fn payload_alert() -> TriggeredAlert {
+        TriggeredAlert {
+            product_name: "天然水 <2L> | 9本 & 送料無料".to_string(),
+            url: "https://example.com/item?a=1&b=<2>|x".to_string(),
+            triggered_at: "2026-10-19T03:04:05Z".to_string(),
+            ..sample_alert()
+        }
+    }
+
+    #[test]
+    fn slack_payload_escapes_the_link() {
+        let payload = slack_payload(&payload_alert());
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "text": "🔔 ¥2480 が目標価格 ¥2500 を下回りました（テスト通知）",
+                "blocks": [
+                    {
+                        "type": "section",
+                        "text": {
+                            "type": "mrkdwn",
+                            "text": "*<https://example.com/item?a=1&amp;b=&lt;2&gt;%7Cx|天然水 &lt;2L&gt; ｜ 9本 &amp; 送料無料>*\n\
+                                     ¥2480 が目標価格 ¥2500 を下回りました（テスト通知）\n\
+                                     ¥2980 → ¥2480（-¥500 / -16.8%）",
+                        },
+                    },
+                    {
+                        "type": "context",
+                        "elements": [{
+                            "type": "mrkdwn",
+                            "text": "¥2500 を下回ったら ・ 2026-10-19 12:04:05",
+                        }],
+                    },
+                ],
+            })
+        );
+
+        // 画像があればサムネイルを添える
+        let alert = TriggeredAlert {
+            image_url: Some("https://example.com/item.jpg".to_string()),
+            ..payload_alert()
+        };
+        assert_eq!(
+            slack_payload(&alert)["blocks"][0]["accessory"],
+            serde_json::json!({
+                "type": "image",
+                "image_url": "https://example.com/item.jpg",
+                "alt_text": "天然水 <2L> | 9本 & 送料無料",
+            })
+        );
+    }
+
+    #[test]
+    fn discord_payload_snapshot() {
+        let alert = TriggeredAlert {
+            image_url: Some("https://example.com/item.jpg".to_string()),
+            ..payload_alert()
+        };
+        assert_eq!(
+            discord_payload(&alert),
+            serde_json::json!({
+                "embeds": [{
+                    "title": "天然水 <2L> | 9本 & 送料無料",
+                    "url": "https://example.com/item?a=1&b=<2>|x",
+                    "description": "¥2480 が目標価格 ¥2500 を下回りました（テスト通知）",
+                    "color": DISCORD_EMBED_COLOR,
+                    "fields": [{ "name": "価格", "value": "¥2980 → ¥2480（-¥500 / -16.8%）" }],
+                    "footer": { "text": "¥2500 を下回ったら" },
+                    "timestamp": "2026-10-19T12:04:05+09:00",
+                    "thumbnail": { "url": "https://example.com/item.jpg" },
+                }],
+            })
+        );
+        assert!(discord_payload(&payload_alert())["embeds"][0].get("thumbnail").is_none());
+    }
+
+    #[test]
+    fn line_form_snapshot() {
+        let message = "\n🔔 ¥2480 が目標価格 ¥2500 を下回りました（テスト通知）\n\
+                       ¥2980 → ¥2480（-¥500 / -16.8%）\n\
+                       天然水 <2L> | 9本 & 送料無料\n\
+                       https://example.com/item?a=1&b=<2>|x";
+        assert_eq!(line_form(&payload_alert()), vec![("message", message.to_string())]);
+
+        let alert = TriggeredAlert {
+            image_url: Some("https://example.com/item.jpg".to_string()),
+            ..payload_alert()
+        };
+        assert_eq!(
+            line_form(&alert),
+            vec![
+                ("message", message.to_string()),
+                ("imageThumbnail", "https://example.com/item.jpg".to_string()),
+                ("imageFullsize", "https://example.com/item.jpg".to_string()),
+            ]
+        );
+
+        // 長いメッセージは1000文字に収める
+        let alert = TriggeredAlert {
+            message: "あ".repeat(2000),
+            ..payload_alert()
+        };
+        assert_eq!(line_form(&alert)[0].1.chars().count(), 1000);
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let quiet: QuietHours = "12:00-13:30".parse().unwrap();
+        assert!(!quiet.contains(time("11:59")));
+        assert!(quiet.contains(time("12:00")));
+        assert!(quiet.contains(time("13:29")));
+        assert!(!quiet.contains(time("13:30")));
+    }
+
+    #[test]
+    fn quiet_hours_across_midnight() {
+        let quiet: QuietHours = "22:00-07:00".parse().unwrap();
+        assert!(!quiet.contains(time("21:59")));
+        assert!(quiet.contains(time("22:00")));
+        assert!(quiet.contains(time("00:00")));
+        assert!(quiet.contains(time("06:59")));
+        assert!(!quiet.contains(time("07:00")));
+        assert!(!quiet.contains(time("12:00")));
+    }
+
+    #[test]
+    fn quiet_hours_parse_errors() {
+        assert!("22:00".parse::<QuietHours>().is_err());
+        assert!("25:00-07:00".parse::<QuietHours>().is_err());
+        assert!("07:00-07:00".parse::<QuietHours>().is_err());
+        assert_eq!(" 22:00 - 07:00 ".trim().parse::<QuietHours>().unwrap().to_string(), "22:00-07:00");
+    }
+}
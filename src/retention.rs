@@ -0,0 +1,277 @@
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use crate::timestamp;
+
+/// 生の価格履歴を保持する日数（これより古いものは日次に集約）
+pub const DEFAULT_RETENTION_DAYS: i64 = 90;
+
+static RETENTION_DAYS: OnceLock<i64> = OnceLock::new();
+
+/// 生の価格履歴を保持する日数を設定（起動時に1回だけ）
+pub fn set_retention_days(days: i64) {
+    let _ = RETENTION_DAYS.set(days);
+}
+
+/// 生の価格履歴を保持する日数（毎晩の整理と `prune` で使う）
+pub fn retention_days() -> i64 {
+    *RETENTION_DAYS.get_or_init(|| DEFAULT_RETENTION_DAYS)
+}
+
+pub struct PruneStats {
+    /// 同一価格の連続を畳み込んで削除した行数
+    pub compacted: usize,
+    /// 日次データに集約して削除した行数
+    pub downsampled: usize,
+    /// 作成・更新した日次データの件数
+    pub daily_rows: usize,
+}
+
+/// 圧縮とダウンサンプリングをまとめて実行
+pub fn prune(conn: &Connection, retention_days: i64) -> Result<PruneStats> {
+    let compacted = compact_history(conn)?;
+    let (downsampled, daily_rows) = downsample_history(conn, retention_days)?;
+
+    Ok(PruneStats {
+        compacted,
+        downsampled,
+        daily_rows,
+    })
+}
+
+/// 同じ価格が連続している行を1つの変化イベントにまとめる
+///
+/// 残す行は最初の行（`checked_at` = 初めてその価格を見た日時）で、
+/// `last_seen_at` を連続の最後の日時まで伸ばす。
+pub fn compact_history(conn: &Connection) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+
+    let rows: Vec<(i64, i64, i32, String)> = {
+        let mut stmt = tx.prepare(
+            "SELECT id, product_id, price, COALESCE(last_seen_at, checked_at)
+             FROM price_history
+             ORDER BY product_id, checked_at, id",
+        )?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+            .collect::<rusqlite::Result<_>>()?
+    };
+
+    // (残す行のID, 商品ID, 価格, 最終確認日時)
+    let mut run: Option<(i64, i64, i32, String)> = None;
+    let mut removed = 0;
+
+    for (id, product_id, price, last_seen) in rows {
+        match &mut run {
+            Some((keep_id, run_product, run_price, run_last))
+                if *run_product == product_id && *run_price == price =>
+            {
+                if last_seen > *run_last {
+                    *run_last = last_seen;
+                }
+                tx.execute(
+                    "UPDATE price_history SET last_seen_at = ?1 WHERE id = ?2",
+                    (&*run_last, *keep_id),
+                )?;
+                tx.execute("DELETE FROM price_history WHERE id = ?1", [id])?;
+                removed += 1;
+            }
+            _ => run = Some((id, product_id, price, last_seen)),
+        }
+    }
+
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// `retention_days` より古い履歴を日次の最安値・最高値・終値に集約する
+///
+/// 戻り値は (削除した生データの行数, 作成・更新した日次データの件数)
+pub fn downsample_history(conn: &Connection, retention_days: i64) -> Result<(usize, usize)> {
+    let cutoff = timestamp::to_db(Utc::now() - Duration::days(retention_days));
+
+    let tx = conn.unchecked_transaction()?;
+
+    let rows: Vec<(i64, i32, String, String)> = {
+        let mut stmt = tx.prepare(
+            "SELECT product_id, price, checked_at, COALESCE(last_seen_at, checked_at)
+             FROM price_history
+             WHERE COALESCE(last_seen_at, checked_at) < ?1
+             ORDER BY product_id, checked_at, id",
+        )?;
+        stmt.query_map([&cutoff], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+            .collect::<rusqlite::Result<_>>()?
+    };
+
+    // (商品ID, 日付) => (最安値, 最高値, 終値, 終値の日時)
+    let mut days: BTreeMap<(i64, String), (i32, i32, i32, String)> = BTreeMap::new();
+
+    for (product_id, price, checked_at, last_seen) in &rows {
+        for (day, ts) in covered_days(checked_at, last_seen) {
+            let entry = days
+                .entry((*product_id, day))
+                .or_insert((*price, *price, *price, ts.clone()));
+            entry.0 = entry.0.min(*price);
+            entry.1 = entry.1.max(*price);
+            if ts >= entry.3 {
+                entry.2 = *price;
+                entry.3 = ts;
+            }
+        }
+    }
+
+    for ((product_id, day), (min_price, max_price, close_price, closed_at)) in &days {
+        tx.execute(
+            "INSERT INTO price_history_daily
+                 (product_id, day, min_price, max_price, close_price, closed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(product_id, day) DO UPDATE SET
+                 min_price = MIN(min_price, excluded.min_price),
+                 max_price = MAX(max_price, excluded.max_price),
+                 close_price = CASE WHEN excluded.closed_at >= closed_at
+                     THEN excluded.close_price ELSE close_price END,
+                 closed_at = MAX(closed_at, excluded.closed_at)",
+            (product_id, day, min_price, max_price, close_price, closed_at),
+        )?;
+    }
+
+    let removed = tx.execute(
+        "DELETE FROM price_history WHERE COALESCE(last_seen_at, checked_at) < ?1",
+        [&cutoff],
+    )?;
+
+    tx.commit()?;
+    Ok((removed, days.len()))
+}
+
+/// 1行の価格が続いた日ごとに、(日付, その日の終値の日時) を返す
+///
+/// 1行が複数日にまたがる場合は間の日もすべて含める（日付の区切りは表示用のタイムゾーン）。
+/// 最後の日以外はその日の終わりまで同じ価格だったので、終値の日時はその日の 23:59:59 にする。
+fn covered_days(checked_at: &str, last_seen: &str) -> Vec<(String, String)> {
+    let first = NaiveDate::parse_from_str(&timestamp::local_day(checked_at), "%Y-%m-%d");
+    let last = NaiveDate::parse_from_str(&timestamp::local_day(last_seen), "%Y-%m-%d");
+    let (Ok(first), Ok(last)) = (first, last) else {
+        // 日時が読めない行は、最初と最後の日だけに反映する
+        return [checked_at, last_seen]
+            .map(|ts| (timestamp::local_day(ts), ts.to_string()))
+            .to_vec();
+    };
+
+    first
+        .iter_days()
+        .take_while(|day| *day <= last)
+        .map(|day| {
+            let closed_at = if day == last {
+                last_seen.to_string()
+            } else {
+                end_of_day(day).unwrap_or_else(|| last_seen.to_string())
+            };
+            (day.format("%Y-%m-%d").to_string(), closed_at)
+        })
+        .collect()
+}
+
+/// 表示用のタイムゾーンでのその日の最後の時刻（DB保存形式）
+fn end_of_day(day: NaiveDate) -> Option<String> {
+    let next = day.succ_opt()?.and_hms_opt(0, 0, 0)?;
+    let midnight = timestamp::timezone().from_local_datetime(&next).earliest()?;
+    Some(timestamp::to_db(midnight.with_timezone(&Utc) - Duration::seconds(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn insert_product(conn: &Connection) -> i64 {
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at)
+             VALUES ('https://example.com/item', 'テスト商品', 1000, '2020-01-01T00:00:00Z')",
+            [],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn insert_history(conn: &Connection, product_id: i64, price: i32, checked_at: &str, last_seen_at: &str) {
+        conn.execute(
+            "INSERT INTO price_history (product_id, price, checked_at, last_seen_at) VALUES (?1, ?2, ?3, ?4)",
+            (product_id, price, checked_at, last_seen_at),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn compact_merges_runs_of_the_same_price() {
+        let conn = db::open_in_memory().unwrap();
+        let id = insert_product(&conn);
+        insert_history(&conn, id, 1000, "2026-10-01T00:00:00Z", "2026-10-01T00:00:00Z");
+        insert_history(&conn, id, 1000, "2026-10-01T01:00:00Z", "2026-10-01T01:00:00Z");
+        insert_history(&conn, id, 1000, "2026-10-01T02:00:00Z", "2026-10-01T03:00:00Z");
+        insert_history(&conn, id, 900, "2026-10-01T04:00:00Z", "2026-10-01T04:00:00Z");
+        insert_history(&conn, id, 1000, "2026-10-01T05:00:00Z", "2026-10-01T05:00:00Z");
+
+        assert_eq!(compact_history(&conn).unwrap(), 2);
+
+        let mut stmt = conn
+            .prepare("SELECT price, checked_at, last_seen_at FROM price_history ORDER BY checked_at")
+            .unwrap();
+        let rows: Vec<(i32, String, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                (1000, "2026-10-01T00:00:00Z".to_string(), "2026-10-01T03:00:00Z".to_string()),
+                (900, "2026-10-01T04:00:00Z".to_string(), "2026-10-01T04:00:00Z".to_string()),
+                (1000, "2026-10-01T05:00:00Z".to_string(), "2026-10-01T05:00:00Z".to_string()),
+            ]
+        );
+
+        // もう一度実行しても何も変わらない
+        assert_eq!(compact_history(&conn).unwrap(), 0);
+    }
+
+    #[test]
+    fn downsample_fills_every_day_a_price_lasted() {
+        let conn = db::open_in_memory().unwrap();
+        let id = insert_product(&conn);
+        // 1月1日12:00〜1月4日12:00（日本時間）は 1000円、1月4日14:00 に 900円
+        insert_history(&conn, id, 1000, "2020-01-01T03:00:00Z", "2020-01-04T03:00:00Z");
+        insert_history(&conn, id, 900, "2020-01-04T05:00:00Z", "2020-01-04T05:00:00Z");
+
+        assert_eq!(downsample_history(&conn, 90).unwrap(), (2, 4));
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT day, min_price, max_price, close_price, closed_at
+                 FROM price_history_daily WHERE product_id = ?1 ORDER BY day",
+            )
+            .unwrap();
+        let days: Vec<(String, i32, i32, i32, String)> = stmt
+            .query_map([id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        let day = |d: &str, min, max, close, at: &str| (d.to_string(), min, max, close, at.to_string());
+        assert_eq!(
+            days,
+            [
+                day("2020-01-01", 1000, 1000, 1000, "2020-01-01T14:59:59Z"),
+                day("2020-01-02", 1000, 1000, 1000, "2020-01-02T14:59:59Z"),
+                day("2020-01-03", 1000, 1000, 1000, "2020-01-03T14:59:59Z"),
+                day("2020-01-04", 900, 1000, 900, "2020-01-04T05:00:00Z"),
+            ]
+        );
+
+        let remaining: i64 = conn
+            .query_row("SELECT COUNT(*) FROM price_history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+}
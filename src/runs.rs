@@ -0,0 +1,437 @@
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::scheduler;
+use crate::scraper::ScrapeError;
+use crate::timestamp;
+
+/// 連続でこの回数失敗した商品は「要確認」にする（定期チェックの間隔を空ける）
+pub const NEEDS_ATTENTION_FAILURES: i64 = 5;
+
+/// 価格チェックを起動したもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Cli,
+    Scheduler,
+    Web,
+    /// スケジューラーの手動実行（`scheduler run` / `POST /api/scheduler/run`）
+    Manual,
+}
+
+impl Trigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Trigger::Cli => "cli",
+            Trigger::Scheduler => "scheduler",
+            Trigger::Web => "web",
+            Trigger::Manual => "manual",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CheckRun {
+    pub id: i64,
+    pub trigger: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    /// 終了や異常終了で、全商品をチェックし終える前に止まった
+    pub interrupted: bool,
+    /// 停止・スリープ中に取りこぼしたチェックを実行した場合、データがない期間の始まり（終わりは started_at）
+    pub missed_since: Option<String>,
+    pub total: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+}
+
+#[derive(Serialize)]
+pub struct CheckAttempt {
+    pub run_id: i64,
+    pub product_id: i64,
+    pub product_name: String,
+    pub status: String,
+    pub error_kind: Option<String>,
+    pub error_message: Option<String>,
+    pub http_status: Option<u16>,
+    pub latency_ms: i64,
+    pub price: Option<i32>,
+    pub attempted_at: String,
+}
+
+/// 実行記録を作成して ID を返す
+///
+/// 対象の商品には、試行を記録するまで「チェック待ち」の印を付けておく（中断したときに再開できるように）。
+/// 実行記録には、このプロセス（実行権の持ち主と同じ識別子）を記録する。
+pub fn start_run(conn: &Connection, trigger: Trigger, product_ids: &[i64]) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO check_runs (trigger_source, started_at, total, owner) VALUES (?1, ?2, ?3, ?4)",
+        (trigger.as_str(), timestamp::now(), product_ids.len(), scheduler::lease_owner()),
+    )?;
+    let run_id = conn.last_insert_rowid();
+
+    let mut stmt = conn.prepare("UPDATE products SET pending_run_id = ?1 WHERE id = ?2")?;
+    for product_id in product_ids {
+        stmt.execute((run_id, product_id))?;
+    }
+
+    Ok(run_id)
+}
+
+/// 取りこぼしたチェックを実行していることを記録（`missed_since` から実行開始までデータがない）
+pub fn mark_catch_up(conn: &Connection, run_id: i64, missed_since: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE check_runs SET missed_since = ?1 WHERE id = ?2",
+        (missed_since, run_id),
+    )?;
+    Ok(())
+}
+
+/// 価格チェックが行われなかった期間
+#[derive(Serialize)]
+pub struct Gap {
+    pub from: String,
+    pub to: String,
+}
+
+/// 期間（DB保存形式、省略可）に重なる、チェックが行われなかった期間
+pub fn list_gaps(conn: &Connection, from: Option<&str>, to: Option<&str>) -> Result<Vec<Gap>> {
+    let mut stmt = conn.prepare(
+        "SELECT missed_since, started_at FROM check_runs
+         WHERE missed_since IS NOT NULL AND started_at >= ?1 AND missed_since < ?2
+         ORDER BY missed_since",
+    )?;
+
+    let gaps = stmt
+        .query_map((from.unwrap_or(""), to.unwrap_or("9999")), |row| {
+            Ok(Gap {
+                from: row.get(0)?,
+                to: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(gaps)
+}
+
+/// 商品1件分の試行を記録
+///
+/// `outcome` は取得できた価格（在庫切れで価格がなければ None）、または失敗の理由。スクレイピングの失敗は
+/// `ScrapeError` から種類とHTTPステータスを取り出し、それ以外（DB保存など）は "internal" とする。
+///
+/// 商品の連続失敗の回数も更新し、今回の失敗で「要確認」になったら true を返す。
+pub fn record_attempt(
+    conn: &Connection,
+    run_id: i64,
+    product_id: i64,
+    outcome: Result<Option<i32>, &anyhow::Error>,
+    latency: Duration,
+) -> Result<bool> {
+    let (status, price, error_kind, error_message, http_status) = match outcome {
+        Ok(price) => ("ok", price, None, None, None),
+        Err(e) => {
+            let scrape = e.downcast_ref::<ScrapeError>();
+            (
+                "error",
+                None,
+                Some(scrape.map_or("internal", |s| s.kind.as_str())),
+                Some(format!("{:#}", e)),
+                scrape.and_then(|s| s.http_status),
+            )
+        }
+    };
+
+    conn.execute(
+        "INSERT INTO check_attempts
+             (run_id, product_id, status, error_kind, error_message, http_status,
+              latency_ms, price, attempted_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        rusqlite::params![
+            run_id,
+            product_id,
+            status,
+            error_kind,
+            error_message,
+            http_status,
+            latency.as_millis() as i64,
+            price,
+            timestamp::now(),
+        ],
+    )?;
+    conn.execute(
+        "UPDATE products SET pending_run_id = NULL WHERE id = ?1 AND pending_run_id = ?2",
+        (product_id, run_id),
+    )?;
+
+    update_failures(conn, product_id, status == "ok", http_status)
+}
+
+/// 連続失敗の回数と「要確認」の状態を更新（要確認になったら true）
+///
+/// 成功すれば元に戻す。商品ページがなくなった（404/410）ときは、回数を待たずに要確認にする。
+fn update_failures(conn: &Connection, product_id: i64, ok: bool, http_status: Option<u16>) -> Result<bool> {
+    if ok {
+        conn.execute(
+            "UPDATE products SET consecutive_failures = 0, needs_attention_at = NULL WHERE id = ?1",
+            [product_id],
+        )?;
+        return Ok(false);
+    }
+
+    let Some((failures, flagged)) = conn
+        .query_row(
+            "SELECT consecutive_failures, needs_attention_at IS NOT NULL FROM products WHERE id = ?1",
+            [product_id],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, bool>(1)?)),
+        )
+        .optional()?
+    else {
+        return Ok(false);
+    };
+
+    let failures = failures + 1;
+    let gone = matches!(http_status, Some(404 | 410));
+    let needs_attention = !flagged && (failures >= NEEDS_ATTENTION_FAILURES || gone);
+    conn.execute(
+        "UPDATE products SET
+             consecutive_failures = ?2,
+             needs_attention_at = CASE WHEN ?3 THEN ?4 ELSE needs_attention_at END
+         WHERE id = ?1",
+        (product_id, failures, needs_attention, timestamp::now()),
+    )?;
+
+    Ok(needs_attention)
+}
+
+/// 実行記録を完了にする（成功・失敗件数は試行の記録から集計）
+pub fn finish_run(conn: &Connection, run_id: i64) -> Result<()> {
+    close_run(conn, run_id, false)?;
+    conn.execute(
+        "UPDATE products SET pending_run_id = NULL WHERE pending_run_id = ?1",
+        [run_id],
+    )?;
+    Ok(())
+}
+
+/// 実行記録を中断として閉じる（チェックし終えていない商品の印は残す）
+pub fn interrupt_run(conn: &Connection, run_id: i64) -> Result<()> {
+    close_run(conn, run_id, true)
+}
+
+fn close_run(conn: &Connection, run_id: i64, interrupted: bool) -> Result<()> {
+    conn.execute(
+        "UPDATE check_runs SET
+             finished_at = ?1,
+             interrupted = ?3,
+             succeeded = (SELECT COUNT(*) FROM check_attempts WHERE run_id = ?2 AND status = 'ok'),
+             failed = (SELECT COUNT(*) FROM check_attempts WHERE run_id = ?2 AND status != 'ok')
+         WHERE id = ?2",
+        (timestamp::now(), run_id, interrupted),
+    )?;
+    Ok(())
+}
+
+/// `owner` のプロセスが始めて終わっていない実行記録を中断として閉じ、閉じた件数を返す
+///
+/// 終了時に、このプロセスで期限までに終わらなかった実行を片付ける（ほかのプロセスの実行には触れない）。
+pub fn interrupt_owned(conn: &Connection, owner: &str) -> Result<usize> {
+    let run_ids = conn
+        .prepare("SELECT id FROM check_runs WHERE finished_at IS NULL AND owner = ?1")?
+        .query_map([owner], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<i64>>>()?;
+    for run_id in &run_ids {
+        interrupt_run(conn, *run_id)?;
+    }
+    Ok(run_ids.len())
+}
+
+/// 始めたプロセスが有効な実行権を持っていない、終わっていない実行記録を中断として閉じ、閉じた件数を返す
+///
+/// 起動時に、異常終了で残った実行を片付ける。`now`（DB保存形式）の時点で実行権を持って動いているプロセスの実行は残す。
+pub fn interrupt_abandoned(conn: &Connection, now: &str) -> Result<usize> {
+    let run_ids = conn
+        .prepare(
+            "SELECT r.id FROM check_runs r
+             WHERE r.finished_at IS NULL
+               AND NOT EXISTS (SELECT 1 FROM scheduler_state s
+                               WHERE s.lock_owner = r.owner AND s.lock_expires_at > ?1)",
+        )?
+        .query_map([now], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<i64>>>()?;
+    for run_id in &run_ids {
+        interrupt_run(conn, *run_id)?;
+    }
+    Ok(run_ids.len())
+}
+
+/// 中断した実行でチェックし終えていない商品（アーカイブしたもの・チェックを止めたものは除く）
+///
+/// ほかのプロセスで実行中のチェックの商品は含めない。
+pub fn pending_products(conn: &Connection) -> Result<Vec<i64>> {
+    let ids = conn
+        .prepare(
+            "SELECT id FROM products
+             WHERE pending_run_id IN (SELECT id FROM check_runs WHERE interrupted = 1)
+               AND archived_at IS NULL AND disabled_at IS NULL
+             ORDER BY id",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(ids)
+}
+
+fn run_from_row(row: &rusqlite::Row) -> rusqlite::Result<CheckRun> {
+    Ok(CheckRun {
+        id: row.get(0)?,
+        trigger: row.get(1)?,
+        started_at: row.get(2)?,
+        finished_at: row.get(3)?,
+        interrupted: row.get(4)?,
+        missed_since: row.get(5)?,
+        total: row.get(6)?,
+        succeeded: row.get(7)?,
+        failed: row.get(8)?,
+    })
+}
+
+/// 直近の実行記録
+pub fn list_runs(conn: &Connection, limit: usize) -> Result<Vec<CheckRun>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, trigger_source, started_at, finished_at, interrupted, missed_since, total, succeeded, failed
+         FROM check_runs
+         ORDER BY started_at DESC, id DESC
+         LIMIT ?1",
+    )?;
+
+    let runs = stmt
+        .query_map([limit], run_from_row)?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(runs)
+}
+
+pub fn get_run(conn: &Connection, run_id: i64) -> Result<Option<CheckRun>> {
+    let run = conn
+        .query_row(
+            "SELECT id, trigger_source, started_at, finished_at, interrupted, missed_since, total, succeeded, failed
+             FROM check_runs WHERE id = ?1",
+            [run_id],
+            run_from_row,
+        )
+        .optional()?;
+
+    Ok(run)
+}
+
+/// 実行中（まだ終わっていない）の実行記録
+pub fn current_run(conn: &Connection) -> Result<Option<CheckRun>> {
+    let run = conn
+        .query_row(
+            "SELECT id, trigger_source, started_at, finished_at, interrupted, missed_since, total, succeeded, failed
+             FROM check_runs
+             WHERE finished_at IS NULL
+             ORDER BY started_at DESC, id DESC
+             LIMIT 1",
+            [],
+            run_from_row,
+        )
+        .optional()?;
+
+    Ok(run)
+}
+
+/// 試行の記録（実行IDか商品IDで絞り込み、新しい順）
+pub fn list_attempts(
+    conn: &Connection,
+    run_id: Option<i64>,
+    product_id: Option<i64>,
+    limit: usize,
+) -> Result<Vec<CheckAttempt>> {
+    let mut stmt = conn.prepare(
+        "SELECT a.run_id, a.product_id, COALESCE(p.display_name, p.name), a.status, a.error_kind, a.error_message,
+                a.http_status, a.latency_ms, a.price, a.attempted_at
+         FROM check_attempts a
+         JOIN products p ON p.id = a.product_id
+         WHERE (?1 IS NULL OR a.run_id = ?1)
+           AND (?2 IS NULL OR a.product_id = ?2)
+         ORDER BY a.attempted_at DESC, a.id DESC
+         LIMIT ?3",
+    )?;
+
+    let attempts = stmt
+        .query_map((run_id, product_id, limit), |row| {
+            Ok(CheckAttempt {
+                run_id: row.get(0)?,
+                product_id: row.get(1)?,
+                product_name: row.get(2)?,
+                status: row.get(3)?,
+                error_kind: row.get(4)?,
+                error_message: row.get(5)?,
+                http_status: row.get(6)?,
+                latency_ms: row.get(7)?,
+                price: row.get(8)?,
+                attempted_at: row.get(9)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(attempts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn insert_product(conn: &Connection, url: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at)
+             VALUES (?1, 'テスト商品', 1000, '2026-10-19T00:00:00Z')",
+            [url],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn is_open(conn: &Connection, run_id: i64) -> bool {
+        conn.query_row("SELECT finished_at IS NULL FROM check_runs WHERE id = ?1", [run_id], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn only_runs_without_a_live_owner_are_interrupted() {
+        let conn = db::open_in_memory().unwrap();
+        let mine = insert_product(&conn, "https://example.com/1");
+        let live = insert_product(&conn, "https://example.com/2");
+        let gone = insert_product(&conn, "https://example.com/3");
+
+        let my_run = start_run(&conn, Trigger::Cli, &[mine]).unwrap();
+        // 別のプロセス: 実行権を持って実行中のもの（daemon）と、異常終了して実行権の期限が切れたもの
+        let live_run = start_run(&conn, Trigger::Scheduler, &[live]).unwrap();
+        let gone_run = start_run(&conn, Trigger::Scheduler, &[gone]).unwrap();
+        conn.execute("UPDATE check_runs SET owner = 'pid:live' WHERE id = ?1", [live_run]).unwrap();
+        conn.execute("UPDATE check_runs SET owner = 'pid:gone' WHERE id = ?1", [gone_run]).unwrap();
+        conn.execute(
+            "UPDATE scheduler_state SET lock_owner = 'pid:live', lock_expires_at = '2026-10-19T01:00:00Z'",
+            [],
+        )
+        .unwrap();
+
+        // 終了時: このプロセスの実行だけ
+        assert_eq!(interrupt_owned(&conn, &scheduler::lease_owner()).unwrap(), 1);
+        assert!(!is_open(&conn, my_run));
+        assert!(is_open(&conn, live_run) && is_open(&conn, gone_run));
+        assert_eq!(pending_products(&conn).unwrap(), [mine]);
+
+        // 起動時: 実行権を持っていないプロセスの実行だけ
+        assert_eq!(interrupt_abandoned(&conn, "2026-10-19T00:30:00Z").unwrap(), 1);
+        assert!(is_open(&conn, live_run));
+        assert!(!is_open(&conn, gone_run));
+        assert_eq!(pending_products(&conn).unwrap(), [mine, gone]);
+
+        // 実行権の期限が切れたら、そのプロセスの実行も閉じる
+        assert_eq!(interrupt_abandoned(&conn, "2026-10-19T01:00:00Z").unwrap(), 1);
+        assert_eq!(pending_products(&conn).unwrap(), [mine, live, gone]);
+    }
+}
@@ -1,93 +1,983 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
 use rusqlite::Connection;
-use std::sync::{Arc, Mutex};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
 use tokio_cron_scheduler::{Job, JobScheduler};
-use tracing::{info, error};
+use tracing::{info, error, warn};
 
+use crate::alerts::{self, HistoryStats};
 use crate::db::{save_price_history, save_product};
-use crate::scraper::fetch_amazon_price;
+use crate::digest;
+use crate::notify;
+use crate::retention;
+use crate::runs::{self, CheckRun, Trigger};
+use crate::scraper;
+use crate::timestamp;
 
 pub type SharedDb = Arc<Mutex<Connection>>;
 
+/// 価格チェックのデフォルトのスケジュール（毎時0分0秒）
+pub const DEFAULT_CHECK_SCHEDULE: &str = "0 0 * * * *";
+
+/// 在庫切れの商品をチェックする間隔（分）。再入荷をすぐ知らせたいので短くする
+const SOLD_OUT_INTERVAL_MINUTES: i64 = 15;
+
+/// チェック間隔の自動調整: 間隔の下限・上限のデフォルト
+pub const DEFAULT_MIN_CHECK_INTERVAL: &str = "1h";
+pub const DEFAULT_MAX_CHECK_INTERVAL: &str = "1d";
+
+/// 指定できるチェック間隔の上限（分）。365日
+pub const MAX_CHECK_INTERVAL_MINUTES: i64 = 365 * 24 * 60;
+
+/// 終了するとき、実行中の価格チェックが終わるのを待つ時間
+pub const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 要確認の商品をチェックする間隔の基準（時間）。失敗が続くほど倍にしていく
+const ATTENTION_BACKOFF_BASE_HOURS: i64 = 6;
+
+/// 要確認の商品をチェックする間隔の上限（日）
+const ATTENTION_BACKOFF_MAX_DAYS: i64 = 7;
+
+/// チェックする時刻をこれ以上（分）過ぎていたら、停止・スリープ中に取りこぼしたとみなす
+const CATCH_UP_TOLERANCE_MINUTES: i64 = 15;
+
+/// 1分ごとの確認の間がこれ以上（秒）空いたら、スリープから復帰したとみなす
+const WAKE_DETECT_SECONDS: i64 = 180;
+
+/// 価格の変化の頻度を数える期間（日）
+const VOLATILITY_WINDOW_DAYS: i64 = 7;
+
+/// 価格が1回変わる間に何回チェックするか（多いほど変化を見逃しにくい）
+const CHECKS_PER_CHANGE: i64 = 4;
+
+/// 価格チェックのスケジュール
+///
+/// Cron形式: "秒 分 時 日 月 曜日"（例: "0 0 * * * *" = 毎時0分、"0 0 9 * * *" = 毎朝9時）。
+/// 時刻は表示用のタイムゾーンで解釈する。
+#[derive(Debug, Clone)]
+pub struct CheckSchedule(cron::Schedule);
+
+impl CheckSchedule {
+    /// `after` より後で、最初にチェックする日時
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tz = timestamp::timezone();
+        self.0
+            .after(&after.with_timezone(&tz))
+            .next()
+            .map(|t| t.with_timezone(&Utc))
+    }
+
+    /// `at` ちょうどか、それより前で最後にチェックする日時
+    fn prev_at_or_before(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tz = timestamp::timezone();
+        self.0
+            .after(&(at + Duration::seconds(1)).with_timezone(&tz))
+            .next_back()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+impl FromStr for CheckSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        cron::Schedule::from_str(s.trim())
+            .map(Self)
+            .map_err(|e| anyhow!("Invalid cron expression '{}': {} (expected \"sec min hour day month weekday\")", s, e))
+    }
+}
+
+impl fmt::Display for CheckSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+static CHECK_SCHEDULE: OnceLock<CheckSchedule> = OnceLock::new();
+
+/// 価格チェックのスケジュールを設定（起動時に1回だけ）
+pub fn set_check_schedule(schedule: CheckSchedule) {
+    let _ = CHECK_SCHEDULE.set(schedule);
+}
+
+/// 価格チェックのスケジュール
+pub fn check_schedule() -> &'static CheckSchedule {
+    CHECK_SCHEDULE.get_or_init(|| DEFAULT_CHECK_SCHEDULE.parse().unwrap())
+}
+
+/// 商品ごとのチェック間隔（"15m", "2h", "1d" または分数で指定）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckInterval {
+    pub minutes: i64,
+}
+
+impl FromStr for CheckInterval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: i64 = number
+            .parse()
+            .map_err(|_| anyhow!("Invalid interval '{}' (e.g. 15m, 2h, 1d)", s))?;
+        let minutes = match unit.trim() {
+            "" | "m" | "min" => Some(number),
+            "h" => number.checked_mul(60),
+            "d" => number.checked_mul(60 * 24),
+            _ => bail!("Invalid interval unit in '{}' (use m, h or d)", s),
+        };
+        match minutes {
+            Some(minutes) if minutes <= 0 => bail!("Interval must be at least 1 minute"),
+            Some(minutes) if minutes <= MAX_CHECK_INTERVAL_MINUTES => Ok(Self { minutes }),
+            _ => bail!("Interval must be at most 365 days"),
+        }
+    }
+}
+
+impl fmt::Display for CheckInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.minutes {
+            m if m % (60 * 24) == 0 => write!(f, "{}日", m / (60 * 24)),
+            m if m % 60 == 0 => write!(f, "{}時間", m / 60),
+            m => write!(f, "{}分", m),
+        }
+    }
+}
+
+/// 自動調整するチェック間隔の下限・上限
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveBounds {
+    pub min: CheckInterval,
+    pub max: CheckInterval,
+}
+
+impl AdaptiveBounds {
+    /// 直近の価格の変化の回数から、次のチェックまでの間隔を決める
+    ///
+    /// 変化がなければ上限、よく変わる商品ほど短くする（下限まで）。
+    fn interval(&self, changes: i64) -> CheckInterval {
+        if changes <= 0 {
+            return self.max;
+        }
+        let minutes = VOLATILITY_WINDOW_DAYS * 24 * 60 / (changes * CHECKS_PER_CHANGE);
+        CheckInterval {
+            minutes: minutes.clamp(self.min.minutes, self.max.minutes),
+        }
+    }
+}
+
+static ADAPTIVE_BOUNDS: OnceLock<AdaptiveBounds> = OnceLock::new();
+
+/// チェック間隔の自動調整の下限・上限を設定（起動時に1回だけ）
+pub fn set_adaptive_bounds(min: CheckInterval, max: CheckInterval) -> Result<()> {
+    if min.minutes > max.minutes {
+        bail!("--min-check-interval ({}) must not exceed --max-check-interval ({})", min, max);
+    }
+    let _ = ADAPTIVE_BOUNDS.set(AdaptiveBounds { min, max });
+    Ok(())
+}
+
+/// チェック間隔の自動調整の下限・上限
+pub fn adaptive_bounds() -> AdaptiveBounds {
+    *ADAPTIVE_BOUNDS.get_or_init(|| AdaptiveBounds {
+        min: DEFAULT_MIN_CHECK_INTERVAL.parse().unwrap(),
+        max: DEFAULT_MAX_CHECK_INTERVAL.parse().unwrap(),
+    })
+}
+
+/// `at` の `minutes` 分後（日時の範囲を超える間隔はエラー）
+fn add_minutes(at: DateTime<Utc>, minutes: i64) -> Result<DateTime<Utc>> {
+    Duration::try_minutes(minutes)
+        .and_then(|d| at.checked_add_signed(d))
+        .ok_or_else(|| anyhow!("Check interval out of range: {} minutes", minutes))
+}
+
+/// 前回のチェックから、次にチェックする日時を決める
+///
+/// 商品ごとの間隔があればそれに従う。なければ直近の価格の変化の回数（`changes`）から間隔を自動で決め、
+/// 全体のスケジュールの時刻に合わせる（例: 毎時0分のスケジュールで間隔が3時間なら、3回に1回チェックする）。
+/// 在庫切れの間は、再入荷をすぐ知らせるために短い間隔でもチェックする。
+fn next_due(
+    schedule: &CheckSchedule,
+    bounds: &AdaptiveBounds,
+    interval: Option<CheckInterval>,
+    changes: i64,
+    in_stock: bool,
+    last_checked: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>> {
+    let regular = match interval {
+        Some(interval) => Some(add_minutes(last_checked, interval.minutes)?),
+        None => {
+            let interval = bounds.interval(changes);
+            let base = schedule.prev_at_or_before(last_checked).unwrap_or(last_checked);
+            // 前回のチェック時刻ちょうどのスケジュールも含める
+            schedule.next_after(add_minutes(base, interval.minutes)? - Duration::seconds(1))
+        }
+    };
+    if in_stock {
+        return Ok(regular);
+    }
+
+    let sold_out = last_checked + Duration::minutes(SOLD_OUT_INTERVAL_MINUTES);
+    Ok(Some(regular.map_or(sold_out, |regular| regular.min(sold_out))))
+}
+
+/// 要確認の商品を次にチェックするまでの間隔（連続失敗の回数が増えるほど長くする）
+fn attention_backoff(failures: i64) -> Duration {
+    let steps = (failures - runs::NEEDS_ATTENTION_FAILURES).clamp(0, 10) as u32;
+    let hours = ATTENTION_BACKOFF_BASE_HOURS * 2_i64.pow(steps);
+    Duration::hours(hours).min(Duration::days(ATTENTION_BACKOFF_MAX_DAYS))
+}
+
+/// 商品の次のチェック日時を計算して保存（チェックのたび・間隔を変えたときに呼ぶ）
+///
+/// 保存した日時（DB保存形式）を返す。チェックを止めている商品は None。
+pub fn reschedule(conn: &Connection, product_id: i64) -> Result<Option<String>> {
+    let since = timestamp::to_db(Utc::now() - Duration::days(VOLATILITY_WINDOW_DAYS));
+    let (in_stock, interval_minutes, last_checked, changes, failures, needs_attention, disabled) = conn.query_row(
+        "SELECT p.in_stock, p.check_interval_minutes,
+                COALESCE((SELECT MAX(a.attempted_at) FROM check_attempts a WHERE a.product_id = p.id),
+                         p.created_at),
+                (SELECT COUNT(*) FROM price_history h
+                 WHERE h.product_id = p.id AND h.checked_at >= ?2
+                   AND EXISTS (SELECT 1 FROM price_history e
+                               WHERE e.product_id = p.id AND e.checked_at < h.checked_at)),
+                p.consecutive_failures, p.needs_attention_at IS NOT NULL, p.disabled_at IS NOT NULL
+         FROM products p
+         WHERE p.id = ?1",
+        (product_id, &since),
+        |row| {
+            Ok((
+                row.get::<_, bool>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, bool>(5)?,
+                row.get::<_, bool>(6)?,
+            ))
+        },
+    )?;
+
+    let last_checked = timestamp::parse(&last_checked)?;
+    // 要確認の商品は、スケジュールや商品ごとの間隔に関係なく間隔を空けて様子を見る
+    let next = if disabled {
+        None
+    } else if needs_attention {
+        Some(last_checked + attention_backoff(failures))
+    } else {
+        let interval = interval_minutes.map(|minutes| CheckInterval { minutes });
+        next_due(check_schedule(), &adaptive_bounds(), interval, changes, in_stock, last_checked)?
+    };
+    let next = next.map(timestamp::to_db);
+
+    conn.execute(
+        "UPDATE products SET next_check_at = ?1 WHERE id = ?2",
+        (&next, product_id),
+    )?;
+
+    Ok(next)
+}
+
+/// すべての商品の次のチェック日時を計算し直す（スケジュールの設定が変わっていても反映されるように起動時に呼ぶ）
+fn reschedule_all(conn: &Connection) -> Result<()> {
+    let ids = conn
+        .prepare("SELECT id FROM products WHERE archived_at IS NULL")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<i64>>>()?;
+    for id in ids {
+        reschedule(conn, id)?;
+    }
+    Ok(())
+}
+
+/// チェックする商品（ID, URL, 商品名）。`due_at` を指定するとその時点でチェックする時刻が来た商品だけ
+fn products_to_check(conn: &Connection, due_at: Option<DateTime<Utc>>) -> Result<Vec<(i64, String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, url, COALESCE(display_name, name) FROM products
+         WHERE archived_at IS NULL AND disabled_at IS NULL
+           AND (?1 IS NULL OR next_check_at IS NULL OR next_check_at <= ?1)
+         ORDER BY id",
+    )?;
+
+    let products = stmt
+        .query_map([due_at.map(timestamp::to_db)], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(products)
+}
+
+/// 価格チェックが実行中か（定期実行と Web からのチェックが重ならないように）
+static CHECK_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// 価格チェックの実行権の有効期限（分）。実行中は商品ごとに延長し、異常終了したプロセスの実行権はこの時間で切れる
+const RUN_LEASE_MINUTES: i64 = 10;
+
+/// 価格チェックを実行している間だけ持つ（drop で解放）
+///
+/// DB接続のロックを持ったまま drop しないこと（実行権の解放にDBを使う）。
+pub struct RunGuard {
+    db: SharedDb,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        if let Ok(conn) = self.db.lock()
+            && let Err(e) = release_lease(&conn)
+        {
+            error!("❌ 価格チェックの実行権の解放エラー: {}", e);
+        }
+        CHECK_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// 価格チェックを始める（ほかのチェックがこのプロセスか別のプロセスで実行中か、終了処理中なら None）
+pub fn try_begin_run(db: &SharedDb) -> Option<RunGuard> {
+    if SHUTDOWN_DEADLINE.get().is_some() || CHECK_RUNNING.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+
+    let acquired = {
+        let conn = db.lock().unwrap();
+        acquire_lease(&conn)
+    };
+    match acquired {
+        Ok(true) => Some(RunGuard { db: db.clone() }),
+        Ok(false) => {
+            CHECK_RUNNING.store(false, Ordering::SeqCst);
+            None
+        }
+        Err(e) => {
+            error!("❌ 価格チェックの実行権の取得エラー: {}", e);
+            CHECK_RUNNING.store(false, Ordering::SeqCst);
+            None
+        }
+    }
+}
+
+/// 実行権を持つプロセスの識別子
+pub fn lease_owner() -> String {
+    format!("pid:{}", std::process::id())
+}
+
+/// 価格チェックの実行権を取る（ほかのプロセスが有効な実行権を持っていれば false）
+///
+/// サーバーと daemon は `try_begin_run` を通して使う。CLI の `check` は直接取って、終わったら `release_lease` で手放す。
+pub fn acquire_lease(conn: &Connection) -> Result<bool> {
+    let now = Utc::now();
+    let changed = conn.execute(
+        "UPDATE scheduler_state SET lock_owner = ?1, lock_expires_at = ?2
+         WHERE id = 1 AND (lock_owner IS NULL OR lock_owner = ?1 OR lock_expires_at <= ?3)",
+        (
+            lease_owner(),
+            timestamp::to_db(now + Duration::minutes(RUN_LEASE_MINUTES)),
+            timestamp::to_db(now),
+        ),
+    )?;
+    Ok(changed > 0)
+}
+
+/// 実行中の価格チェックの実行権を延長する
+pub fn renew_lease(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE scheduler_state SET lock_expires_at = ?2 WHERE id = 1 AND lock_owner = ?1",
+        (lease_owner(), timestamp::to_db(Utc::now() + Duration::minutes(RUN_LEASE_MINUTES))),
+    )?;
+    Ok(())
+}
+
+/// 価格チェックの実行権を手放す（このプロセスが持っていなければ何もしない）
+pub fn release_lease(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE scheduler_state SET lock_owner = NULL, lock_expires_at = NULL WHERE id = 1 AND lock_owner = ?1",
+        [lease_owner()],
+    )?;
+    Ok(())
+}
+
+/// 終了処理の期限（終了シグナルを受け取ったときに決まる）
+static SHUTDOWN_DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+/// 終了処理を始める: 新しい価格チェックは始めず、実行中のものは期限まで続けさせる。期限を返す
+pub fn begin_shutdown() -> Instant {
+    *SHUTDOWN_DEADLINE.get_or_init(|| Instant::now() + SHUTDOWN_TIMEOUT)
+}
+
+/// 終了処理を始めたか（終了シグナルを受け取ったか）
+pub fn shutting_down() -> bool {
+    SHUTDOWN_DEADLINE.get().is_some()
+}
+
+/// 終了処理の期限を過ぎたか（価格チェックは次の商品に進まずに中断する）
+fn shutdown_deadline_passed() -> bool {
+    SHUTDOWN_DEADLINE.get().is_some_and(|deadline| Instant::now() >= *deadline)
+}
+
+/// 実行中の価格チェックが終わるのを期限まで待つ
+///
+/// 期限までに終わらなかった実行は中断として記録し、チェックし終えていない商品は次の起動時に再開する。
+pub async fn wait_for_running_check(db: &SharedDb) -> Result<()> {
+    let deadline = begin_shutdown();
+    while CHECK_RUNNING.load(Ordering::SeqCst) && Instant::now() < deadline {
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    }
+
+    // 価格チェック自身が中断を記録するのを少しだけ待つ（取得中の商品は待たない）
+    let grace = Instant::now() + std::time::Duration::from_secs(2);
+    while CHECK_RUNNING.load(Ordering::SeqCst) && Instant::now() < grace {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    let conn = db.lock().unwrap();
+    let interrupted = runs::interrupt_owned(&conn, &lease_owner())?;
+    if interrupted > 0 {
+        warn!("⏹️  期限までに終わらなかった価格チェック {}件を中断として記録しました", interrupted);
+    }
+    let pending = runs::pending_products(&conn)?.len();
+    if pending > 0 {
+        info!("⏹️  チェックし終えていない商品 {}件は次回の起動時にチェックします", pending);
+    }
+    // 期限までに終わらなかった価格チェックの実行権は、有効期限を待たずに手放す
+    release_lease(&conn)?;
+
+    Ok(())
+}
+
+/// Ctrl-C（SIGINT）か SIGTERM を受け取るまで待つ
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("❌ Ctrl-C の待ち受けに失敗: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("❌ SIGTERM の待ち受けに失敗: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// 前回中断した価格チェックの残りの商品を、すぐにチェックする
+fn resume_interrupted(conn: &Connection) -> Result<()> {
+    // ほかのプロセス（daemon など）が実行権を持って実行中のチェックは中断扱いにしない
+    let interrupted = runs::interrupt_abandoned(conn, &timestamp::now())?;
+    if interrupted > 0 {
+        warn!("⏹️  前回終わらなかった価格チェック {}件を中断として記録しました", interrupted);
+    }
+
+    let pending = runs::pending_products(conn)?;
+    let now = timestamp::now();
+    for product_id in &pending {
+        conn.execute(
+            "UPDATE products SET next_check_at = ?1 WHERE id = ?2",
+            (&now, product_id),
+        )?;
+    }
+    if !pending.is_empty() {
+        info!("🔁 前回中断した価格チェックの残り {}件を再開します", pending.len());
+    }
+
+    Ok(())
+}
+
+/// スケジューラーの状態
+#[derive(Serialize)]
+pub struct SchedulerStatus {
+    /// 価格チェックのスケジュール（Cron形式）
+    pub schedule: String,
+    /// 一時停止した日時（null なら動作中）
+    pub paused_at: Option<String>,
+    /// 手動実行がリクエストされ、まだ始まっていない
+    pub run_requested_at: Option<String>,
+    /// 実行中の価格チェック
+    pub running: Option<CheckRun>,
+    /// 次に商品をチェックする日時
+    pub next_check_at: Option<String>,
+    /// チェックする時刻を過ぎている商品の数
+    pub due: usize,
+}
+
+/// スケジューラーの状態を取得
+pub fn status(conn: &Connection) -> Result<SchedulerStatus> {
+    let (paused_at, run_requested_at) = conn.query_row(
+        "SELECT paused_at, run_requested_at FROM scheduler_state WHERE id = 1",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let (next_check_at, due) = conn.query_row(
+        "SELECT MIN(next_check_at), COUNT(*) FILTER (WHERE next_check_at IS NULL OR next_check_at <= ?1)
+         FROM products WHERE archived_at IS NULL AND disabled_at IS NULL",
+        [timestamp::now()],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    Ok(SchedulerStatus {
+        schedule: check_schedule().to_string(),
+        paused_at,
+        run_requested_at,
+        running: runs::current_run(conn)?,
+        next_check_at,
+        due,
+    })
+}
+
+/// 定期価格チェックを一時停止（すでに停止中なら false）
+pub fn pause(conn: &Connection) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE scheduler_state SET paused_at = ?1 WHERE id = 1 AND paused_at IS NULL",
+        [timestamp::now()],
+    )?;
+    Ok(changed > 0)
+}
+
+/// 定期価格チェックを再開（停止していなければ false）
+pub fn resume(conn: &Connection) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE scheduler_state SET paused_at = NULL WHERE id = 1 AND paused_at IS NOT NULL",
+        [],
+    )?;
+    Ok(changed > 0)
+}
+
+/// 全商品の価格チェックをリクエスト（サーバーか daemon のスケジューラーが1分以内に実行する）
+pub fn request_run(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE scheduler_state SET run_requested_at = ?1 WHERE id = 1",
+        [timestamp::now()],
+    )?;
+    Ok(())
+}
+
+/// 手動実行のリクエストを取り出す（あれば true）
+fn take_run_request(conn: &Connection) -> Result<bool> {
+    let changed = conn.execute(
+        "UPDATE scheduler_state SET run_requested_at = NULL WHERE id = 1 AND run_requested_at IS NOT NULL",
+        [],
+    )?;
+    Ok(changed > 0)
+}
+
+fn is_paused(conn: &Connection) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT paused_at IS NOT NULL FROM scheduler_state WHERE id = 1",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// 全商品の価格チェックを今すぐ始める（ほかのチェックが実行中なら false）
+pub fn spawn_run_now(db: SharedDb) -> bool {
+    let Some(guard) = try_begin_run(&db) else {
+        return false;
+    };
+
+    tokio::spawn(async move {
+        let _guard = guard;
+        info!("▶️  手動実行: 全商品の価格チェックを開始します");
+        let products = {
+            let conn = db.lock().unwrap();
+            products_to_check(&conn, None)
+        };
+        let result = match products {
+            Ok(products) => check_prices(db, products, Trigger::Manual, None).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            error!("❌ 手動実行の価格チェックでエラー: {}", e);
+        }
+    });
+
+    true
+}
+
+/// チェックする時刻を大きく過ぎている商品があれば、最も古いチェック時刻（取りこぼしが始まった日時）
+fn overdue_since(conn: &Connection, now: DateTime<Utc>) -> Result<Option<String>> {
+    let tolerance = now - Duration::minutes(CATCH_UP_TOLERANCE_MINUTES);
+    Ok(conn.query_row(
+        "SELECT MIN(next_check_at) FROM products
+         WHERE archived_at IS NULL AND disabled_at IS NULL AND next_check_at < ?1",
+        [timestamp::to_db(tolerance)],
+        |row| row.get(0),
+    )?)
+}
+
+/// 前回の1分ごとの確認の時刻（UNIX秒）。スリープからの復帰を検出するのに使う
+static LAST_TICK: AtomicI64 = AtomicI64::new(0);
+
+/// 1分ごとの確認: 手動実行のリクエストがあれば全商品、一時停止中でなければチェックする時刻が来た商品をチェック
+///
+/// 停止・スリープ中にチェックする時刻を過ぎた商品もここでまとめてチェックし、取りこぼした期間を実行記録に残す。
+async fn run_scheduled(db: SharedDb) -> Result<()> {
+    let now = Utc::now();
+    let last_tick = LAST_TICK.swap(now.timestamp(), Ordering::SeqCst);
+    if last_tick > 0 && now.timestamp() - last_tick > WAKE_DETECT_SECONDS {
+        info!("💤 スリープからの復帰を検出しました（{}分間止まっていました）", (now.timestamp() - last_tick) / 60);
+    }
+
+    let (products, trigger, missed_since) = {
+        let conn = db.lock().unwrap();
+        if take_run_request(&conn)? {
+            info!("▶️  手動実行のリクエスト: 全商品の価格チェックを開始します");
+            (products_to_check(&conn, None)?, Trigger::Manual, None)
+        } else if is_paused(&conn)? {
+            return Ok(());
+        } else {
+            (products_to_check(&conn, Some(now))?, Trigger::Scheduler, overdue_since(&conn, now)?)
+        }
+    };
+
+    if let Some(missed_since) = &missed_since {
+        info!(
+            "⏭️  停止・スリープ中に取りこぼしたチェックを実行します（{}から）",
+            timestamp::display(missed_since)
+        );
+    }
+
+    check_prices(db, products, trigger, missed_since).await
+}
+
 /// 定期実行スケジューラーを起動
 pub async fn start_scheduler(db: SharedDb) -> Result<()> {
     info!("🕐 スケジューラーを起動します");
 
+    {
+        let conn = db.lock().unwrap();
+        reschedule_all(&conn)?;
+        resume_interrupted(&conn)?;
+        if is_paused(&conn)? {
+            info!("⏸️  定期価格チェックは一時停止中です（scheduler resume で再開）");
+        }
+    }
+
     let scheduler = JobScheduler::new().await?;
 
-    // 毎時0分に実行（例: 10:00, 11:00, 12:00...）
-    // Cron形式: "秒 分 時 日 月 曜日"
-    // "0 0 * * * *" = 毎時0分0秒
-    let job = Job::new_async("0 0 * * * *", move |_uuid, _lock| {
-        let db = db.clone();
+    // 1分ごとに、チェックする時刻（products.next_check_at）が来た商品だけをチェック
+    let check_db = db.clone();
+    let job = Job::new_async("0 * * * * *", move |_uuid, _lock| {
+        let db = check_db.clone();
         Box::pin(async move {
-            info!("⏰ 定期価格チェックを開始します");
-            if let Err(e) = check_all_prices(db).await {
+            let Some(_guard) = try_begin_run(&db) else {
+                return;
+            };
+            if let Err(e) = run_scheduled(db).await {
                 error!("❌ 定期価格チェックでエラー: {}", e);
-            } else {
-                info!("✅ 定期価格チェック完了");
+            }
+        })
+    })?;
+
+    // 静かな時間帯に溜まったアラートを、時間帯が終わったら送る（5分ごとに確認）
+    // 価格チェック（CLI の check も溜まったアラートを送る）と同じ実行権の下で送り、同じアラートを二重に送らない
+    let queue_db = db.clone();
+    let queue_job = Job::new_async("0 */5 * * * *", move |_uuid, _lock| {
+        let db = queue_db.clone();
+        Box::pin(async move {
+            let Some(_guard) = try_begin_run(&db) else {
+                return;
+            };
+            if let Err(e) = flush_queued_alerts(db).await {
+                error!("❌ 溜まっていたアラートの送信でエラー: {}", e);
+            }
+        })
+    })?;
+
+    // 毎日3時30分に価格履歴を整理（毎時チェックと重ならないように）
+    let prune_db = db.clone();
+    let prune_job = Job::new_async("0 30 3 * * *", move |_uuid, _lock| {
+        let db = prune_db.clone();
+        Box::pin(async move {
+            info!("🧹 価格履歴の整理を開始します");
+            let conn = db.lock().unwrap();
+            match retention::prune(&conn, retention::retention_days()) {
+                Ok(stats) => info!(
+                    "✅ 価格履歴の整理完了: 圧縮 {}行 / 日次集約 {}行",
+                    stats.compacted, stats.downsampled
+                ),
+                Err(e) => error!("❌ 価格履歴の整理でエラー: {}", e),
+            }
+        })
+    })?;
+
+    // 毎朝8時（表示用のタイムゾーン）に日次レポートを送信
+    let catch_up_db = db.clone();
+    let digest_job = Job::new_async_tz("0 0 8 * * *", timestamp::timezone(), move |_uuid, _lock| {
+        let db = db.clone();
+        Box::pin(async move {
+            if let Err(e) = send_digest(db).await {
+                error!("❌ 日次レポートでエラー: {}", e);
             }
         })
     })?;
 
     scheduler.add(job).await?;
+    scheduler.add(queue_job).await?;
+    scheduler.add(prune_job).await?;
+    scheduler.add(digest_job).await?;
     scheduler.start().await?;
 
-    info!("✅ スケジューラーが起動しました（毎時0分に実行）");
+    // 停止中にチェックする時刻を過ぎた商品があれば、最初の1分を待たずにすぐチェックする
+    tokio::spawn(async move {
+        let Some(_guard) = try_begin_run(&catch_up_db) else {
+            return;
+        };
+        if let Err(e) = run_scheduled(catch_up_db).await {
+            error!("❌ 起動時の価格チェックでエラー: {}", e);
+        }
+    });
+
+    info!(
+        "✅ スケジューラーが起動しました（価格チェック: {}、価格の変化の頻度に応じて{}〜{}ごと、在庫切れの商品は{}分ごと、毎日3時30分に履歴整理、毎朝8時に日次レポート）",
+        check_schedule(),
+        adaptive_bounds().min,
+        adaptive_bounds().max,
+        SOLD_OUT_INTERVAL_MINUTES
+    );
+
+    Ok(())
+}
+
+/// Web UI なしでスケジューラーだけを動かす（終了シグナルを受け取るまで）
+///
+/// `metrics` を指定すると、そのアドレスで GET /metrics（Prometheus のテキスト形式）を提供する。
+/// Web UI は別プロセスの `serve --no-scheduler` で同じDBを使って動かせる。
+pub async fn run_daemon(metrics: Option<std::net::SocketAddr>) -> Result<()> {
+    let db: SharedDb = Arc::new(Mutex::new(crate::db::init_db()?));
+    start_scheduler(db.clone()).await?;
+
+    if let Some(addr) = metrics {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("メトリクスのアドレス {} を使えません", addr))?;
+        info!("📈 メトリクス: http://{}/metrics", addr);
+        let app = crate::metrics::create_router(db.clone());
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                error!("❌ メトリクスのサーバーでエラー: {}", e);
+            }
+        });
+    }
+
+    shutdown_signal().await;
+    info!(
+        "🛑 終了します: 実行中の価格チェックを最大{}秒待ちます",
+        SHUTDOWN_TIMEOUT.as_secs()
+    );
+    wait_for_running_check(&db).await?;
+    info!("👋 スケジューラーを停止しました");
+
+    Ok(())
+}
+
+/// 静かな時間帯に溜まったアラートを送る（静かな時間帯の間は何もしない）
+///
+/// 実行権を持って呼ぶこと（価格チェックの実行中なら、次の確認まで待つ）。
+async fn flush_queued_alerts(db: SharedDb) -> Result<()> {
+    let (queued, channels) = {
+        let conn = db.lock().unwrap();
+        (alerts::queued_alerts(&conn)?, notify::list_channels(&conn)?)
+    };
+    if queued.is_empty() {
+        return Ok(());
+    }
+
+    info!("🔔 静かな時間帯に溜まっていたアラート {}件を送信します", queued.len());
+    let deliveries = notify::deliver(&channels, &queued).await;
+    let conn = db.lock().unwrap();
+    notify::record_deliveries(&conn, &deliveries)?;
+    let kept = alerts::clear_queued(&conn, &queued, &deliveries)?;
+    if kept > 0 {
+        warn!("⚠️  送れなかったアラート {}件は次のチェックで送り直します", kept);
+    }
 
     Ok(())
 }
 
-/// 全商品の価格をチェック
-async fn check_all_prices(db: SharedDb) -> Result<()> {
-    let products: Vec<(i64, String, String)> = {
+/// 日次レポートを作成して、レポートを受け取る通知先に送る
+async fn send_digest(db: SharedDb) -> Result<()> {
+    let (digest, channels) = {
         let conn = db.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT id, url, name FROM products")?;
-        
-        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
-            .filter_map(Result::ok)
-            .collect()
+        (
+            digest::build(&conn, digest::DEFAULT_DIGEST_HOURS)?,
+            notify::list_channels(&conn)?,
+        )
     };
 
-    info!("📦 {}件の商品をチェックします", products.len());
+    if digest.is_empty() {
+        info!("📊 日次レポート: 報告することがないので送信しません");
+        return Ok(());
+    }
+
+    for (channel_id, result) in notify::deliver_digest(&channels, &digest).await {
+        match result {
+            Ok(()) => info!("📊 日次レポートを送信しました (通知先 {})", channel_id),
+            Err(e) => error!("❌ 日次レポートの送信失敗 (通知先 {}): {}", channel_id, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// 商品の価格をチェック（取りこぼしたチェックなら `missed_since` にデータがない期間の始まり）
+async fn check_prices(
+    db: SharedDb,
+    products: Vec<(i64, String, String)>,
+    trigger: Trigger,
+    missed_since: Option<String>,
+) -> Result<()> {
+    if products.is_empty() {
+        return Ok(());
+    }
+    info!("⏰ 定期価格チェックを開始します: {}件の商品", products.len());
+
+    let ids: Vec<i64> = products.iter().map(|(id, _, _)| *id).collect();
+    let (run_id, channels) = {
+        let conn = db.lock().unwrap();
+        let channels = notify::list_channels(&conn)?;
+        let run_id = runs::start_run(&conn, trigger, &ids)?;
+        if let Some(missed_since) = &missed_since
+            && let Err(e) = runs::mark_catch_up(&conn, run_id, missed_since)
+        {
+            error!("❌ 実行記録の保存エラー: {}", e);
+        }
+        (run_id, channels)
+    };
 
     let mut success_count = 0;
     let mut error_count = 0;
+    let mut interrupted = false;
+
+    // 取得はレート制限の設定に従って先に進め（同時に max_concurrent 件まで）、結果は商品の順に保存する
+    use futures::stream::{self, StreamExt};
+    let urls: Vec<String> = products.iter().map(|(_, url, _)| url.clone()).collect();
+    let mut fetches = stream::iter(urls)
+        .map(|url| async move { scraper::fetch_throttled(&url).await })
+        .buffered(scraper::rate_limit().max_concurrent);
 
     // 🔧 &products に変更（参照でイテレート）
-    for (product_id, url, name) in &products {
+    for (product_id, _, name) in &products {
+        // 終了処理の期限を過ぎたら、残りの商品は次の起動時に回す
+        if shutdown_deadline_passed() {
+            interrupted = true;
+            break;
+        }
         info!("🔍 チェック中: {}", name);
 
-        match fetch_amazon_price(url).await {
-            Ok(product) => {
-                let conn = db.lock().unwrap();
-                if let Err(e) = save_product(&conn, &product) {
-                    error!("❌ 商品保存エラー ({}): {}", name, e);
-                    error_count += 1;
-                    continue;
-                }
-                if let Err(e) = save_price_history(&conn, *product_id, product.price) {
-                    error!("❌ 価格履歴保存エラー ({}): {}", name, e);
-                    error_count += 1;
-                    continue;
-                }
-                drop(conn);
+        let Some((fetched, latency)) = fetches.next().await else {
+            break;
+        };
 
-                info!("✅ 更新成功: {} - ¥{}", name, product.price);
-                success_count += 1;
+        // ロックを保持したまま await しないようにブロックで区切る
+        let (outcome, triggered) = {
+            let conn = db.lock().unwrap();
+            if let Err(e) = renew_lease(&conn) {
+                error!("❌ 価格チェックの実行権の延長エラー: {}", e);
+            }
+            // アラート評価用に、今回の価格を保存する前の統計を取っておく
+            let stats = HistoryStats::load(&conn, *product_id);
+            let outcome = match fetched {
+                Ok(product) => save_product(&conn, &product)
+                    .context("商品保存エラー")
+                    .and_then(|_| match product.price {
+                        Some(price) => {
+                            save_price_history(&conn, *product_id, price).context("価格履歴保存エラー")
+                        }
+                        None => Ok(()),
+                    })
+                    .map(|_| (product.price, product.in_stock)),
+                Err(e) => Err(e.context("スクレイピングエラー")),
+            };
+            let price = outcome.as_ref().map(|(price, _)| *price);
+            match runs::record_attempt(&conn, run_id, *product_id, price, latency) {
+                Ok(true) => warn!("⚠️  要確認にしました（チェックの間隔を空けます）: {}", name),
+                Ok(false) => {}
+                Err(e) => error!("❌ 実行記録の保存エラー ({}): {}", name, e),
+            }
+            if let Err(e) = reschedule(&conn, *product_id) {
+                error!("❌ 次のチェック日時の保存エラー ({}): {}", name, e);
+            }
+            // 在庫切れの間はアラートを評価しない
+            let triggered = match &outcome {
+                Ok((Some(price), true)) => stats.and_then(|stats| alerts::evaluate(&conn, *product_id, &stats, *price)),
+                _ => Ok(Vec::new()),
+            };
+            let triggered = triggered.unwrap_or_else(|e| {
+                error!("❌ アラート評価エラー ({}): {}", name, e);
+                Vec::new()
+            });
+            (outcome, triggered)
+        };
+
+        for alert in &triggered {
+            if alert.queued {
+                info!("🔕 アラート（静かな時間帯のため後で通知）: {} - {}", name, alert.message);
+            } else {
+                info!("🔔 アラート: {} - {}", name, alert.message);
+            }
+        }
+
+        let deliveries = notify::deliver(&channels, &triggered).await;
+        if !deliveries.is_empty() {
+            let conn = db.lock().unwrap();
+            if let Err(e) = notify::record_deliveries(&conn, &deliveries) {
+                error!("❌ 配信ログの保存エラー ({}): {}", name, e);
+            }
+        }
 
-                // レート制限対策: 各リクエスト間に1秒待機
-                let _ = tokio::time::sleep(tokio::time::Duration::from_secs(1));
+        match outcome {
+            Ok((price, in_stock)) => {
+                match (price, in_stock) {
+                    (Some(price), true) => info!("✅ 更新成功: {} - ¥{}", name, price),
+                    _ => info!("🚫 在庫切れ: {}", name),
+                }
+                success_count += 1;
             }
             Err(e) => {
-                error!("❌ スクレイピングエラー ({}): {}", name, e);
+                error!("❌ {} ({}): {}", e, name, e.root_cause());
                 error_count += 1;
             }
         }
     }
 
+    if interrupted {
+        let conn = db.lock().unwrap();
+        runs::interrupt_run(&conn, run_id)?;
+        warn!(
+            "⏹️  定期価格チェックを中断しました: 成功 {}件 / エラー {}件（残り {}件は次回の起動時にチェック）",
+            success_count,
+            error_count,
+            products.len() - success_count - error_count
+        );
+        return Ok(());
+    }
+
+    {
+        let conn = db.lock().unwrap();
+        runs::finish_run(&conn, run_id)?;
+    }
+
     info!(
-        "📊 結果: 成功 {}件 / エラー {}件 / 合計 {}件",
+        "✅ 定期価格チェック完了: 成功 {}件 / エラー {}件 / 合計 {}件",
         success_count,
         error_count,
         products.len()
@@ -95,3 +985,210 @@ async fn check_all_prices(db: SharedDb) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn bounds() -> AdaptiveBounds {
+        AdaptiveBounds {
+            min: "1h".parse().unwrap(),
+            max: "1d".parse().unwrap(),
+        }
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        timestamp::parse(s).unwrap()
+    }
+
+    #[test]
+    fn interval_shrinks_with_changes_within_bounds() {
+        let bounds = bounds();
+        assert_eq!(bounds.interval(0).minutes, 24 * 60);
+        assert_eq!(bounds.interval(1).minutes, 24 * 60);
+        // 1週間に14回（1日2回）変わる → 3時間ごと
+        assert_eq!(bounds.interval(14).minutes, 3 * 60);
+        assert_eq!(bounds.interval(500).minutes, 60);
+    }
+
+    #[test]
+    fn adaptive_due_follows_schedule_ticks() {
+        let hourly: CheckSchedule = "0 0 * * * *".parse().unwrap();
+        // 毎時0分の実行が数十秒かかっても、次は間隔ぶん後の0分
+        let last = at("2026-10-19T10:00:40Z");
+        assert_eq!(
+            next_due(&hourly, &bounds(), None, 500, true, last).unwrap(),
+            Some(at("2026-10-19T11:00:00Z"))
+        );
+        assert_eq!(
+            next_due(&hourly, &bounds(), None, 14, true, last).unwrap(),
+            Some(at("2026-10-19T13:00:00Z"))
+        );
+        assert_eq!(
+            next_due(&hourly, &bounds(), None, 0, true, last).unwrap(),
+            Some(at("2026-10-20T10:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn attention_backoff_doubles_up_to_a_week() {
+        assert_eq!(attention_backoff(1), Duration::hours(6));
+        assert_eq!(attention_backoff(runs::NEEDS_ATTENTION_FAILURES), Duration::hours(6));
+        assert_eq!(attention_backoff(runs::NEEDS_ATTENTION_FAILURES + 2), Duration::hours(24));
+        assert_eq!(attention_backoff(100), Duration::days(7));
+    }
+
+    #[test]
+    fn explicit_interval_and_sold_out() {
+        let hourly: CheckSchedule = "0 0 * * * *".parse().unwrap();
+        let last = at("2026-10-19T10:07:00Z");
+        let interval = Some(CheckInterval { minutes: 30 });
+        assert_eq!(
+            next_due(&hourly, &bounds(), interval, 0, true, last).unwrap(),
+            Some(at("2026-10-19T10:37:00Z"))
+        );
+        assert_eq!(
+            next_due(&hourly, &bounds(), None, 0, false, last).unwrap(),
+            Some(at("2026-10-19T10:22:00Z"))
+        );
+    }
+
+    #[test]
+    fn interval_parsing_rejects_out_of_range() {
+        assert_eq!("90".parse::<CheckInterval>().unwrap().minutes, 90);
+        assert_eq!("2h".parse::<CheckInterval>().unwrap().minutes, 120);
+        assert_eq!("365d".parse::<CheckInterval>().unwrap().minutes, MAX_CHECK_INTERVAL_MINUTES);
+        assert!("0m".parse::<CheckInterval>().is_err());
+        assert!("366d".parse::<CheckInterval>().is_err());
+        // 掛け算で桁あふれする値もエラーにする
+        assert!("9223372036854775807h".parse::<CheckInterval>().is_err());
+        assert!("6405119470038038d".parse::<CheckInterval>().is_err());
+        assert!("99999999999999999999m".parse::<CheckInterval>().is_err());
+    }
+
+    #[test]
+    fn huge_stored_interval_is_an_error_not_a_panic() {
+        let hourly: CheckSchedule = "0 0 * * * *".parse().unwrap();
+        let last = at("2026-10-19T10:07:00Z");
+        let interval = Some(CheckInterval { minutes: i64::MAX });
+        assert!(next_due(&hourly, &bounds(), interval, 0, true, last).is_err());
+    }
+
+    #[test]
+    fn archived_and_disabled_products_are_not_checked() {
+        let conn = db::open_in_memory().unwrap();
+        for name in ["active", "archived", "disabled"] {
+            conn.execute(
+                "INSERT INTO products (url, name, current_price, created_at)
+                 VALUES (?1, ?2, 1000, '2026-10-01T00:00:00Z')",
+                (format!("https://example.com/{}", name), name),
+            )
+            .unwrap();
+        }
+        assert!(db::archive_product(&conn, 2).unwrap());
+        conn.execute("UPDATE products SET disabled_at = '2026-10-01T00:00:00Z' WHERE id = 3", [])
+            .unwrap();
+
+        let ids = |due_at| -> Vec<i64> {
+            products_to_check(&conn, due_at).unwrap().into_iter().map(|(id, ..)| id).collect()
+        };
+        assert_eq!(ids(None), [1]);
+        assert_eq!(ids(Some(at("2026-10-19T00:00:00Z"))), [1]);
+        // 元に戻せば、またチェックする
+        assert!(db::restore_product(&conn, 2).unwrap());
+        assert_eq!(ids(None), [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn overdue_products_start_a_catch_up_run_recorded_as_a_gap() {
+        let conn = db::open_in_memory().unwrap();
+        let now = Utc::now();
+        // 停止している間に、3時間前と2時間前にチェックするはずだった商品と、まだ時刻が来ていない商品
+        // （取得は接続を拒否されてすぐ失敗する）
+        for (i, next) in [now - Duration::hours(3), now - Duration::hours(2), now + Duration::hours(1)]
+            .into_iter()
+            .enumerate()
+        {
+            conn.execute(
+                "INSERT INTO products (url, name, current_price, created_at, next_check_at)
+                 VALUES (?1, 'テスト商品', 1000, '2026-10-01T00:00:00Z', ?2)",
+                (format!("http://127.0.0.1:1/{}", i), timestamp::to_db(next)),
+            )
+            .unwrap();
+        }
+        let missed_since = timestamp::to_db(now - Duration::hours(3));
+        assert_eq!(overdue_since(&conn, now).unwrap().as_deref(), Some(missed_since.as_str()));
+        // 許容範囲内の遅れは取りこぼしにしない
+        assert_eq!(overdue_since(&conn, now - Duration::hours(3)).unwrap(), None);
+
+        let db: SharedDb = Arc::new(Mutex::new(conn));
+        run_scheduled(db.clone()).await.unwrap();
+
+        let conn = db.lock().unwrap();
+        let (started_at, total, run_missed_since): (String, i64, Option<String>) = conn
+            .query_row(
+                "SELECT started_at, total, missed_since FROM check_runs ORDER BY id DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(run_missed_since.as_deref(), Some(missed_since.as_str()));
+
+        // データがない期間は、取りこぼしの始まりから取り戻した実行の開始まで
+        let gaps = runs::list_gaps(&conn, None, None).unwrap();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!((gaps[0].from.as_str(), gaps[0].to.as_str()), (missed_since.as_str(), started_at.as_str()));
+        // 期間を指定したときは、重なる期間だけ
+        let before = timestamp::to_db(now - Duration::hours(4));
+        let inside = timestamp::to_db(now - Duration::hours(1));
+        assert_eq!(runs::list_gaps(&conn, Some(&before), Some(&inside)).unwrap().len(), 1);
+        assert!(runs::list_gaps(&conn, None, Some(&before)).unwrap().is_empty());
+        assert!(runs::list_gaps(&conn, Some(&timestamp::to_db(now + Duration::hours(1))), None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn interrupted_run_resumes_only_unattempted_products() {
+        let conn = db::open_in_memory().unwrap();
+        let mut ids = Vec::new();
+        for i in 1..=3 {
+            conn.execute(
+                "INSERT INTO products (url, name, current_price, created_at, next_check_at)
+                 VALUES (?1, 'テスト商品', 1000, '2026-10-19T00:00:00Z', '2030-01-01T00:00:00Z')",
+                [format!("https://example.com/{}", i)],
+            )
+            .unwrap();
+            ids.push(conn.last_insert_rowid());
+        }
+
+        // 1件目だけチェックしたところで異常終了した（実行権は持っていない）
+        let run_id = runs::start_run(&conn, Trigger::Scheduler, &ids).unwrap();
+        runs::record_attempt(&conn, run_id, ids[0], Ok(Some(980)), std::time::Duration::from_millis(5)).unwrap();
+
+        let before = timestamp::now();
+        resume_interrupted(&conn).unwrap();
+
+        let (interrupted, finished, succeeded): (bool, bool, i64) = conn
+            .query_row(
+                "SELECT interrupted, finished_at IS NOT NULL, succeeded FROM check_runs WHERE id = ?1",
+                [run_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((interrupted, finished, succeeded), (true, true, 1));
+
+        let next_check_at = |id: i64| -> String {
+            conn.query_row("SELECT next_check_at FROM products WHERE id = ?1", [id], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(next_check_at(ids[0]), "2030-01-01T00:00:00Z");
+        for id in &ids[1..] {
+            let next = next_check_at(*id);
+            assert!(next >= before && next.as_str() < "2030", "{}", next);
+        }
+        assert_eq!(runs::pending_products(&conn).unwrap(), &ids[1..]);
+    }
+}
@@ -1,21 +1,176 @@
 use anyhow::Result;
 use scraper::{Html, Selector};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// 商品ページを取得するときのデフォルトの User-Agent
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/140.0.0.0 Safari/537.36";
+/// 商品ページの取得を諦めるまでのデフォルトの時間（秒）
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+/// 商品を続けてチェックするときの、デフォルトのリクエストの間隔（ミリ秒）
+pub const DEFAULT_REQUEST_DELAY_MS: u64 = 1000;
+/// 商品を続けてチェックするときに、同時に取得するデフォルトの商品数
+pub const DEFAULT_MAX_CONCURRENT: usize = 4;
+
+/// 商品ページを取得するHTTPクライアントの設定
+#[derive(Debug, Clone)]
+pub struct HttpSettings {
+    pub timeout: Duration,
+    pub user_agent: String,
+}
+
+/// 商品ページを取得する頻度の制限（レート制限対策）
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// 商品を続けてチェックするときのリクエストの間隔
+    pub request_delay: Duration,
+    /// 同時に取得する商品数の上限
+    pub max_concurrent: usize,
+}
+
+static HTTP_SETTINGS: OnceLock<HttpSettings> = OnceLock::new();
+static RATE_LIMIT: OnceLock<RateLimit> = OnceLock::new();
+
+/// HTTPクライアントと頻度の制限を設定（起動時に1回だけ）
+pub fn set_http_settings(http: HttpSettings, rate_limit: RateLimit) {
+    let _ = HTTP_SETTINGS.set(http);
+    let _ = RATE_LIMIT.set(rate_limit);
+}
+
+fn http_settings() -> &'static HttpSettings {
+    HTTP_SETTINGS.get_or_init(|| HttpSettings {
+        timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+        user_agent: DEFAULT_USER_AGENT.to_string(),
+    })
+}
+
+/// 商品ページを取得する頻度の制限
+pub fn rate_limit() -> RateLimit {
+    *RATE_LIMIT.get_or_init(|| RateLimit {
+        request_delay: Duration::from_millis(DEFAULT_REQUEST_DELAY_MS),
+        max_concurrent: DEFAULT_MAX_CONCURRENT,
+    })
+}
 
 pub struct Product {
     pub name: String,
-    pub price: i32,
+    /// 価格（在庫切れで価格が表示されていない場合は None）
+    pub price: Option<i32>,
+    /// 在庫があるか
+    pub in_stock: bool,
     pub url: String,
+    /// 商品画像のURL（通知に使う。見つからなければ None）
+    pub image_url: Option<String>,
+    /// 出品者（「Amazon.co.jp」など。見つからなければ None）
+    pub seller: Option<String>,
+}
+
+/// スクレイピング失敗の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrapeErrorKind {
+    /// 接続できない・タイムアウトなど
+    Network,
+    /// 2xx 以外のステータスが返った
+    Http,
+    /// ページは取れたが商品名・価格が見つからない
+    Parse,
+}
+
+impl ScrapeErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScrapeErrorKind::Network => "network",
+            ScrapeErrorKind::Http => "http",
+            ScrapeErrorKind::Parse => "parse",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ScrapeError {
+    pub kind: ScrapeErrorKind,
+    pub http_status: Option<u16>,
+    message: String,
+}
+
+impl ScrapeError {
+    fn new(kind: ScrapeErrorKind, http_status: Option<u16>, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            http_status,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ScrapeError {}
+
+/// 在庫切れの表示（「現在在庫切れです」「一時的に在庫切れ」など）
+const OUT_OF_STOCK_PHRASES: &[&str] = &["在庫切れ", "現在お取り扱いできません", "currently unavailable", "out of stock"];
+
+/// ページに在庫切れの表示があるか
+fn is_out_of_stock(document: &Html) -> bool {
+    let out_of_stock_selector = Selector::parse("#outOfStock").unwrap();
+    if document.select(&out_of_stock_selector).next().is_some() {
+        return true;
+    }
+
+    let availability_selector = Selector::parse("#availability").unwrap();
+    document.select(&availability_selector).next().is_some_and(|el| {
+        let text = el.text().collect::<String>().to_lowercase();
+        OUT_OF_STOCK_PHRASES.iter().any(|phrase| text.contains(phrase))
+    })
+}
+
+/// 頻度の制限を守って商品ページを取得し、結果と取得にかかった時間を返す（価格チェックで商品を続けて取得するとき）
+///
+/// 取得のあと、成功・失敗にかかわらず `request_delay` だけ待つ（エラーが続いても間を空けずに取り直さないように）。
+/// 呼び出し側は `buffered(rate_limit().max_concurrent)` で同時に取得する数を抑える。
+pub async fn fetch_throttled(url: &str) -> (Result<Product>, Duration) {
+    let started = Instant::now();
+    let result = fetch_amazon_price(url).await;
+    let latency = started.elapsed();
+    tokio::time::sleep(rate_limit().request_delay).await;
+    (result, latency)
 }
 
 // 🔧 async fnに変更、reqwest::blockingを削除
 pub async fn fetch_amazon_price(url: &str) -> Result<Product> {
+    let settings = http_settings();
     let client = reqwest::Client::builder()
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/140.0.0.0 Safari/537.36")
+        .user_agent(&settings.user_agent)
+        .timeout(settings.timeout)
         .build()?;
 
     // 🔧 .awaitを追加
-    let resp = client.get(url).send().await?;
-    let body = resp.text().await?;
+    let resp = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| ScrapeError::new(ScrapeErrorKind::Network, None, e.to_string()))?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(ScrapeError::new(
+            ScrapeErrorKind::Http,
+            Some(status.as_u16()),
+            format!("HTTP {}", status),
+        )
+        .into());
+    }
+
+    let body = resp
+        .text()
+        .await
+        .map_err(|e| ScrapeError::new(ScrapeErrorKind::Network, Some(status.as_u16()), e.to_string()))?;
+    let parse_error =
+        |message: String| ScrapeError::new(ScrapeErrorKind::Parse, Some(status.as_u16()), message);
 
     // parse HTML
     let document = Html::parse_document(&body);
@@ -25,32 +180,59 @@ pub async fn fetch_amazon_price(url: &str) -> Result<Product> {
     let title = document
         .select(&title_selector)
         .next()
-        .ok_or_else(|| anyhow::anyhow!("Product title not found"))?
+        .ok_or_else(|| parse_error("Product title not found".to_string()))?
         .text()
         .collect::<String>()
         .trim()
         .to_string();
 
-    // extract product price
+    let in_stock = !is_out_of_stock(&document);
+
+    // extract product price（在庫切れのページには価格がないことがある）
     let price_selector = Selector::parse(".a-price .a-offscreen").unwrap();
-    let price_text = document
-        .select(&price_selector)
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Product price not found"))?
-        .text()
-        .collect::<String>();
+    let price = match document.select(&price_selector).next() {
+        Some(el) => {
+            let price_text = el.text().collect::<String>();
+
+            // convert price to integer (remove ¥ and comma)
+            let price: i32 = price_text
+                .chars()
+                .filter(|c| c.is_numeric())
+                .collect::<String>()
+                .parse()
+                .map_err(|_| parse_error(format!("Failed to parse price: {}", price_text)))?;
+            Some(price)
+        }
+        None if !in_stock => None,
+        None => return Err(parse_error("Product price not found".to_string()).into()),
+    };
+
+    // extract product image (optional)
+    let image_selector = Selector::parse("#landingImage, #imgBlkFront").unwrap();
+    let image_url = document.select(&image_selector).next().and_then(|img| {
+        ["data-old-hires", "src"]
+            .iter()
+            .filter_map(|attr| img.value().attr(attr))
+            .find(|src| src.starts_with("https://") || src.starts_with("http://"))
+            .map(str::to_string)
+    });
+
+    // extract seller (optional)
+    let seller_selector = Selector::parse(
+        "#sellerProfileTriggerId, #merchantInfoFeature_feature_div .offer-display-feature-text-message, #merchant-info a",
+    )
+    .unwrap();
+    let seller = document
+        .select(&seller_selector)
+        .map(|el| el.text().collect::<String>().trim().to_string())
+        .find(|text| !text.is_empty());
 
-    // convert price to integer (remove ¥ and comma)
-    let price: i32 = price_text
-        .chars()
-        .filter(|c| c.is_numeric())
-        .collect::<String>()
-        .parse()
-        .map_err(|_| anyhow::anyhow!("Failed to parse price: {}", price_text))?;
-    
     Ok(Product {
         name: title,
         price,
+        in_stock,
         url: url.to_string(),
+        image_url,
+        seller,
     })
 }
@@ -0,0 +1,173 @@
+use anyhow::Result;
+use rusqlite::{types::Value, Connection};
+use serde::Serialize;
+
+/// 検索結果の件数のデフォルトと上限
+pub const DEFAULT_LIMIT: usize = 20;
+pub const MAX_LIMIT: usize = 200;
+
+/// trigram で索引を引ける最短の語の長さ（文字数）。これより短い語は LIKE で探す
+const TRIGRAM_LEN: usize = 3;
+
+/// 商品名の検索結果の1件
+#[derive(Serialize)]
+pub struct SearchHit {
+    pub id: i64,
+    pub url: String,
+    pub name: String,
+    pub current_price: i32,
+    pub in_stock: bool,
+    pub seller: Option<String>,
+    pub archived: bool,
+}
+
+/// FTS5 のフレーズとして扱う（" は "" にする）
+fn fts_phrase(term: &str) -> String {
+    format!("\"{}\"", term.replace('"', "\"\""))
+}
+
+/// LIKE の % と _ を文字として扱う
+fn like_pattern(term: &str) -> String {
+    let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// 商品名（表示名を設定していればそちら）を全文検索（空白で区切った語をすべて含む商品を、よく一致する順に返す）
+///
+/// 索引は trigram なので、3文字以上の語は FTS5 で、「お茶」のような2文字以下の語は LIKE で探す。
+/// 大文字・小文字は区別しない。`archived` が true ならアーカイブ済みの商品も含める。
+pub fn search_products(conn: &Connection, query: &str, archived: bool, limit: usize) -> Result<Vec<SearchHit>> {
+    let (long, short): (Vec<&str>, Vec<&str>) =
+        query.split_whitespace().partition(|term| term.chars().count() >= TRIGRAM_LEN);
+    if long.is_empty() && short.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut params: Vec<Value> = Vec::new();
+    let mut sql = String::from(
+        "SELECT p.id, p.url, COALESCE(p.display_name, p.name), p.current_price, p.in_stock, p.seller,
+                p.archived_at IS NOT NULL
+         FROM products p",
+    );
+    let order = if long.is_empty() {
+        "p.id DESC"
+    } else {
+        params.push(Value::Text(long.iter().map(|term| fts_phrase(term)).collect::<Vec<_>>().join(" ")));
+        sql.push_str(" JOIN products_fts ON products_fts.rowid = p.id AND products_fts MATCH ?1");
+        "products_fts.rank, p.id DESC"
+    };
+
+    sql.push_str(" WHERE 1 = 1");
+    if !archived {
+        sql.push_str(" AND p.archived_at IS NULL");
+    }
+    for term in short {
+        params.push(Value::Text(like_pattern(term)));
+        sql.push_str(&format!(" AND COALESCE(p.display_name, p.name) LIKE ?{} ESCAPE '\\'", params.len()));
+    }
+    params.push(Value::Integer(limit.clamp(1, MAX_LIMIT) as i64));
+    sql.push_str(&format!(" ORDER BY {} LIMIT ?{}", order, params.len()));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let hits = stmt
+        .query_map(rusqlite::params_from_iter(params), |row| {
+            Ok(SearchHit {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                name: row.get(2)?,
+                current_price: row.get(3)?,
+                in_stock: row.get(4)?,
+                seller: row.get(5)?,
+                archived: row.get(6)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn insert(conn: &Connection, url: &str, name: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at)
+             VALUES (?1, ?2, 1000, '2026-10-19T00:00:00Z')",
+            (url, name),
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn ids(conn: &Connection, query: &str) -> Vec<i64> {
+        let mut ids: Vec<i64> = search_products(conn, query, true, MAX_LIMIT)
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn short_terms_use_like_and_long_terms_use_the_index() {
+        let conn = db::open_in_memory().unwrap();
+        let case = insert(&conn, "https://example.com/1", "Apple iPhone ケース");
+        let tea = insert(&conn, "https://example.com/2", "伊藤園 お茶 緑茶 2L×9本");
+        let juice = insert(&conn, "https://example.com/3", "果汁100% オレンジ_ジュース");
+
+        // 3文字以上は trigram の索引（大文字・小文字は区別しない）
+        assert_eq!(ids(&conn, "IPHONE"), [case]);
+        assert_eq!(ids(&conn, "100%"), [juice]);
+        // 2文字以下は LIKE
+        assert_eq!(ids(&conn, "お茶"), [tea]);
+        assert_eq!(ids(&conn, "2l"), [tea]);
+        // 長い語と短い語を混ぜるとすべてを含む商品
+        assert_eq!(ids(&conn, "緑茶 2L"), [tea]);
+        assert_eq!(ids(&conn, "ケース 2L"), Vec::<i64>::new());
+        // LIKE の % と _ は文字として扱う
+        assert_eq!(ids(&conn, "%"), [juice]);
+        assert_eq!(ids(&conn, "_"), [juice]);
+        assert!(ids(&conn, "  ").is_empty());
+    }
+
+    #[test]
+    fn terms_are_quoted_as_phrases() {
+        assert_eq!(fts_phrase("iPhone"), "\"iPhone\"");
+        assert_eq!(fts_phrase("19\"型"), "\"19\"\"型\"");
+
+        let conn = db::open_in_memory().unwrap();
+        let monitor = insert(&conn, "https://example.com/1", "27\" 4K モニター");
+        assert_eq!(ids(&conn, "27\" 4K"), [monitor]);
+        // FTS5 の演算子や記号も検索語として扱う（構文エラーにしない）
+        assert!(ids(&conn, "モニター AND NOT").is_empty());
+        assert!(ids(&conn, "NEAR( *-^").is_empty());
+    }
+
+    #[test]
+    fn index_follows_insert_rename_and_delete() {
+        let conn = db::open_in_memory().unwrap();
+        let id = insert(&conn, "https://example.com/1", "ミネラルウォーター 2L");
+        assert_eq!(ids(&conn, "ウォーター"), [id]);
+
+        conn.execute("UPDATE products SET name = '炭酸水 500ml' WHERE id = ?1", [id]).unwrap();
+        assert!(ids(&conn, "ウォーター").is_empty());
+        assert_eq!(ids(&conn, "炭酸水"), [id]);
+
+        // 表示名があれば商品名の代わりに表示名で探す
+        conn.execute("UPDATE products SET display_name = 'いつもの水' WHERE id = ?1", [id]).unwrap();
+        assert!(ids(&conn, "炭酸水").is_empty());
+        assert_eq!(ids(&conn, "いつもの"), [id]);
+
+        conn.execute("UPDATE products SET display_name = NULL WHERE id = ?1", [id]).unwrap();
+        assert!(ids(&conn, "いつもの").is_empty());
+        assert_eq!(ids(&conn, "炭酸水"), [id]);
+
+        conn.execute("DELETE FROM products WHERE id = ?1", [id]).unwrap();
+        assert!(ids(&conn, "炭酸水").is_empty());
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM products_fts", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 0);
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::{bail, Result};
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::db::product_exists;
+
+/// タグの最大の長さ（文字数）
+const MAX_TAG_LEN: usize = 50;
+
+/// カテゴリーの最大の長さ（文字数）
+const MAX_CATEGORY_LEN: usize = 50;
+
+/// メモの最大の長さ（文字数）
+const MAX_NOTE_LEN: usize = 2000;
+
+/// 商品 p がタグ ?N を持つ条件（タグは大文字・小文字を区別しない）
+pub fn has_tag(param: usize) -> String {
+    format!(
+        "EXISTS (SELECT 1 FROM product_tags pt JOIN tags t ON t.id = pt.tag_id
+                 WHERE pt.product_id = p.id AND t.name = ?{})",
+        param
+    )
+}
+
+/// 商品のタグを名前順に並べたもの（US で区切る。`split_tags` で分ける）
+pub const TAG_LIST: &str = "(SELECT group_concat(name, char(31)) FROM (
+                                SELECT t.name FROM product_tags pt JOIN tags t ON t.id = pt.tag_id
+                                WHERE pt.product_id = p.id ORDER BY t.name COLLATE NOCASE))";
+
+/// タグの一覧の1件
+#[derive(Serialize)]
+pub struct TagCount {
+    pub name: String,
+    /// タグのついた商品の数（アーカイブ済みを除く）
+    pub products: i64,
+}
+
+/// `TAG_LIST` の値をタグごとに分ける
+pub fn split_tags(list: Option<String>) -> Vec<String> {
+    list.map(|s| s.split('\u{1f}').map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// タグの前後の空白を取り除き、使えない文字が含まれていないか確かめる
+pub fn normalize_tag(tag: &str) -> Result<String> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        bail!("Tag must not be empty");
+    }
+    if tag.chars().count() > MAX_TAG_LEN {
+        bail!("Tag must be at most {} characters: {}", MAX_TAG_LEN, tag);
+    }
+    if tag.chars().any(|c| c == ',' || c.is_control()) {
+        bail!("Tag must not contain commas or control characters: {}", tag);
+    }
+    Ok(tag.to_string())
+}
+
+/// カテゴリー・メモの前後の空白を取り除く（空なら None）
+pub fn normalize_category(category: &str) -> Result<Option<String>> {
+    let category = category.trim();
+    if category.chars().count() > MAX_CATEGORY_LEN {
+        bail!("Category must be at most {} characters", MAX_CATEGORY_LEN);
+    }
+    Ok((!category.is_empty()).then(|| category.to_string()))
+}
+
+pub fn normalize_note(note: &str) -> Result<Option<String>> {
+    let note = note.trim();
+    if note.chars().count() > MAX_NOTE_LEN {
+        bail!("Note must be at most {} characters", MAX_NOTE_LEN);
+    }
+    Ok((!note.is_empty()).then(|| note.to_string()))
+}
+
+/// 商品にタグをつける（タグは大文字・小文字を区別せず、最初につけたときの表記を使う）
+///
+/// 対象の商品が存在しない場合は `false` を返す。
+pub fn add_tags(conn: &Connection, product_id: i64, tags: &[String]) -> Result<bool> {
+    let tags = tags.iter().map(|t| normalize_tag(t)).collect::<Result<Vec<_>>>()?;
+    if !product_exists(conn, product_id)? {
+        return Ok(false);
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    attach_tags(&tx, product_id, &tags)?;
+    tx.commit()?;
+
+    Ok(true)
+}
+
+fn attach_tags(conn: &Connection, product_id: i64, tags: &[String]) -> Result<()> {
+    for tag in tags {
+        conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [tag])?;
+        conn.execute(
+            "INSERT OR IGNORE INTO product_tags (product_id, tag_id)
+             SELECT ?1, id FROM tags WHERE name = ?2",
+            (product_id, tag),
+        )?;
+    }
+    Ok(())
+}
+
+/// どの商品にもついていないタグを消す
+fn remove_unused_tags(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM product_tags)", [])?;
+    Ok(())
+}
+
+/// 商品からタグを外す（どの商品にもついていないタグは消す）
+///
+/// 対象の商品が存在しない場合は `false` を返す。
+pub fn remove_tags(conn: &Connection, product_id: i64, tags: &[String]) -> Result<bool> {
+    if !product_exists(conn, product_id)? {
+        return Ok(false);
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    for tag in tags {
+        tx.execute(
+            "DELETE FROM product_tags
+             WHERE product_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+            (product_id, tag.trim()),
+        )?;
+    }
+    remove_unused_tags(&tx)?;
+    tx.commit()?;
+
+    Ok(true)
+}
+
+/// 商品のタグを置き換える（正規化済みのタグを渡す。呼び出し側のトランザクションの中で使う）
+pub fn replace_tags(conn: &Connection, product_id: i64, tags: &[String]) -> Result<()> {
+    conn.execute("DELETE FROM product_tags WHERE product_id = ?1", [product_id])?;
+    attach_tags(conn, product_id, tags)?;
+    remove_unused_tags(conn)
+}
+
+/// 商品のタグ（名前順）
+pub fn product_tags(conn: &Connection, product_id: i64) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name FROM product_tags pt JOIN tags t ON t.id = pt.tag_id
+         WHERE pt.product_id = ?1 ORDER BY t.name COLLATE NOCASE",
+    )?;
+    let tags = stmt
+        .query_map([product_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(tags)
+}
+
+/// 使われているタグの一覧（名前順）
+pub fn list_tags(conn: &Connection) -> Result<Vec<TagCount>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name, COUNT(p.id)
+         FROM tags t
+         JOIN product_tags pt ON pt.tag_id = t.id
+         LEFT JOIN products p ON p.id = pt.product_id AND p.archived_at IS NULL
+         GROUP BY t.id
+         ORDER BY t.name COLLATE NOCASE",
+    )?;
+    let tags = stmt
+        .query_map([], |row| {
+            Ok(TagCount {
+                name: row.get(0)?,
+                products: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_trimmed_and_validated() {
+        assert_eq!(normalize_tag("  ゲーム ").unwrap(), "ゲーム");
+        assert!(normalize_tag("   ").is_err());
+        assert!(normalize_tag("a,b").is_err());
+        assert!(normalize_tag(&"x".repeat(MAX_TAG_LEN + 1)).is_err());
+        assert_eq!(split_tags(Some("a\u{1f}b".to_string())), ["a", "b"]);
+        assert!(split_tags(None).is_empty());
+    }
+}
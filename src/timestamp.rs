@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::sync::OnceLock;
+
+// DBにはUTCのRFC3339（例: 2026-10-19T03:00:00Z）で保存する
+// 桁数が固定なので文字列のまま比較・ソートできる
+
+/// 表示用のデフォルトのタイムゾーン
+pub const DEFAULT_TIMEZONE: &str = "Asia/Tokyo";
+
+static DISPLAY_TZ: OnceLock<Tz> = OnceLock::new();
+
+/// 表示用のタイムゾーンを設定（起動時に1回だけ）
+pub fn set_timezone(tz: Tz) {
+    let _ = DISPLAY_TZ.set(tz);
+}
+
+/// 表示用のタイムゾーン
+pub fn timezone() -> Tz {
+    *DISPLAY_TZ.get_or_init(|| DEFAULT_TIMEZONE.parse().unwrap())
+}
+
+/// 現在時刻をDB保存用の文字列で返す
+pub fn now() -> String {
+    to_db(Utc::now())
+}
+
+pub fn to_db(dt: DateTime<Utc>) -> String {
+    dt.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub fn parse(s: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(s)
+        .map_err(|e| anyhow!("Invalid timestamp '{}': {}", s, e))?
+        .with_timezone(&Utc))
+}
+
+/// API用: 設定したタイムゾーンのオフセット付きRFC3339に変換
+pub fn render(s: &str) -> String {
+    match parse(s) {
+        Ok(dt) => dt
+            .with_timezone(&timezone())
+            .to_rfc3339_opts(SecondsFormat::Secs, false),
+        Err(_) => s.to_string(),
+    }
+}
+
+/// CLI用: 設定したタイムゾーンで読みやすく表示
+pub fn display(s: &str) -> String {
+    match parse(s) {
+        Ok(dt) => dt
+            .with_timezone(&timezone())
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string(),
+        Err(_) => s.to_string(),
+    }
+}
+
+/// 設定したタイムゾーンでの日付（YYYY-MM-DD）
+pub fn local_day(s: &str) -> String {
+    match parse(s) {
+        Ok(dt) => dt.with_timezone(&timezone()).format("%Y-%m-%d").to_string(),
+        Err(_) => s.get(..10).unwrap_or(s).to_string(),
+    }
+}
+
+/// 範囲指定の境界をDB保存形式に変換
+///
+/// RFC3339 のほか `YYYY-MM-DD` も受け付け、設定したタイムゾーンの0時として扱う。
+pub fn parse_bound(s: &str) -> Result<String> {
+    if let Ok(dt) = parse(s) {
+        return Ok(to_db(dt));
+    }
+
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid date '{}' (expected RFC3339 or YYYY-MM-DD)", s))?;
+    let midnight = timezone()
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+        .earliest()
+        .ok_or_else(|| anyhow!("Invalid local date: {}", s))?;
+
+    Ok(to_db(midnight.with_timezone(&Utc)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_bound_is_local_midnight_in_utc() {
+        // デフォルトのタイムゾーン（Asia/Tokyo, UTC+9）の0時
+        assert_eq!(parse_bound("2026-10-19").unwrap(), "2026-10-18T15:00:00Z");
+        assert_eq!(parse_bound("2026-01-01").unwrap(), "2025-12-31T15:00:00Z");
+    }
+
+    #[test]
+    fn rfc3339_bound_is_converted_to_utc() {
+        assert_eq!(parse_bound("2026-10-19T09:00:00+09:00").unwrap(), "2026-10-19T00:00:00Z");
+        assert_eq!(parse_bound("2026-10-19T00:00:00Z").unwrap(), "2026-10-19T00:00:00Z");
+        assert!(parse_bound("2026/10/19").is_err());
+        assert!(parse_bound("2026-10-19 09:00:00").is_err());
+    }
+}
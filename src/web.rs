@@ -1,19 +1,31 @@
+use anyhow::{bail, Context};
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Json,
+    extract::{rejection::JsonRejection, Query, State},
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Json, Redirect, Response},
     routing::{get, post},
     Router,
 };
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
+use rust_embed::RustEmbed;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use tower_http::services::ServeDir;
+use tracing::{error, info, warn};
 use crate::scheduler;
 
-use crate::db::{init_db, save_price_history, save_product};
-use crate::scraper::fetch_amazon_price;
-use tracing::info;
+use crate::db::{self, init_db, load_price_history, save_price_history, save_product};
+use crate::alerts::{self, HistoryStats, RuleKind};
+use crate::notify::{self, ChannelKind};
+use crate::runs::{self, Trigger};
+use crate::search::{self, SearchHit};
+use crate::tags::{self, TagCount};
+use crate::edit::{self, EditError, EditErrorKind, ProductEdit};
+use crate::timestamp;
+use crate::scraper::{self, fetch_amazon_price};
 
 // 共有DB接続（スレッドセーフ）
 pub type SharedDb = Arc<Mutex<Connection>>;
@@ -23,14 +35,122 @@ pub type SharedDb = Arc<Mutex<Connection>>;
 struct Product {
     id: i64,
     url: String,
+    /// 表示する名前（表示名を設定していればそちら）
     name: String,
+    /// 表示名（null なら商品ページの名前）
+    display_name: Option<String>,
     current_price: i32,
+    in_stock: bool,
+    /// 商品ごとのチェック間隔（分）。null なら価格の変化の頻度から自動で決める
+    check_interval_minutes: Option<i64>,
+    /// 次にチェックする日時（定期実行）
+    next_check_at: Option<String>,
+    /// 連続で失敗した回数
+    consecutive_failures: i64,
+    /// 失敗が続いているか商品ページがなくなったため、間隔を空けてチェックしている
+    needs_attention: bool,
+    /// 最後の試行のエラー（成功していれば null）
+    last_error: Option<String>,
+    /// 出品者（「Amazon.co.jp」など）
+    seller: Option<String>,
+    /// 最後に価格が変わった日時（変わったことがなければ null）
+    last_changed_at: Option<String>,
+    /// 最後の価格の変化の変化率（%）
+    change_percent: Option<f64>,
+    category: Option<String>,
+    /// タグ（名前順）
+    tags: Vec<String>,
+    note: Option<String>,
+    target_price: Option<i32>,
+    /// false なら価格チェックを止めている
+    enabled: bool,
+}
+
+// 価格履歴は変化があったときだけ行を追加するので、最新の行が最後の変化、その前の行が変化前の価格。
+// どちらも商品ごとに idx_price_history_checked_at で引く（履歴全体は走査しない）
+const PRODUCT_SELECT: &str = "
+    SELECT p.id, p.url, COALESCE(p.display_name, p.name), p.current_price, p.in_stock,
+           p.check_interval_minutes, p.next_check_at, p.consecutive_failures, p.needs_attention_at IS NOT NULL,
+           (SELECT a.error_message FROM check_attempts a WHERE a.product_id = p.id
+            ORDER BY a.attempted_at DESC, a.id DESC LIMIT 1),
+           p.seller,
+           CASE WHEN prev.id IS NOT NULL THEN c.checked_at END AS last_changed_at,
+           (c.price - prev.price) * 100.0 / NULLIF(prev.price, 0) AS change_percent,
+           p.category, p.note, {tags}, p.display_name, p.target_price, p.disabled_at IS NULL
+    FROM products p
+    LEFT JOIN price_history c ON c.id = (
+        SELECT h.id FROM price_history h WHERE h.product_id = p.id
+        ORDER BY h.checked_at DESC, h.id DESC LIMIT 1)
+    LEFT JOIN price_history prev ON prev.id = (
+        SELECT h.id FROM price_history h
+        WHERE h.product_id = p.id AND (h.checked_at, h.id) < (c.checked_at, c.id)
+        ORDER BY h.checked_at DESC, h.id DESC LIMIT 1)";
+
+/// `PRODUCT_SELECT` の1行を Product にする
+fn product_from_row(row: &rusqlite::Row) -> rusqlite::Result<Product> {
+    Ok(Product {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        name: row.get(2)?,
+        current_price: row.get(3)?,
+        in_stock: row.get(4)?,
+        check_interval_minutes: row.get(5)?,
+        next_check_at: row.get::<_, Option<String>>(6)?.as_deref().map(timestamp::render),
+        consecutive_failures: row.get(7)?,
+        needs_attention: row.get(8)?,
+        last_error: row.get(9)?,
+        seller: row.get(10)?,
+        last_changed_at: row.get::<_, Option<String>>(11)?.as_deref().map(timestamp::render),
+        change_percent: row.get(12)?,
+        category: row.get(13)?,
+        note: row.get(14)?,
+        tags: tags::split_tags(row.get(15)?),
+        display_name: row.get(16)?,
+        target_price: row.get(17)?,
+        enabled: row.get(18)?,
+    })
+}
+
+fn product_select() -> String {
+    PRODUCT_SELECT.replace("{tags}", tags::TAG_LIST)
+}
+
+/// 商品を1件読み込む
+fn load_product(conn: &Connection, product_id: i64) -> anyhow::Result<Option<Product>> {
+    let product = conn
+        .query_row(
+            &format!("{} WHERE p.id = ?1", product_select()),
+            [product_id],
+            product_from_row,
+        )
+        .optional()?;
+    Ok(product)
+}
+
+/// 商品一覧の1ページ
+#[derive(Serialize)]
+struct ProductPage {
+    products: Vec<Product>,
+    /// 条件に合う商品の数（全ページ）
+    total: i64,
+    page: u32,
+    limit: u32,
+    /// 条件に合う商品の平均価格（全ページ）
+    average_price: Option<f64>,
+    /// 条件に合う商品の最安値（全ページ）
+    lowest_price: Option<i32>,
 }
 
 #[derive(Serialize)]
 struct PriceHistory {
     price: i32,
     checked_at: String,
+    last_seen_at: String,
+    // 日次に集約済みのデータのみ
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_price: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_price: Option<i32>,
 }
 
 #[derive(Deserialize)]
@@ -38,39 +158,331 @@ struct AddProductRequest {
     url: String,
 }
 
+#[derive(Deserialize)]
+struct IntervalRequest {
+    /// null で自動調整に戻す
+    check_interval_minutes: Option<i64>,
+}
+
 // ルーター設定
-pub fn create_router(db: SharedDb) -> Router {
-    Router::new()
+/// Web UI のデフォルトの待ち受けアドレスとポート
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// リバースプロキシの配下で動かすときの URL の接頭辞（例: /prices）
+///
+/// 先頭と末尾の `/` はあってもなくてもよい。空か `/` ならルートで動かす。
+#[derive(Debug, Clone, Default)]
+pub struct BasePath(String);
+
+impl FromStr for BasePath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let trimmed = s.trim().trim_matches('/');
+        if trimmed.is_empty() {
+            return Ok(Self::default());
+        }
+        if trimmed.split('/').any(|segment| {
+            segment.is_empty()
+                || segment == "."
+                || segment == ".."
+                || !segment.chars().all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c))
+        }) {
+            bail!("Invalid base path '{}' (e.g. /prices)", s);
+        }
+        Ok(Self(format!("/{}", trimmed)))
+    }
+}
+
+impl fmt::Display for BasePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/", self.0)
+    }
+}
+
+/// Web UI の起動設定
+pub struct ServerOptions {
+    /// 待ち受けるアドレス（例: 127.0.0.1, 0.0.0.0）
+    pub host: String,
+    pub port: u16,
+    pub base_path: BasePath,
+    /// 画面のファイルを読み込むディレクトリ（None ならバイナリに埋め込んだ static/ を使う）
+    pub static_dir: Option<PathBuf>,
+}
+
+/// バイナリに埋め込んだ画面のファイル（static/）
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct Assets;
+
+// 埋め込んだ画面のファイルを返す（ディレクトリなら index.html）
+async fn embedded_asset(uri: Uri) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    let path = if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else {
+        path.to_string()
+    };
+
+    match Assets::get(&path) {
+        Some(file) => ([(header::CONTENT_TYPE, file.metadata.mimetype().to_string())], file.data).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// API と画面のルーター（`static_dir` を指定するとそのディレクトリから画面のファイルを返す）
+pub fn create_router(db: SharedDb, static_dir: Option<&Path>) -> Router {
+    let router = Router::new()
         .route("/api/products", get(list_products).post(add_product))
         .route("/api/products/check", post(check_prices))
+        .route("/api/products/search", get(search_products))
         .route("/api/products/:id/history", get(get_price_history))
-        .route("/api/products/:id", axum::routing::delete(delete_product))  // 🆕 追加
-        .with_state(db)
-        .nest_service("/", ServeDir::new("static"))
+        .route("/api/products/:id", axum::routing::delete(delete_product).patch(update_product))
+        .route("/api/products/:id/restore", post(restore_product))
+        .route("/api/products/:id/interval", axum::routing::put(set_check_interval))
+        .route("/api/products/:id/attempts", get(get_product_attempts))
+        .route("/api/products/:id/rules", get(list_rules).post(add_rule))
+        .route("/api/tags", get(list_tags))
+        .route("/api/rules/:id", axum::routing::delete(delete_rule))
+        .route("/api/rules/:id/channels", axum::routing::put(set_rule_channels))
+        .route("/api/alerts", get(list_alerts))
+        .route("/api/channels", get(list_channels).post(add_channel))
+        .route("/api/channels/:id", axum::routing::delete(delete_channel))
+        .route("/api/deliveries", get(list_deliveries))
+        .route("/api/runs", get(list_runs))
+        .route("/api/runs/:id", get(get_run))
+        .route("/api/gaps", get(list_gaps))
+        .route("/api/scheduler", get(get_scheduler_status))
+        .route("/api/scheduler/pause", post(pause_scheduler))
+        .route("/api/scheduler/resume", post(resume_scheduler))
+        .route("/api/scheduler/run", post(run_scheduler))
+        .with_state(db);
+
+    match static_dir {
+        Some(dir) => router.fallback_service(ServeDir::new(dir)),
+        None => router.fallback(embedded_asset),
+    }
+}
+
+/// 商品一覧の1ページのデフォルトの件数と上限
+const DEFAULT_PAGE_LIMIT: u32 = 50;
+const MAX_PAGE_LIMIT: u32 = 200;
+
+/// 商品一覧の並び順
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ProductSort {
+    /// 登録順
+    #[default]
+    Id,
+    Name,
+    Price,
+    /// 最後に価格が変わった日時
+    LastChange,
+    /// 最後の価格の変化の変化率
+    ChangePercent,
+}
+
+impl ProductSort {
+    /// 並べる式と、order を省略したときの向き
+    fn column(self) -> (&'static str, SortOrder) {
+        match self {
+            ProductSort::Id => ("p.id", SortOrder::Desc),
+            ProductSort::Name => ("COALESCE(p.display_name, p.name) COLLATE NOCASE", SortOrder::Asc),
+            ProductSort::Price => ("p.current_price", SortOrder::Asc),
+            ProductSort::LastChange => ("last_changed_at", SortOrder::Desc),
+            ProductSort::ChangePercent => ("change_percent", SortOrder::Asc),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// 在庫・状態での絞り込み
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Availability {
+    InStock,
+    OutOfStock,
+    NeedsAttention,
+    /// 価格チェックを止めている
+    Disabled,
 }
 
-// GET /api/products - 商品一覧取得
-async fn list_products(State(db): State<SharedDb>) -> Result<Json<Vec<Product>>, StatusCode> {
+impl Availability {
+    fn as_str(self) -> &'static str {
+        match self {
+            Availability::InStock => "in_stock",
+            Availability::OutOfStock => "out_of_stock",
+            Availability::NeedsAttention => "needs_attention",
+            Availability::Disabled => "disabled",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    archived: bool,
+    /// ページ番号（1から）
+    page: Option<u32>,
+    /// 1ページの件数（最大200）
+    limit: Option<u32>,
+    #[serde(default)]
+    sort: ProductSort,
+    order: Option<SortOrder>,
+    /// 商品名に含まれる文字列
+    name: Option<String>,
+    /// 出品者（大文字・小文字は区別しない）
+    retailer: Option<String>,
+    min_price: Option<i32>,
+    max_price: Option<i32>,
+    availability: Option<Availability>,
+    /// タグ（大文字・小文字は区別しない）
+    tag: Option<String>,
+    /// カテゴリー（大文字・小文字は区別しない）
+    category: Option<String>,
+}
+
+/// 商品一覧の絞り込み条件（?1〜?8 は ListQuery の値。未指定なら NULL で条件なし）
+fn product_filter() -> String {
+    format!(
+        "WHERE (p.archived_at IS NOT NULL) = ?1
+      AND (?2 IS NULL OR COALESCE(p.display_name, p.name) LIKE '%' || ?2 || '%' ESCAPE '\\')
+      AND (?3 IS NULL OR p.seller = ?3 COLLATE NOCASE)
+      AND (?4 IS NULL OR p.current_price >= ?4)
+      AND (?5 IS NULL OR p.current_price <= ?5)
+      AND (?6 IS NULL
+           OR (?6 = 'in_stock' AND p.in_stock = 1)
+           OR (?6 = 'out_of_stock' AND p.in_stock = 0)
+           OR (?6 = 'needs_attention' AND p.needs_attention_at IS NOT NULL)
+           OR (?6 = 'disabled' AND p.disabled_at IS NOT NULL))
+      AND (?7 IS NULL OR {})
+      AND (?8 IS NULL OR p.category = ?8 COLLATE NOCASE)",
+        tags::has_tag(7)
+    )
+}
+
+/// LIKE の % と _ を文字として扱う
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+// GET /api/products - 商品一覧取得（ページ分割・並び替え・絞り込み。archived=true でアーカイブ済み）
+async fn list_products(
+    State(db): State<SharedDb>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<ProductPage>, StatusCode> {
     let conn = db.lock().unwrap();
-    
-    let mut stmt = conn
-        .prepare("SELECT id, url, name, current_price FROM products ORDER BY id DESC")
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let page = load_product_page(&conn, &query).map_err(|e| {
+        error!("❌ 商品一覧の取得エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
-    let products: Vec<Product> = stmt
-        .query_map([], |row| {
-            Ok(Product {
-                id: row.get(0)?,
-                url: row.get(1)?,
-                name: row.get(2)?,
-                current_price: row.get(3)?,
-            })
-        })
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .filter_map(Result::ok)
-        .collect();
+    Ok(Json(page))
+}
+
+fn load_product_page(conn: &Connection, query: &ListQuery) -> anyhow::Result<ProductPage> {
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let name = query
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(escape_like);
+    let non_empty = |s: &Option<String>| s.as_deref().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string);
+    let retailer = non_empty(&query.retailer);
+    let tag = non_empty(&query.tag);
+    let category = non_empty(&query.category);
+    let availability = query.availability.map(Availability::as_str);
+    let filter = product_filter();
+
+    let (total, average_price, lowest_price) = conn.query_row(
+        &format!(
+            "SELECT COUNT(*), AVG(p.current_price), MIN(p.current_price) FROM products p {}",
+            filter
+        ),
+        rusqlite::params![
+            query.archived,
+            name,
+            retailer,
+            query.min_price,
+            query.max_price,
+            availability,
+            tag,
+            category,
+        ],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+
+    let (sort_column, default_order) = query.sort.column();
+    let order = match query.order.unwrap_or(default_order) {
+        SortOrder::Asc => "ASC",
+        SortOrder::Desc => "DESC",
+    };
+    let sql = format!(
+        "{} {} ORDER BY {} IS NULL, {} {}, p.id DESC LIMIT ?9 OFFSET ?10",
+        product_select(), filter, sort_column, sort_column, order
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let offset = (page as i64 - 1) * limit as i64;
+    let products = stmt
+        .query_map(
+            rusqlite::params![
+                query.archived,
+                name,
+                retailer,
+                query.min_price,
+                query.max_price,
+                availability,
+                tag,
+                category,
+                limit,
+                offset,
+            ],
+            product_from_row,
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(ProductPage {
+        products,
+        total,
+        page,
+        limit,
+        average_price,
+        lowest_price,
+    })
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    #[serde(default)]
+    archived: bool,
+    limit: Option<usize>,
+}
+
+// GET /api/products/search?q= - 商品名の全文検索（よく一致する順。archived=true でアーカイブ済みも含める）
+async fn search_products(
+    State(db): State<SharedDb>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchHit>>, StatusCode> {
+    let conn = db.lock().unwrap();
+    let limit = query.limit.unwrap_or(search::DEFAULT_LIMIT);
+    let hits = search::search_products(&conn, &query.q, query.archived, limit).map_err(|e| {
+        error!("❌ 商品の検索エラー: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
-    Ok(Json(products))
+    Ok(Json(hits))
 }
 
 // POST /api/products - 商品追加
@@ -78,141 +490,928 @@ async fn add_product(
     State(db): State<SharedDb>,
     Json(req): Json<AddProductRequest>,
 ) -> Result<Json<Product>, StatusCode> {
-    let url = req.url.clone();
+    // edit で変えるURLと同じ形にそろえる（同じ商品を別のURLで二重に登録しないように）
+    let url = edit::canonical_url(&req.url).map_err(|_| StatusCode::BAD_REQUEST)?;
     
     // スクレイピング実行
     let product = fetch_amazon_price(&url)
         .await
         .map_err(|_| StatusCode::BAD_REQUEST)?;
+    // 在庫切れで価格が表示されていない商品は追加できない
+    let price = product.price.ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
 
     // DB保存
     let conn = db.lock().unwrap();
     let product_id = save_product(&conn, &product)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    save_price_history(&conn, product_id, product.price)
+    // アーカイブ済みの商品を追加し直した場合は復元する
+    db::restore_product(&conn, product_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    save_price_history(&conn, product_id, price)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // 追加し直した商品は、以前のチェック間隔・タグなどを引き継ぐ
+    scheduler::reschedule(&conn, product_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let product = load_product(&conn, product_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(Product {
-        id: product_id,
-        url: product.url.clone(),
-        name: product.name.clone(),
-        current_price: product.price,
-    }))
+    Ok(Json(product))
+}
+
+#[derive(Deserialize)]
+struct CheckQuery {
+    /// このタグのついた商品だけチェックする
+    tag: Option<String>,
 }
 
-// POST /api/products/check - 全商品の価格チェック
-async fn check_prices(State(db): State<SharedDb>) -> Result<Json<Vec<Product>>, StatusCode> {
+// POST /api/products/check?tag= - 全商品の価格チェック（定期実行などほかのチェックが実行中なら 409）
+async fn check_prices(
+    State(db): State<SharedDb>,
+    Query(query): Query<CheckQuery>,
+) -> Result<Json<Vec<Product>>, StatusCode> {
+    let _guard = scheduler::try_begin_run(&db).ok_or(StatusCode::CONFLICT)?;
+
     // 先にDB接続を取得してデータを全部読み込む
-    let products: Vec<(i64, String, String, i32)> = {
+    let products: Vec<(i64, String)> = {
         let conn = db.lock().unwrap();
         let mut stmt = conn
-            .prepare("SELECT id, url, name, current_price FROM products")
+            .prepare(&format!(
+                "SELECT p.id, p.url FROM products p
+                 WHERE p.archived_at IS NULL AND p.disabled_at IS NULL AND (?1 IS NULL OR {})",
+                tags::has_tag(1)
+            ))
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+        stmt.query_map([&query.tag], |row| Ok((row.get(0)?, row.get(1)?)))
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
             .filter_map(Result::ok)
             .collect()
     };
 
-    // futures::future::join_allで並列実行
-    use futures::future::join_all;
+    let (run_id, channels) = {
+        let conn = db.lock().unwrap();
+        let ids: Vec<i64> = products.iter().map(|(id, ..)| *id).collect();
+        let run_id = runs::start_run(&conn, Trigger::Web, &ids)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let channels = notify::list_channels(&conn)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        (run_id, Arc::new(channels))
+    };
+
+    // 並列実行（同時に取得する商品数はレート制限の設定まで）
+    use futures::stream::{self, StreamExt};
 
     let tasks: Vec<_> = products
         .into_iter()
-        .map(|(product_id, url, _name, _old_price)| {
+        .map(|(product_id, url)| {
             let db = db.clone();
+            let channels = channels.clone();
             async move {
-                if let Ok(product) = fetch_amazon_price(&url).await {
+                let (fetched, latency) = scraper::fetch_throttled(&url).await;
+
+                // ロックを保持したまま await しないようにブロックで区切る
+                let (outcome, triggered) = {
+                    let conn = db.lock().unwrap();
+                    // アラート評価用に、今回の価格を保存する前の統計を取っておく
+                    let stats = HistoryStats::load(&conn, product_id);
+                    let outcome = match fetched {
+                        Ok(product) => save_product(&conn, &product)
+                            .and_then(|_| match product.price {
+                                Some(price) => save_price_history(&conn, product_id, price),
+                                None => Ok(()),
+                            })
+                            .map(|_| product),
+                        Err(e) => Err(e),
+                    };
+                    let price = outcome.as_ref().map(|p| p.price);
+                    if let Err(e) = &outcome {
+                        error!("❌ 価格チェックエラー (ID:{}): {:#}", product_id, e);
+                    }
+                    match runs::record_attempt(&conn, run_id, product_id, price, latency) {
+                        Ok(true) => warn!("⚠️  要確認にしました（チェックの間隔を空けます）: ID:{}", product_id),
+                        Ok(false) => {}
+                        Err(e) => error!("❌ 実行記録の保存エラー (ID:{}): {}", product_id, e),
+                    }
+                    if let Err(e) = scheduler::reschedule(&conn, product_id) {
+                        error!("❌ 次のチェック日時の保存エラー (ID:{}): {}", product_id, e);
+                    }
+                    // 在庫切れの間はアラートを評価しない
+                    let in_stock_price = outcome.as_ref().ok().filter(|p| p.in_stock).and_then(|p| p.price);
+                    let triggered = match in_stock_price {
+                        Some(price) => stats
+                            .and_then(|stats| alerts::evaluate(&conn, product_id, &stats, price))
+                            .unwrap_or_else(|e| {
+                                error!("❌ アラート評価エラー (ID:{}): {}", product_id, e);
+                                Vec::new()
+                            }),
+                        None => Vec::new(),
+                    };
+                    (outcome, triggered)
+                };
+
+                for alert in &triggered {
+                    if alert.queued {
+                        info!("🔕 アラート（静かな時間帯のため後で通知）: {} - {}", alert.product_name, alert.message);
+                    } else {
+                        info!("🔔 アラート: {} - {}", alert.product_name, alert.message);
+                    }
+                }
+                let deliveries = notify::deliver(&channels, &triggered).await;
+                if !deliveries.is_empty() {
                     let conn = db.lock().unwrap();
-                    let _ = save_product(&conn, &product);
-                    let _ = save_price_history(&conn, product_id, product.price);
-                    drop(conn);
-
-                    Some(Product {
-                        id: product_id,
-                        url: product.url.clone(),
-                        name: product.name.clone(),
-                        current_price: product.price,
-                    })
-                } else {
-                    None
+                    if let Err(e) = notify::record_deliveries(&conn, &deliveries) {
+                        error!("❌ 配信ログの保存エラー (ID:{}): {}", product_id, e);
+                    }
                 }
+
+                outcome.ok().map(|_| product_id)
             }
         })
         .collect();
 
-    let results = join_all(tasks).await;
-    let updated_products: Vec<Product> = results.into_iter().filter_map(|x| x).collect();
+    let results: Vec<_> = stream::iter(tasks)
+        .buffered(scraper::rate_limit().max_concurrent)
+        .collect()
+        .await;
+
+    // 取得できた商品を、保存後の状態で返す
+    let updated_products = {
+        let conn = db.lock().unwrap();
+        runs::finish_run(&conn, run_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        results
+            .into_iter()
+            .flatten()
+            .map(|product_id| load_product(&conn, product_id))
+            .filter_map(Result::transpose)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    };
 
     Ok(Json(updated_products))
 }
 
+#[derive(Deserialize)]
+struct HistoryQuery {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+// GET /api/products/:id/history?from=&to= - 価格履歴取得
 async fn get_price_history(
     State(db): State<SharedDb>,
     axum::extract::Path(product_id): axum::extract::Path<i64>,
+    Query(query): Query<HistoryQuery>,
 ) -> Result<Json<Vec<PriceHistory>>, StatusCode> {
+    let from = query
+        .from
+        .as_deref()
+        .map(timestamp::parse_bound)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let to = query
+        .to
+        .as_deref()
+        .map(timestamp::parse_bound)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
     let conn = db.lock().unwrap();
 
-    let mut stmt = conn
-        .prepare(
-            "SELECT price, checked_at FROM price_history 
-             WHERE product_id = ? 
-             ORDER BY checked_at ASC"
-        )
+    let history: Vec<PriceHistory> =
+        load_price_history(&conn, product_id, from.as_deref(), to.as_deref())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .into_iter()
+            .map(|h| PriceHistory {
+                price: h.price,
+                // 設定したタイムゾーンのオフセット付きで返す
+                checked_at: timestamp::render(&h.checked_at),
+                last_seen_at: timestamp::render(&h.last_seen_at),
+                min_price: h.min_price,
+                max_price: h.max_price,
+            })
+            .collect();
+
+    Ok(Json(history))
+}
+
+#[derive(Deserialize)]
+struct DeleteQuery {
+    #[serde(default)]
+    hard: bool,
+}
+
+// DELETE /api/products/:id?hard= - 商品をアーカイブ（hard=true で履歴ごと完全に削除）
+async fn delete_product(
+    State(db): State<SharedDb>,
+    axum::extract::Path(product_id): axum::extract::Path<i64>,
+    Query(query): Query<DeleteQuery>,
+) -> Result<StatusCode, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let found = if query.hard {
+        db::delete_product(&conn, product_id)
+    } else {
+        db::archive_product(&conn, product_id)
+    }
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !found {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /api/products/:id/restore - アーカイブした商品を元に戻す
+async fn restore_product(
+    State(db): State<SharedDb>,
+    axum::extract::Path(product_id): axum::extract::Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let found = db::restore_product(&conn, product_id)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let history: Vec<PriceHistory> = stmt
-        .query_map([product_id], |row| {
-            Ok(PriceHistory {
-                price: row.get(0)?,
-                checked_at: row.get(1)?,
-            })
+    if !found {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// PUT /api/products/:id/interval - 商品ごとのチェック間隔を設定
+async fn set_check_interval(
+    State(db): State<SharedDb>,
+    axum::extract::Path(product_id): axum::extract::Path<i64>,
+    Json(req): Json<IntervalRequest>,
+) -> Result<StatusCode, StatusCode> {
+    if req
+        .check_interval_minutes
+        .is_some_and(|m| m <= 0 || m > scheduler::MAX_CHECK_INTERVAL_MINUTES)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let conn = db.lock().unwrap();
+
+    let found = db::set_check_interval(&conn, product_id, req.check_interval_minutes)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !found {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    scheduler::reschedule(&conn, product_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// API のエラー（`{"error": "...", "field": "..."}`）
+struct ApiError {
+    status: StatusCode,
+    field: Option<&'static str>,
+    message: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.message, "field": self.field });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<EditError> for ApiError {
+    fn from(e: EditError) -> Self {
+        let status = match e.kind {
+            EditErrorKind::Invalid => StatusCode::BAD_REQUEST,
+            EditErrorKind::NotFound => StatusCode::NOT_FOUND,
+            EditErrorKind::Conflict => StatusCode::CONFLICT,
+            EditErrorKind::Unreachable => StatusCode::UNPROCESSABLE_ENTITY,
+            EditErrorKind::Internal => {
+                error!("❌ 商品の変更エラー: {}", e.message);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        Self {
+            status,
+            field: e.field,
+            message: e.message,
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            field: None,
+            message: rejection.body_text(),
+        }
+    }
+}
+
+// PATCH /api/products/:id - 商品の表示名・URL・目標価格・タグ・チェック間隔・チェックの停止・カテゴリー・メモを変更
+async fn update_product(
+    State(db): State<SharedDb>,
+    axum::extract::Path(product_id): axum::extract::Path<i64>,
+    body: Result<Json<ProductEdit>, JsonRejection>,
+) -> Result<Json<Product>, ApiError> {
+    let Json(edit) = body?;
+    let edit = edit.validate()?;
+
+    // URLを変える場合は、ロックを保持したまま await しないように、確認と取得を分ける
+    let new_url = match edit.url() {
+        Some(url) => {
+            let conn = db.lock().unwrap();
+            edit::check_url(&conn, product_id, url)?
+        }
+        None => None,
+    };
+    let page = match new_url {
+        Some(url) => Some(edit::fetch_url(&url).await?),
+        None => None,
+    };
+
+    let conn = db.lock().unwrap();
+    edit::apply(&conn, product_id, &edit, page.as_ref())?;
+    let product = load_product(&conn, product_id)
+        .map_err(EditError::from)?
+        .ok_or_else(|| ApiError {
+            status: StatusCode::NOT_FOUND,
+            field: None,
+            message: format!("Product not found: {}", product_id),
+        })?;
+
+    Ok(Json(product))
+}
+
+// GET /api/tags - 使われているタグと商品数
+async fn list_tags(State(db): State<SharedDb>) -> Result<Json<Vec<TagCount>>, StatusCode> {
+    let conn = db.lock().unwrap();
+    let tags = tags::list_tags(&conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(tags))
+}
+
+#[derive(Deserialize)]
+struct LimitQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct RunDetail {
+    #[serde(flatten)]
+    run: runs::CheckRun,
+    attempts: Vec<runs::CheckAttempt>,
+}
+
+fn render_run(mut run: runs::CheckRun) -> runs::CheckRun {
+    run.started_at = timestamp::render(&run.started_at);
+    run.finished_at = run.finished_at.as_deref().map(timestamp::render);
+    run.missed_since = run.missed_since.as_deref().map(timestamp::render);
+    run
+}
+
+fn render_attempt(mut attempt: runs::CheckAttempt) -> runs::CheckAttempt {
+    attempt.attempted_at = timestamp::render(&attempt.attempted_at);
+    attempt
+}
+
+// GET /api/runs?limit= - 価格チェックの実行記録
+async fn list_runs(
+    State(db): State<SharedDb>,
+    Query(query): Query<LimitQuery>,
+) -> Result<Json<Vec<runs::CheckRun>>, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let runs = runs::list_runs(&conn, query.limit.unwrap_or(20))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(render_run)
+        .collect();
+
+    Ok(Json(runs))
+}
+
+// GET /api/runs/:id - 実行記録と商品ごとの結果
+async fn get_run(
+    State(db): State<SharedDb>,
+    axum::extract::Path(run_id): axum::extract::Path<i64>,
+) -> Result<Json<RunDetail>, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let run = runs::get_run(&conn, run_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let attempts = runs::list_attempts(&conn, Some(run_id), None, run.total.max(0) as usize)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(render_attempt)
+        .collect();
+
+    Ok(Json(RunDetail {
+        run: render_run(run),
+        attempts,
+    }))
+}
+
+// GET /api/gaps?from=&to= - 停止・スリープで価格チェックが行われなかった期間（グラフで線をつながないように使う）
+async fn list_gaps(
+    State(db): State<SharedDb>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<runs::Gap>>, StatusCode> {
+    let from = query
+        .from
+        .as_deref()
+        .map(timestamp::parse_bound)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let to = query
+        .to
+        .as_deref()
+        .map(timestamp::parse_bound)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let conn = db.lock().unwrap();
+
+    let gaps = runs::list_gaps(&conn, from.as_deref(), to.as_deref())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|gap| runs::Gap {
+            from: timestamp::render(&gap.from),
+            to: timestamp::render(&gap.to),
         })
+        .collect();
+
+    Ok(Json(gaps))
+}
+
+// GET /api/scheduler - スケジューラーの状態
+async fn get_scheduler_status(
+    State(db): State<SharedDb>,
+) -> Result<Json<scheduler::SchedulerStatus>, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let mut status = scheduler::status(&conn)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    status.paused_at = status.paused_at.as_deref().map(timestamp::render);
+    status.run_requested_at = status.run_requested_at.as_deref().map(timestamp::render);
+    status.next_check_at = status.next_check_at.as_deref().map(timestamp::render);
+    status.running = status.running.map(render_run);
+
+    Ok(Json(status))
+}
+
+// POST /api/scheduler/pause - 定期価格チェックを一時停止
+async fn pause_scheduler(State(db): State<SharedDb>) -> Result<StatusCode, StatusCode> {
+    let conn = db.lock().unwrap();
+    scheduler::pause(&conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /api/scheduler/resume - 定期価格チェックを再開
+async fn resume_scheduler(State(db): State<SharedDb>) -> Result<StatusCode, StatusCode> {
+    let conn = db.lock().unwrap();
+    scheduler::resume(&conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// POST /api/scheduler/run - 全商品の価格チェックを今すぐ開始（ほかのチェックが実行中なら 409）
+async fn run_scheduler(State(db): State<SharedDb>) -> StatusCode {
+    if scheduler::spawn_run_now(db) {
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::CONFLICT
+    }
+}
+
+// GET /api/products/:id/attempts?limit= - 商品ごとの試行履歴（失敗を含む）
+async fn get_product_attempts(
+    State(db): State<SharedDb>,
+    axum::extract::Path(product_id): axum::extract::Path<i64>,
+    Query(query): Query<LimitQuery>,
+) -> Result<Json<Vec<runs::CheckAttempt>>, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let attempts = runs::list_attempts(&conn, None, Some(product_id), query.limit.unwrap_or(50))
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .filter_map(Result::ok)
+        .into_iter()
+        .map(render_attempt)
         .collect();
 
-    Ok(Json(history))
+    Ok(Json(attempts))
 }
 
-async fn delete_product(
+#[derive(Deserialize)]
+struct AddRuleRequest {
+    kind: RuleKind,
+    threshold: Option<f64>,
+    /// 条件式（kind が expression のとき）
+    expression: Option<String>,
+    /// 通知先ID（省略すると全ての通知先）
+    #[serde(default)]
+    channels: Vec<i64>,
+    cooldown_minutes: Option<i64>,
+    /// 省略すると true（条件を外れるまで1回だけ発火）
+    rearm: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct RuleChannelsRequest {
+    channels: Vec<i64>,
+}
+
+// GET /api/products/:id/rules - 商品のアラートルール一覧
+async fn list_rules(
+    State(db): State<SharedDb>,
+    axum::extract::Path(product_id): axum::extract::Path<i64>,
+) -> Result<Json<Vec<alerts::AlertRule>>, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let rules = alerts::list_rules(&conn, Some(product_id))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rules))
+}
+
+// POST /api/products/:id/rules - アラートルール追加
+async fn add_rule(
     State(db): State<SharedDb>,
     axum::extract::Path(product_id): axum::extract::Path<i64>,
+    Json(req): Json<AddRuleRequest>,
+) -> Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    alerts::validate(req.kind, req.threshold, req.expression.as_deref()).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if req.cooldown_minutes.is_some_and(|m| m <= 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let options = alerts::RuleOptions {
+        cooldown_minutes: req.cooldown_minutes,
+        rearm: req.rearm.unwrap_or(true),
+    };
+
+    let conn = db.lock().unwrap();
+
+    // 商品がなければ 404、存在しない通知先は 400
+    if !db::product_exists(&conn, product_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    alerts::ensure_channels_exist(&conn, &req.channels).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let rule_id = alerts::add_rule(
+        &conn,
+        product_id,
+        req.kind,
+        req.threshold,
+        req.expression.as_deref(),
+        options,
+        &req.channels,
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "id": rule_id }))))
+}
+
+// PUT /api/rules/:id/channels - アラートルールの通知先を設定（空なら全ての通知先）
+async fn set_rule_channels(
+    State(db): State<SharedDb>,
+    axum::extract::Path(rule_id): axum::extract::Path<i64>,
+    Json(req): Json<RuleChannelsRequest>,
 ) -> Result<StatusCode, StatusCode> {
     let conn = db.lock().unwrap();
 
-    // 価格履歴を削除
-    conn.execute("DELETE FROM price_history WHERE product_id = ?", [product_id])
+    let found = alerts::set_rule_channels(&conn, rule_id, &req.channels)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    if !found {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// DELETE /api/rules/:id - アラートルール削除
+async fn delete_rule(
+    State(db): State<SharedDb>,
+    axum::extract::Path(rule_id): axum::extract::Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let found = alerts::remove_rule(&conn, rule_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !found {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct AlertsQuery {
+    product_id: Option<i64>,
+    limit: Option<usize>,
+}
+
+// GET /api/alerts?product_id=&limit= - 発火したアラートの履歴
+async fn list_alerts(
+    State(db): State<SharedDb>,
+    Query(query): Query<AlertsQuery>,
+) -> Result<Json<Vec<alerts::AlertEvent>>, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let events = alerts::list_events(&conn, query.product_id, query.limit.unwrap_or(50))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|mut event| {
+            event.triggered_at = timestamp::render(&event.triggered_at);
+            event
+        })
+        .collect();
+
+    Ok(Json(events))
+}
+
+#[derive(Deserialize)]
+struct AddChannelRequest {
+    kind: ChannelKind,
+    target: String,
+    secret: Option<String>,
+    #[serde(default)]
+    digest: bool,
+}
+
+// GET /api/channels - 通知先一覧（秘密鍵は返さない）
+async fn list_channels(State(db): State<SharedDb>) -> Result<Json<Vec<notify::Channel>>, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let channels = notify::list_channels(&conn)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // 商品を削除
-    let deleted = conn.execute("DELETE FROM products WHERE id = ?", [product_id])
+    Ok(Json(channels))
+}
+
+// POST /api/channels - 通知先追加
+async fn add_channel(
+    State(db): State<SharedDb>,
+    Json(req): Json<AddChannelRequest>,
+) -> Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let channel_id = notify::add_channel(&conn, req.kind, &req.target, req.secret.as_deref(), req.digest)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "id": channel_id }))))
+}
+
+// DELETE /api/channels/:id - 通知先削除
+async fn delete_channel(
+    State(db): State<SharedDb>,
+    axum::extract::Path(channel_id): axum::extract::Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let found = notify::remove_channel(&conn, channel_id)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    if deleted == 0 {
+    if !found {
         return Err(StatusCode::NOT_FOUND);
     }
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+// GET /api/deliveries?limit= - 配信ログ
+async fn list_deliveries(
+    State(db): State<SharedDb>,
+    Query(query): Query<LimitQuery>,
+) -> Result<Json<Vec<notify::DeliveryLog>>, StatusCode> {
+    let conn = db.lock().unwrap();
+
+    let logs = notify::list_deliveries(&conn, query.limit.unwrap_or(50))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|mut log| {
+            log.attempted_at = timestamp::render(&log.attempted_at);
+            log
+        })
+        .collect();
+
+    Ok(Json(logs))
+}
+
 // サーバー起動関数
-pub async fn run_server() -> anyhow::Result<()> {
+/// Web UI を起動（`with_scheduler` が false なら定期実行は別プロセスの daemon に任せる）
+pub async fn run_server(options: ServerOptions, with_scheduler: bool) -> anyhow::Result<()> {
+    if let Some(dir) = &options.static_dir
+        && !dir.join("index.html").is_file()
+    {
+        bail!("{} に index.html がありません", dir.display());
+    }
+
     let db = Arc::new(Mutex::new(init_db()?));
-    let app = create_router(db.clone());
+    let router = create_router(db.clone(), options.static_dir.as_deref());
 
-    tokio::spawn(async move {
-        if let Err(e) = scheduler::start_scheduler(db).await {
-            tracing::error!("❌ スケジューラー起動エラー: {}", e);
-        }
-    });
+    // 接頭辞の下に置く（接頭辞だけのアクセスは、相対パスで画面のファイルを読めるように末尾に / を付けて転送）
+    let app = if options.base_path.0.is_empty() {
+        router
+    } else {
+        let index = options.base_path.to_string();
+        Router::new()
+            .route(&options.base_path.0, get(move || async move { Redirect::permanent(&index) }))
+            .nest(&options.base_path.to_string(), router)
+    };
+
+    if with_scheduler {
+        let scheduler_db = db.clone();
+        tokio::spawn(async move {
+            if let Err(e) = scheduler::start_scheduler(scheduler_db).await {
+                tracing::error!("❌ スケジューラー起動エラー: {}", e);
+            }
+        });
+    } else {
+        info!("🕐 スケジューラーは起動しません（定期実行は daemon で動かしてください）");
+    }
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-        .await?;
+    let listener = tokio::net::TcpListener::bind((options.host.as_str(), options.port))
+        .await
+        .with_context(|| format!("{}:{} で待ち受けできません", options.host, options.port))?;
+
+    let addr = listener.local_addr()?;
+    println!("🚀 Server running at http://{}{}", addr, options.base_path);
+    match &options.static_dir {
+        Some(dir) => info!("📁 画面のファイル: {}", dir.display()),
+        None => info!("📁 画面のファイル: バイナリに埋め込み済み"),
+    }
 
-    println!("🚀 Server running at http://127.0.0.1:3000");
+    let (deadline_tx, deadline_rx) = tokio::sync::oneshot::channel();
+    let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+        scheduler::shutdown_signal().await;
+        info!(
+            "🛑 終了します: 新しいリクエストの受付を停止し、実行中の価格チェックを最大{}秒待ちます",
+            scheduler::SHUTDOWN_TIMEOUT.as_secs()
+        );
+        let _ = deadline_tx.send(scheduler::begin_shutdown());
+    });
+
+    // 期限までに終わらないリクエスト（Web からの価格チェックなど）は待たない
+    tokio::select! {
+        result = server => result?,
+        _ = async {
+            match deadline_rx.await {
+                Ok(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                Err(_) => std::future::pending().await,
+            }
+        } => warn!("⏹️  期限までに終わらなかったリクエストを待たずに終了します"),
+    }
 
-    axum::serve(listener, app).await?;
+    scheduler::wait_for_running_check(&db).await?;
+    println!("👋 Server stopped");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 商品を1件登録する
+    fn insert_product(conn: &Connection, name: &str, price: i32) -> i64 {
+        conn.execute(
+            "INSERT INTO products (url, name, current_price, created_at) VALUES (?1, ?2, ?3, '2026-10-01T00:00:00Z')",
+            (format!("https://example.com/{}", name), name, price),
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    /// 一覧のクエリ（`?name=...&sort=...` と同じ項目の JSON）で1ページ読み込み、商品IDを返す
+    fn list(conn: &Connection, query: serde_json::Value) -> (Vec<i64>, i64) {
+        let query: ListQuery = serde_json::from_value(query).unwrap();
+        let page = load_product_page(conn, &query).unwrap();
+        (page.products.iter().map(|p| p.id).collect(), page.total)
+    }
+
+    /// 一覧の並び替え・絞り込みを確かめるための商品
+    ///
+    /// | ID | 名前 | 価格 | 価格の変化 | その他 |
+    /// |----|------|------|------------|--------|
+    /// | 1 | Apple Juice | ¥300 | 10/10 に ¥400 → ¥300（-25%） | Amazon.co.jp, カテゴリー Drink, タグ 飲料 |
+    /// | 2 | Banana | ¥150 | 10/05 に ¥100 → ¥150（+50%） | 在庫切れ |
+    /// | 3 | cherry | ¥800 | なし（履歴1件） | amazon.co.jp |
+    /// | 4 | Durian | ¥2000 | なし（履歴なし） | 要確認 |
+    /// | 5 | 100%_Orange | ¥450 | 10/02 に ¥500 → ¥450（-10%） | チェック停止中 |
+    fn insert_fixture(conn: &Connection) {
+        for (name, price) in [("Apple Juice", 300), ("Banana", 150), ("cherry", 800), ("Durian", 2000), ("100%_Orange", 450)] {
+            insert_product(conn, name, price);
+        }
+        conn.execute_batch(
+            "UPDATE products SET seller = 'Amazon.co.jp', category = 'Drink' WHERE id = 1;
+            UPDATE products SET seller = 'Other', in_stock = 0 WHERE id = 2;
+            UPDATE products SET seller = 'amazon.co.jp' WHERE id = 3;
+            UPDATE products SET needs_attention_at = '2026-10-10T00:00:00Z' WHERE id = 4;
+            UPDATE products SET disabled_at = '2026-10-10T00:00:00Z' WHERE id = 5;
+            INSERT INTO price_history (product_id, price, checked_at, last_seen_at) VALUES
+                (1, 400, '2026-10-01T00:00:00Z', '2026-10-09T00:00:00Z'),
+                (1, 300, '2026-10-10T00:00:00Z', '2026-10-18T00:00:00Z'),
+                (2, 100, '2026-10-01T00:00:00Z', '2026-10-04T00:00:00Z'),
+                (2, 150, '2026-10-05T00:00:00Z', '2026-10-18T00:00:00Z'),
+                (3, 800, '2026-10-01T00:00:00Z', '2026-10-18T00:00:00Z'),
+                (5, 500, '2026-10-01T00:00:00Z', '2026-10-01T12:00:00Z'),
+                (5, 450, '2026-10-02T00:00:00Z', '2026-10-09T00:00:00Z');",
+        )
+        .unwrap();
+        tags::add_tags(conn, 1, &["飲料".to_string()]).unwrap();
+    }
+
+    fn ids(conn: &Connection, query: serde_json::Value) -> Vec<i64> {
+        list(conn, query).0
+    }
+
+    #[test]
+    fn last_change_comes_from_the_latest_two_history_rows() {
+        let conn = db::open_in_memory().unwrap();
+        insert_fixture(&conn);
+        let changes: Vec<_> = (1..=5)
+            .map(|id| {
+                let p = load_product(&conn, id).unwrap().unwrap();
+                (p.last_changed_at.is_some(), p.change_percent)
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [(true, Some(-25.0)), (true, Some(50.0)), (false, None), (false, None), (true, Some(-10.0))]
+        );
+    }
+
+    #[test]
+    fn list_filters() {
+        let conn = db::open_in_memory().unwrap();
+        insert_fixture(&conn);
+
+        assert_eq!(ids(&conn, serde_json::json!({ "name": "an" })), [5, 4, 2]);
+        // % と _ は文字として探す
+        assert_eq!(ids(&conn, serde_json::json!({ "name": "%" })), [5]);
+        assert_eq!(ids(&conn, serde_json::json!({ "name": "_" })), [5]);
+        assert_eq!(ids(&conn, serde_json::json!({ "name": "  " })), [5, 4, 3, 2, 1]);
+        assert_eq!(ids(&conn, serde_json::json!({ "retailer": "AMAZON.CO.JP" })), [3, 1]);
+        assert_eq!(ids(&conn, serde_json::json!({ "min_price": 300, "max_price": 800 })), [5, 3, 1]);
+        assert_eq!(ids(&conn, serde_json::json!({ "availability": "in_stock" })), [5, 4, 3, 1]);
+        assert_eq!(ids(&conn, serde_json::json!({ "availability": "out_of_stock" })), [2]);
+        assert_eq!(ids(&conn, serde_json::json!({ "availability": "needs_attention" })), [4]);
+        assert_eq!(ids(&conn, serde_json::json!({ "availability": "disabled" })), [5]);
+        assert_eq!(ids(&conn, serde_json::json!({ "tag": "飲料" })), [1]);
+        assert_eq!(ids(&conn, serde_json::json!({ "category": "drink" })), [1]);
+        assert_eq!(ids(&conn, serde_json::json!({ "retailer": "amazon.co.jp", "min_price": 500 })), [3]);
+    }
+
+    #[test]
+    fn list_sorts_put_missing_values_last() {
+        let conn = db::open_in_memory().unwrap();
+        insert_fixture(&conn);
+
+        let sorted = |sort: &str, order: Option<&str>| ids(&conn, serde_json::json!({ "sort": sort, "order": order }));
+        assert_eq!(sorted("id", None), [5, 4, 3, 2, 1]);
+        assert_eq!(sorted("id", Some("asc")), [1, 2, 3, 4, 5]);
+        assert_eq!(sorted("name", None), [5, 1, 2, 3, 4]);
+        assert_eq!(sorted("price", None), [2, 1, 5, 3, 4]);
+        assert_eq!(sorted("price", Some("desc")), [4, 3, 5, 1, 2]);
+        // 価格が変わったことのない商品は、どちらの向きでも最後（その中では新しい順）
+        assert_eq!(sorted("last_change", None), [1, 2, 5, 4, 3]);
+        assert_eq!(sorted("last_change", Some("asc")), [5, 2, 1, 4, 3]);
+        assert_eq!(sorted("change_percent", None), [1, 5, 2, 4, 3]);
+        assert_eq!(sorted("change_percent", Some("desc")), [2, 5, 1, 4, 3]);
+    }
+
+    #[test]
+    fn list_pages_and_totals() {
+        let conn = db::open_in_memory().unwrap();
+        insert_fixture(&conn);
+
+        let page = |page: u32, limit: u32| list(&conn, serde_json::json!({ "page": page, "limit": limit }));
+        assert_eq!(page(1, 2), (vec![5, 4], 5));
+        assert_eq!(page(2, 2), (vec![3, 2], 5));
+        assert_eq!(page(3, 2), (vec![1], 5));
+        assert_eq!(page(4, 2), (vec![], 5));
+        // ページと件数は範囲内に丸める
+        assert_eq!(page(0, 0), (vec![5], 5));
+
+        // 件数・平均・最安値は、絞り込んだ全ページについて
+        let query: ListQuery =
+            serde_json::from_value(serde_json::json!({ "retailer": "amazon.co.jp", "limit": 1 })).unwrap();
+        let result = load_product_page(&conn, &query).unwrap();
+        assert_eq!(result.products.len(), 1);
+        assert_eq!((result.total, result.average_price, result.lowest_price), (2, Some(550.0), Some(300)));
+    }
+
+    #[test]
+    fn archived_products_are_listed_only_when_asked() {
+        let conn = db::open_in_memory().unwrap();
+        let kept = insert_product(&conn, "kept", 1000);
+        let archived = insert_product(&conn, "archived", 2000);
+        assert!(db::archive_product(&conn, archived).unwrap());
+
+        assert_eq!(list(&conn, serde_json::json!({})), (vec![kept], 1));
+        assert_eq!(list(&conn, serde_json::json!({ "archived": true })), (vec![archived], 1));
+    }
+}
@@ -30,15 +30,104 @@ function showToast(message, type = "info") {
   }, 3000);
 }
 
+// アーカイブ済み商品を表示中かどうか
+let showArchived = false;
+
+// 表示中のページと1ページの件数
+let currentPage = 1;
+const PAGE_LIMIT = 50;
+
 // 初回読み込み
 loadProducts();
+loadTags();
+
+// タグの絞り込みの選択肢
+async function loadTags() {
+  try {
+    const res = await fetch("api/tags");
+    if (!res.ok) return;
+    const tags = await res.json();
+    const select = document.getElementById("tagFilter");
+    const selected = select.value;
+    select.innerHTML =
+      '<option value="">すべてのタグ</option>' +
+      tags
+        .map(
+          (t) =>
+            `<option value="${escapeAttr(t.name)}">🏷️ ${escapeHtml(t.name)}（${t.products}）</option>`
+        )
+        .join("");
+    select.value = selected;
+  } catch (err) {
+    // タグがなくても一覧は使える
+  }
+}
+
+// 商品カードのタグをクリックしたら、そのタグで絞り込む
+function filterByTag(tag) {
+  const select = document.getElementById("tagFilter");
+  if (![...select.options].some((o) => o.value === tag)) {
+    select.add(new Option(`🏷️ ${tag}`, tag));
+  }
+  select.value = tag;
+  applyFilters();
+}
+
+function toggleArchived() {
+  showArchived = !showArchived;
+  document.getElementById("archiveToggle").textContent = showArchived
+    ? "📦 登録商品"
+    : "🗃️ アーカイブ";
+  currentPage = 1;
+  loadProducts();
+}
+
+// 絞り込み・並び替えを変えたら1ページ目から表示し直す
+function applyFilters() {
+  currentPage = 1;
+  loadProducts();
+}
+
+// 商品名の入力は少し待ってから反映する
+let filterTimer = null;
+function onFilterInput() {
+  clearTimeout(filterTimer);
+  filterTimer = setTimeout(applyFilters, 300);
+}
+
+function changePage(delta) {
+  currentPage = Math.max(1, currentPage + delta);
+  loadProducts();
+}
+
+function productQuery() {
+  const params = new URLSearchParams({
+    archived: showArchived,
+    page: currentPage,
+    limit: PAGE_LIMIT,
+    sort: document.getElementById("sortSelect").value,
+  });
+  const filters = {
+    name: document.getElementById("nameFilter").value.trim(),
+    tag: document.getElementById("tagFilter").value,
+    availability: document.getElementById("availabilityFilter").value,
+    min_price: document.getElementById("minPriceFilter").value,
+    max_price: document.getElementById("maxPriceFilter").value,
+  };
+  for (const [key, value] of Object.entries(filters)) {
+    if (value !== "") params.set(key, value);
+  }
+  return params.toString();
+}
 
 async function loadProducts() {
   try {
-    const res = await fetch("/api/products");
-    const products = await res.json();
-    displayProducts(products);
-    updateStats(products);
+    const res = await fetch(`api/products?${productQuery()}`);
+    if (!res.ok) throw new Error(res.statusText);
+    const page = await res.json();
+    displayProducts(page.products);
+    updateStats(page);
+    updatePager(page);
   } catch (err) {
     document.getElementById("productList").innerHTML =
       '<div class="loading">エラーが発生しました</div>';
@@ -46,20 +135,28 @@ async function loadProducts() {
   }
 }
 
-function updateStats(products) {
-  const total = products.length;
-  const prices = products.map((p) => p.current_price);
-  const avg = prices.length
-    ? Math.round(prices.reduce((a, b) => a + b, 0) / prices.length)
-    : 0;
-  const lowest = prices.length ? Math.min(...prices) : 0;
+// 統計は表示中のページではなく、条件に合う商品全体のもの
+function updateStats(page) {
+  const avg = Math.round(page.average_price ?? 0);
+  const lowest = page.lowest_price ?? 0;
 
-  document.getElementById("totalProducts").textContent = total;
+  document.getElementById("totalProducts").textContent = page.total;
   document.getElementById("avgPrice").textContent = "¥" + avg.toLocaleString();
   document.getElementById("lowestPrice").textContent =
     "¥" + lowest.toLocaleString();
 }
 
+function updatePager(page) {
+  const pages = Math.max(1, Math.ceil(page.total / page.limit));
+  const from = page.total === 0 ? 0 : (page.page - 1) * page.limit + 1;
+  const to = Math.min(page.page * page.limit, page.total);
+  document.getElementById("pager").style.display = pages > 1 ? "" : "none";
+  document.getElementById("pageInfo").textContent =
+    `${from}–${to} / ${page.total}件`;
+  document.getElementById("prevPage").disabled = page.page <= 1;
+  document.getElementById("nextPage").disabled = page.page >= pages;
+}
+
 async function addProduct() {
   const url = document.getElementById("urlInput").value.trim();
   if (!url) {
@@ -71,7 +168,7 @@ async function addProduct() {
     '<div class="loading"><div class="spinner"></div>追加中...</div>';
 
   try {
-    const res = await fetch("/api/products", {
+    const res = await fetch("api/products", {
       method: "POST",
       headers: { "Content-Type": "application/json" },
       body: JSON.stringify({ url }),
@@ -96,10 +193,14 @@ async function checkPrices() {
     '<div class="loading"><div class="spinner"></div>価格チェック中...</div>';
 
   try {
-    const res = await fetch("/api/products/check", { method: "POST" });
-    const products = await res.json();
-    displayProducts(products);
-    updateStats(products);
+    const res = await fetch("api/products/check", { method: "POST" });
+    if (res.status === 409) {
+      loadProducts();
+      showToast("⏳ 別の価格チェックが実行中です", "error");
+      return;
+    }
+    await res.json();
+    loadProducts();
     showToast("✅ 価格チェック完了！", "success");
   } catch (err) {
     showToast("❌ 価格チェックに失敗しました", "error");
@@ -107,11 +208,76 @@ async function checkPrices() {
   }
 }
 
+// 商品を変更する（失敗したらAPIのエラーメッセージを表示）
+async function updateProduct(id, changes) {
+  try {
+    const res = await fetch(`api/products/${id}`, {
+      method: "PATCH",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify(changes),
+    });
+    if (res.ok) {
+      loadProducts();
+      showToast("🔧 商品を変更しました", "success");
+    } else {
+      const body = await res.json().catch(() => ({}));
+      showToast(`❌ 変更できませんでした: ${body.error || res.status}`, "error");
+    }
+  } catch (err) {
+    showToast("❌ エラーが発生しました", "error");
+  }
+}
+
+function editTargetPrice(id, current) {
+  const input = prompt("目標価格（円）を入力してください（空にすると解除）", current ?? "");
+  if (input === null) return;
+  const value = input.replace(/[¥,\s]/g, "");
+  if (value !== "" && !/^\d+$/.test(value)) {
+    showToast("❌ 目標価格は数字で入力してください", "error");
+    return;
+  }
+  updateProduct(id, { target_price: value === "" ? null : Number(value) });
+}
+
+async function archiveProduct(id, name) {
+  if (!confirm(`「${name}」をアーカイブしますか？（価格履歴は残ります）`))
+    return;
+
+  try {
+    const res = await fetch(`api/products/${id}`, { method: "DELETE" });
+    if (res.ok) {
+      loadProducts();
+      showToast("🗃️ 商品をアーカイブしました", "success");
+    } else {
+      showToast("❌ アーカイブに失敗しました", "error");
+    }
+  } catch (err) {
+    showToast("❌ エラーが発生しました", "error");
+  }
+}
+
+async function restoreProduct(id) {
+  try {
+    const res = await fetch(`api/products/${id}/restore`, { method: "POST" });
+    if (res.ok) {
+      loadProducts();
+      showToast("♻️ 商品を復元しました", "success");
+    } else {
+      showToast("❌ 復元に失敗しました", "error");
+    }
+  } catch (err) {
+    showToast("❌ エラーが発生しました", "error");
+  }
+}
+
 async function deleteProduct(id, name) {
-  if (!confirm(`「${name}」を削除しますか？`)) return;
+  if (!confirm(`「${name}」を価格履歴ごと完全に削除しますか？元に戻せません。`))
+    return;
 
   try {
-    const res = await fetch(`/api/products/${id}`, { method: "DELETE" });
+    const res = await fetch(`api/products/${id}?hard=true`, {
+      method: "DELETE",
+    });
     if (res.ok) {
       loadProducts();
       showToast("🗑️ 商品を削除しました", "success");
@@ -126,7 +292,9 @@ async function deleteProduct(id, name) {
 function displayProducts(products) {
   const list = document.getElementById("productList");
   if (products.length === 0) {
-    list.innerHTML = '<div class="loading">📦 商品が登録されていません</div>';
+    list.innerHTML = showArchived
+      ? '<div class="loading">🗃️ アーカイブ済みの商品はありません</div>'
+      : '<div class="loading">📦 商品が登録されていません</div>';
     return;
   }
 
@@ -139,19 +307,35 @@ function displayProducts(products) {
                     <h3>${escapeHtml(p.name)}</h3>
                     <div class="price-container">
                         <div class="price">¥${p.current_price.toLocaleString()}</div>
+                        ${changeBadge(p)}
+                        ${
+                          p.in_stock
+                            ? ""
+                            : '<span class="price-badge out-of-stock">🚫 在庫切れ</span>'
+                        }
+                        ${
+                          p.needs_attention
+                            ? `<span class="price-badge needs-attention" title="${escapeHtml(
+                                p.last_error || ""
+                              )}">⚠️ 要確認（${p.consecutive_failures}回連続で失敗）</span>`
+                            : ""
+                        }
+                        ${targetBadge(p)}
+                        ${
+                          p.enabled
+                            ? ""
+                            : '<span class="price-badge disabled">⏸️ チェック停止中</span>'
+                        }
                     </div>
+                    ${productLabels(p)}
                     <div class="url">${escapeHtml(p.url)}</div>
+                    ${p.note ? `<div class="note">📝 ${escapeHtml(p.note)}</div>` : ""}
                 </div>
                 <div class="product-actions">
                     <button class="btn-graph" onclick="toggleGraph(${
                       p.id
                     })">📈 グラフ</button>
-                    <button class="btn-delete btn-danger" onclick="deleteProduct(${
-                      p.id
-                    }, '${escapeHtml(p.name).replace(
-        /'/g,
-        "\\'"
-      )}')">🗑️ 削除</button>
+                    ${productActions(p)}
                 </div>
             </div>
             <div class="chart-container" id="chart-${p.id}">
@@ -163,12 +347,64 @@ function displayProducts(products) {
     .join("");
 }
 
+// カテゴリーとタグ
+function productLabels(p) {
+  const labels = [];
+  if (p.category) {
+    labels.push(`<span class="label category">📁 ${escapeHtml(p.category)}</span>`);
+  }
+  for (const tag of p.tags) {
+    labels.push(
+      `<button class="label tag" data-tag="${escapeAttr(tag)}" onclick="filterByTag(this.dataset.tag)">🏷️ ${escapeHtml(tag)}</button>`
+    );
+  }
+  return labels.length ? `<div class="labels">${labels.join("")}</div>` : "";
+}
+
+// 前回の価格からの変化
+function changeBadge(p) {
+  if (p.change_percent == null) return "";
+  const percent = Math.abs(p.change_percent).toFixed(1);
+  return p.change_percent < 0
+    ? `<span class="price-badge price-down">▼ ${percent}%</span>`
+    : `<span class="price-badge price-up">▲ ${percent}%</span>`;
+}
+
+// 目標価格（現在の価格が目標以下なら達成）
+function targetBadge(p) {
+  if (p.target_price == null) return "";
+  const target = p.target_price.toLocaleString();
+  return p.current_price <= p.target_price
+    ? `<span class="price-badge target-reached">🎯 目標達成（¥${target}）</span>`
+    : `<span class="price-badge target-price">🎯 目標 ¥${target}</span>`;
+}
+
+function productActions(p) {
+  const name = escapeHtml(p.name).replace(/'/g, "\\'");
+  if (showArchived) {
+    return `
+      <button class="btn-delete btn-secondary" onclick="restoreProduct(${p.id})">♻️ 復元</button>
+      <button class="btn-delete btn-danger" onclick="deleteProduct(${p.id}, '${name}')">🗑️ 完全に削除</button>`;
+  }
+  return `
+    <button class="btn-delete btn-secondary" onclick="editTargetPrice(${p.id}, ${p.target_price ?? "null"})">🎯 目標価格</button>
+    <button class="btn-delete btn-secondary" onclick="updateProduct(${p.id}, { enabled: ${!p.enabled} })">${
+      p.enabled ? "⏸️ 停止" : "▶️ 再開"
+    }</button>
+    <button class="btn-delete btn-danger" onclick="archiveProduct(${p.id}, '${name}')">🗃️ アーカイブ</button>`;
+}
+
 function escapeHtml(text) {
   const div = document.createElement("div");
   div.textContent = text;
   return div.innerHTML;
 }
 
+// 属性値に入れる文字列（escapeHtml は引用符をエスケープしない）
+function escapeAttr(text) {
+  return escapeHtml(text).replace(/"/g, "&quot;");
+}
+
 // グラフ表示
 const chartInstances = {};
 
@@ -187,7 +423,7 @@ async function toggleGraph(productId) {
   chartContainer.classList.add("active");
 
   try {
-    const res = await fetch(`/api/products/${productId}/history`);
+    const res = await fetch(`api/products/${productId}/history`);
     const history = await res.json();
 
     if (history.length === 0) {
@@ -197,10 +433,26 @@ async function toggleGraph(productId) {
     }
 
     // 🔧 日時データを正確なタイムスタンプに変換
-    const data = history.map((h) => ({
-      x: new Date(h.checked_at), // Date オブジェクトに変換
-      y: h.price,
-    }));
+    // 同じ価格が続いた期間は「最初に見た日時」と「最後に見た日時」の2点で描く
+    const data = history.flatMap((h) => {
+      const points = [{ x: new Date(h.checked_at), y: h.price }];
+      if (h.last_seen_at && h.last_seen_at !== h.checked_at) {
+        points.push({ x: new Date(h.last_seen_at), y: h.price });
+      }
+      return points;
+    });
+
+    // 停止・スリープでチェックしていなかった期間は線をつながない（null の点で区切る）
+    const gapsRes = await fetch(
+      `api/gaps?from=${encodeURIComponent(history[0].checked_at)}`
+    );
+    const gaps = gapsRes.ok ? await gapsRes.json() : [];
+    for (const gap of gaps) {
+      const from = new Date(gap.from).getTime();
+      const to = new Date(gap.to).getTime();
+      data.push({ x: new Date((from + to) / 2), y: null });
+    }
+    data.sort((a, b) => a.x - b.x);
 
     const ctx = document.getElementById(`canvas-${productId}`).getContext("2d");
 